@@ -0,0 +1,86 @@
+//! Unit tests of the esp32-nimble modules that don't need the NimBLE host.
+//!
+//! The modules are compiled from `../src` against [`sys`], a stand-in for the ESP-IDF bindings,
+//! so their `#[cfg(test)]` modules run on the build machine.
+//!
+//! The target has to be given explicitly, as the repository's `.cargo/config.toml` selects an ESP32:
+//!
+//! ```sh
+//! cargo test --target x86_64-unknown-linux-gnu
+//! ```
+
+#![no_std]
+#![allow(clippy::new_without_default)]
+#![allow(clippy::single_match)]
+#![allow(dead_code)]
+
+extern crate alloc;
+extern crate std;
+
+// Resolves `esp_idf_svc::sys` in the included modules to the stand-in.
+extern crate self as esp_idf_svc;
+
+pub mod sys;
+
+pub use uuid::uuid as uuid_macro;
+
+// The `ble!` macro is only used by the NimBLE calls.
+#[allow(unused_macros, unused_imports)]
+#[path = "../../src/ble_error.rs"]
+mod ble_error;
+pub use ble_error::BLEError;
+
+#[path = "../../src/enums.rs"]
+pub mod enums;
+
+#[path = "../../src/beacon/mod.rs"]
+pub mod beacon;
+
+#[path = "../../src/client"]
+mod client {
+    mod ble_advertised_data;
+    pub use ble_advertised_data::BLEAdvertisedData;
+
+    mod ble_connection_state;
+    pub use ble_connection_state::*;
+}
+pub use client::*;
+
+#[path = "../../src/server"]
+mod server {
+    mod ble_advertisement_data;
+    pub use ble_advertisement_data::{AdField, AdvDataError, BLEAdvertisementData};
+
+    pub mod cpfd;
+    pub(crate) mod cpfd_constants;
+
+    pub mod descriptors;
+
+    mod att_value;
+}
+pub use server::*;
+
+#[path = "../../src/utilities"]
+pub mod utilities {
+    mod ble_uuid;
+    pub use ble_uuid::BleUuid;
+
+    pub mod fragmentation;
+
+    pub mod mutex;
+
+    mod notify;
+}
+
+/// Stand-in for the device, only asked for the advertising TX power.
+pub struct BLEDevice;
+
+impl BLEDevice {
+    pub fn take() -> &'static Self {
+        &BLEDevice
+    }
+
+    pub fn get_power(&self, _power_type: enums::PowerType) -> enums::PowerLevel {
+        enums::PowerLevel::N0
+    }
+}
@@ -0,0 +1,400 @@
+//! Stand-in for the ESP-IDF bindings used by the modules under test.
+//!
+//! Values match the NimBLE and ESP-IDF headers.
+
+#![allow(non_camel_case_types, non_upper_case_globals)]
+#![allow(clippy::missing_safety_doc)]
+
+use core::{
+    ffi::{c_int, c_uint},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+// host/ble_hs.h
+pub const BLE_HS_EAGAIN: u32 = 1;
+pub const BLE_HS_EALREADY: u32 = 2;
+pub const BLE_HS_EINVAL: u32 = 3;
+pub const BLE_HS_EMSGSIZE: u32 = 4;
+pub const BLE_HS_ENOENT: u32 = 5;
+pub const BLE_HS_ENOMEM: u32 = 6;
+pub const BLE_HS_ENOTCONN: u32 = 7;
+pub const BLE_HS_ENOTSUP: u32 = 8;
+pub const BLE_HS_EAPP: u32 = 9;
+pub const BLE_HS_EBADDATA: u32 = 10;
+pub const BLE_HS_EOS: u32 = 11;
+pub const BLE_HS_ECONTROLLER: u32 = 12;
+pub const BLE_HS_ETIMEOUT: u32 = 13;
+pub const BLE_HS_EDONE: u32 = 14;
+pub const BLE_HS_EBUSY: u32 = 15;
+pub const BLE_HS_EREJECT: u32 = 16;
+pub const BLE_HS_EUNKNOWN: u32 = 17;
+pub const BLE_HS_EROLE: u32 = 18;
+pub const BLE_HS_ETIMEOUT_HCI: u32 = 19;
+pub const BLE_HS_ENOMEM_EVT: u32 = 20;
+pub const BLE_HS_ENOADDR: u32 = 21;
+pub const BLE_HS_ENOTSYNCED: u32 = 22;
+pub const BLE_HS_EAUTHEN: u32 = 23;
+pub const BLE_HS_EAUTHOR: u32 = 24;
+pub const BLE_HS_EENCRYPT: u32 = 25;
+pub const BLE_HS_EENCRYPT_KEY_SZ: u32 = 26;
+pub const BLE_HS_ESTORE_CAP: u32 = 27;
+pub const BLE_HS_ESTORE_FAIL: u32 = 28;
+
+pub const BLE_HS_ERR_ATT_BASE: u32 = 0x100;
+pub const BLE_HS_ERR_HCI_BASE: u32 = 0x200;
+pub const BLE_HS_ERR_L2C_BASE: u32 = 0x300;
+pub const BLE_HS_ERR_SM_US_BASE: u32 = 0x400;
+
+pub const BLE_HS_IO_DISPLAY_ONLY: u32 = 0;
+pub const BLE_HS_IO_DISPLAY_YESNO: u32 = 1;
+pub const BLE_HS_IO_KEYBOARD_ONLY: u32 = 2;
+pub const BLE_HS_IO_NO_INPUT_OUTPUT: u32 = 3;
+pub const BLE_HS_IO_KEYBOARD_DISPLAY: u32 = 4;
+
+// host/ble_att.h
+pub const BLE_ATT_ATTR_MAX_LEN: u32 = 512;
+
+pub const BLE_ATT_ERR_INVALID_HANDLE: u32 = 0x01;
+pub const BLE_ATT_ERR_READ_NOT_PERMITTED: u32 = 0x02;
+pub const BLE_ATT_ERR_WRITE_NOT_PERMITTED: u32 = 0x03;
+pub const BLE_ATT_ERR_INVALID_PDU: u32 = 0x04;
+pub const BLE_ATT_ERR_INSUFFICIENT_AUTHEN: u32 = 0x05;
+pub const BLE_ATT_ERR_REQ_NOT_SUPPORTED: u32 = 0x06;
+pub const BLE_ATT_ERR_INVALID_OFFSET: u32 = 0x07;
+pub const BLE_ATT_ERR_INSUFFICIENT_AUTHOR: u32 = 0x08;
+pub const BLE_ATT_ERR_PREPARE_QUEUE_FULL: u32 = 0x09;
+pub const BLE_ATT_ERR_ATTR_NOT_FOUND: u32 = 0x0a;
+pub const BLE_ATT_ERR_ATTR_NOT_LONG: u32 = 0x0b;
+pub const BLE_ATT_ERR_INSUFFICIENT_KEY_SZ: u32 = 0x0c;
+pub const BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN: u32 = 0x0d;
+pub const BLE_ATT_ERR_UNLIKELY: u32 = 0x0e;
+pub const BLE_ATT_ERR_INSUFFICIENT_ENC: u32 = 0x0f;
+pub const BLE_ATT_ERR_UNSUPPORTED_GROUP: u32 = 0x10;
+pub const BLE_ATT_ERR_INSUFFICIENT_RES: u32 = 0x11;
+
+// nimble/ble.h
+pub type ble_error_codes = c_uint;
+pub const ble_error_codes_BLE_ERR_UNKNOWN_HCI_CMD: ble_error_codes = 0x01;
+pub const ble_error_codes_BLE_ERR_UNK_CONN_ID: ble_error_codes = 0x02;
+pub const ble_error_codes_BLE_ERR_HW_FAIL: ble_error_codes = 0x03;
+pub const ble_error_codes_BLE_ERR_PAGE_TMO: ble_error_codes = 0x04;
+pub const ble_error_codes_BLE_ERR_AUTH_FAIL: ble_error_codes = 0x05;
+pub const ble_error_codes_BLE_ERR_PINKEY_MISSING: ble_error_codes = 0x06;
+pub const ble_error_codes_BLE_ERR_MEM_CAPACITY: ble_error_codes = 0x07;
+pub const ble_error_codes_BLE_ERR_CONN_SPVN_TMO: ble_error_codes = 0x08;
+pub const ble_error_codes_BLE_ERR_CONN_LIMIT: ble_error_codes = 0x09;
+pub const ble_error_codes_BLE_ERR_SYNCH_CONN_LIMIT: ble_error_codes = 0x0a;
+pub const ble_error_codes_BLE_ERR_ACL_CONN_EXISTS: ble_error_codes = 0x0b;
+pub const ble_error_codes_BLE_ERR_CMD_DISALLOWED: ble_error_codes = 0x0c;
+pub const ble_error_codes_BLE_ERR_CONN_REJ_RESOURCES: ble_error_codes = 0x0d;
+pub const ble_error_codes_BLE_ERR_INV_HCI_CMD_PARMS: ble_error_codes = 0x12;
+pub const ble_error_codes_BLE_ERR_REM_USER_CONN_TERM: ble_error_codes = 0x13;
+pub const ble_error_codes_BLE_ERR_CONN_TERM_LOCAL: ble_error_codes = 0x16;
+pub const ble_error_codes_BLE_ERR_CONN_TERM_MIC: ble_error_codes = 0x3d;
+pub const ble_error_codes_BLE_ERR_CONN_ESTABLISHMENT: ble_error_codes = 0x3e;
+
+// host/ble_l2cap.h
+pub const BLE_L2CAP_SIG_ERR_CMD_NOT_UNDERSTOOD: u32 = 0x0000;
+pub const BLE_L2CAP_SIG_ERR_MTU_EXCEEDED: u32 = 0x0001;
+pub const BLE_L2CAP_SIG_ERR_INVALID_CID: u32 = 0x0002;
+
+// host/ble_sm.h
+pub const BLE_SM_ERR_PASSKEY: u32 = 0x01;
+pub const BLE_SM_ERR_OOB: u32 = 0x02;
+pub const BLE_SM_ERR_AUTHREQ: u32 = 0x03;
+pub const BLE_SM_ERR_CONFIRM_MISMATCH: u32 = 0x04;
+pub const BLE_SM_ERR_PAIR_NOT_SUPP: u32 = 0x05;
+pub const BLE_SM_ERR_ENC_KEY_SZ: u32 = 0x06;
+pub const BLE_SM_ERR_CMD_NOT_SUPP: u32 = 0x07;
+pub const BLE_SM_ERR_UNSPECIFIED: u32 = 0x08;
+pub const BLE_SM_ERR_REPEATED: u32 = 0x09;
+pub const BLE_SM_ERR_INVAL: u32 = 0x0a;
+pub const BLE_SM_ERR_DHKEY: u32 = 0x0b;
+pub const BLE_SM_ERR_NUMCMP: u32 = 0x0c;
+pub const BLE_SM_ERR_ALREADY: u32 = 0x0d;
+pub const BLE_SM_ERR_CROSS_TRANS: u32 = 0x0e;
+
+pub const BLE_SM_PAIR_KEY_DIST_ENC: u32 = 0x01;
+pub const BLE_SM_PAIR_KEY_DIST_ID: u32 = 0x02;
+pub const BLE_SM_PAIR_KEY_DIST_SIGN: u32 = 0x04;
+pub const BLE_SM_PAIR_KEY_DIST_LINK: u32 = 0x08;
+
+// host/ble_hs_adv.h
+pub const BLE_HS_ADV_MAX_SZ: u32 = 31;
+pub const BLE_HS_ADV_TX_PWR_LVL_LEN: u32 = 1;
+pub const BLE_HS_ADV_APPEARANCE_LEN: u32 = 2;
+
+pub const BLE_HS_ADV_TYPE_FLAGS: u32 = 0x01;
+pub const BLE_HS_ADV_TYPE_INCOMP_UUIDS16: u32 = 0x02;
+pub const BLE_HS_ADV_TYPE_COMP_UUIDS16: u32 = 0x03;
+pub const BLE_HS_ADV_TYPE_INCOMP_UUIDS32: u32 = 0x04;
+pub const BLE_HS_ADV_TYPE_COMP_UUIDS32: u32 = 0x05;
+pub const BLE_HS_ADV_TYPE_INCOMP_UUIDS128: u32 = 0x06;
+pub const BLE_HS_ADV_TYPE_COMP_UUIDS128: u32 = 0x07;
+pub const BLE_HS_ADV_TYPE_INCOMP_NAME: u32 = 0x08;
+pub const BLE_HS_ADV_TYPE_COMP_NAME: u32 = 0x09;
+pub const BLE_HS_ADV_TYPE_TX_PWR_LVL: u32 = 0x0a;
+pub const BLE_HS_ADV_TYPE_SVC_DATA_UUID16: u32 = 0x16;
+pub const BLE_HS_ADV_TYPE_SVC_DATA_UUID32: u32 = 0x20;
+pub const BLE_HS_ADV_TYPE_SVC_DATA_UUID128: u32 = 0x21;
+pub const BLE_HS_ADV_TYPE_MFG_DATA: u32 = 0xff;
+
+pub const BLE_HS_ADV_F_DISC_LTD: u32 = 0x01;
+pub const BLE_HS_ADV_F_DISC_GEN: u32 = 0x02;
+pub const BLE_HS_ADV_F_BREDR_UNSUP: u32 = 0x04;
+
+#[derive(Copy, Clone)]
+pub struct ble_hs_adv_fields {
+    pub flags: u8,
+    pub uuids16: *const ble_uuid16_t,
+    pub num_uuids16: u8,
+    pub uuids16_is_complete: c_uint,
+    pub uuids32: *const ble_uuid32_t,
+    pub num_uuids32: u8,
+    pub uuids32_is_complete: c_uint,
+    pub uuids128: *const ble_uuid128_t,
+    pub num_uuids128: u8,
+    pub uuids128_is_complete: c_uint,
+    pub name: *const u8,
+    pub name_len: u8,
+    pub name_is_complete: c_uint,
+    pub tx_pwr_lvl: i8,
+    pub tx_pwr_lvl_is_present: c_uint,
+    pub svc_data_uuid16: *const u8,
+    pub svc_data_uuid16_len: u8,
+    pub appearance: u16,
+    pub appearance_is_present: c_uint,
+    pub svc_data_uuid32: *const u8,
+    pub svc_data_uuid32_len: u8,
+    pub svc_data_uuid128: *const u8,
+    pub svc_data_uuid128_len: u8,
+    pub mfg_data: *const u8,
+    pub mfg_data_len: u8,
+}
+
+impl Default for ble_hs_adv_fields {
+    fn default() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+impl ble_hs_adv_fields {
+    pub fn set_uuids16_is_complete(&mut self, val: c_uint) {
+        self.uuids16_is_complete = val;
+    }
+
+    pub fn set_uuids32_is_complete(&mut self, val: c_uint) {
+        self.uuids32_is_complete = val;
+    }
+
+    pub fn set_uuids128_is_complete(&mut self, val: c_uint) {
+        self.uuids128_is_complete = val;
+    }
+
+    pub fn set_name_is_complete(&mut self, val: c_uint) {
+        self.name_is_complete = val;
+    }
+
+    pub fn set_tx_pwr_lvl_is_present(&mut self, val: c_uint) {
+        self.tx_pwr_lvl_is_present = val;
+    }
+
+    pub fn set_appearance_is_present(&mut self, val: c_uint) {
+        self.appearance_is_present = val;
+    }
+}
+
+// host/ble_uuid.h
+pub const BLE_UUID_TYPE_16: u32 = 16;
+pub const BLE_UUID_TYPE_32: u32 = 32;
+pub const BLE_UUID_TYPE_128: u32 = 128;
+
+#[derive(Copy, Clone, Default)]
+pub struct ble_uuid_t {
+    pub type_: u8,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct ble_uuid16_t {
+    pub u: ble_uuid_t,
+    pub value: u16,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct ble_uuid32_t {
+    pub u: ble_uuid_t,
+    pub value: u32,
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct ble_uuid128_t {
+    pub u: ble_uuid_t,
+    pub value: [u8; 16],
+}
+
+#[derive(Copy, Clone)]
+pub union ble_uuid_any_t {
+    pub u: ble_uuid_t,
+    pub u16_: ble_uuid16_t,
+    pub u32_: ble_uuid32_t,
+    pub u128_: ble_uuid128_t,
+}
+
+impl Default for ble_uuid_any_t {
+    fn default() -> Self {
+        unsafe { core::mem::zeroed() }
+    }
+}
+
+// host/ble_gap.h, host/ble_hs.h, nimble/hci_common.h
+pub const BLE_OWN_ADDR_PUBLIC: u32 = 0;
+pub const BLE_OWN_ADDR_RANDOM: u32 = 1;
+pub const BLE_OWN_ADDR_RPA_PUBLIC_DEFAULT: u32 = 2;
+pub const BLE_OWN_ADDR_RPA_RANDOM_DEFAULT: u32 = 3;
+
+pub const BLE_GAP_CONN_MODE_NON: u32 = 0;
+pub const BLE_GAP_CONN_MODE_DIR: u32 = 1;
+pub const BLE_GAP_CONN_MODE_UND: u32 = 2;
+
+pub const BLE_GAP_DISC_MODE_NON: u32 = 0;
+pub const BLE_GAP_DISC_MODE_LTD: u32 = 1;
+pub const BLE_GAP_DISC_MODE_GEN: u32 = 2;
+
+pub const BLE_GAP_LE_PHY_1M_MASK: u32 = 0x01;
+pub const BLE_GAP_LE_PHY_2M_MASK: u32 = 0x02;
+pub const BLE_GAP_LE_PHY_CODED_MASK: u32 = 0x04;
+
+pub const BLE_GAP_LE_PHY_CODED_ANY: u32 = 0;
+pub const BLE_GAP_LE_PHY_CODED_S2: u32 = 1;
+pub const BLE_GAP_LE_PHY_CODED_S8: u32 = 2;
+
+pub const BLE_GAP_EXT_ADV_DATA_STATUS_COMPLETE: u32 = 0;
+pub const BLE_GAP_EXT_ADV_DATA_STATUS_INCOMPLETE: u32 = 1;
+pub const BLE_GAP_EXT_ADV_DATA_STATUS_TRUNCATED: u32 = 2;
+
+pub const BLE_HCI_ADV_RPT_EVTYPE_ADV_IND: u32 = 0;
+pub const BLE_HCI_ADV_RPT_EVTYPE_DIR_IND: u32 = 1;
+pub const BLE_HCI_ADV_RPT_EVTYPE_SCAN_IND: u32 = 2;
+pub const BLE_HCI_ADV_RPT_EVTYPE_NONCONN_IND: u32 = 3;
+pub const BLE_HCI_ADV_RPT_EVTYPE_SCAN_RSP: u32 = 4;
+
+pub const BLE_HCI_SCAN_FILT_NO_WL: u32 = 0;
+pub const BLE_HCI_SCAN_FILT_USE_WL: u32 = 1;
+pub const BLE_HCI_SCAN_FILT_NO_WL_INITA: u32 = 2;
+pub const BLE_HCI_SCAN_FILT_USE_WL_INITA: u32 = 3;
+
+pub const BLE_HCI_ADV_FILT_NONE: u32 = 0;
+pub const BLE_HCI_ADV_FILT_SCAN: u32 = 1;
+pub const BLE_HCI_ADV_FILT_CONN: u32 = 2;
+pub const BLE_HCI_ADV_FILT_BOTH: u32 = 3;
+
+pub const BLE_HCI_LE_PHY_1M: u32 = 1;
+pub const BLE_HCI_LE_PHY_2M: u32 = 2;
+pub const BLE_HCI_LE_PHY_CODED: u32 = 3;
+
+// esp_bt.h
+pub type esp_power_level_t = c_uint;
+pub const esp_power_level_t_ESP_PWR_LVL_N12: esp_power_level_t = 0;
+pub const esp_power_level_t_ESP_PWR_LVL_N9: esp_power_level_t = 1;
+pub const esp_power_level_t_ESP_PWR_LVL_N6: esp_power_level_t = 2;
+pub const esp_power_level_t_ESP_PWR_LVL_N3: esp_power_level_t = 3;
+pub const esp_power_level_t_ESP_PWR_LVL_N0: esp_power_level_t = 4;
+pub const esp_power_level_t_ESP_PWR_LVL_P3: esp_power_level_t = 5;
+pub const esp_power_level_t_ESP_PWR_LVL_P6: esp_power_level_t = 6;
+pub const esp_power_level_t_ESP_PWR_LVL_P9: esp_power_level_t = 7;
+
+pub type esp_ble_power_type_t = c_uint;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL0: esp_ble_power_type_t = 0;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL1: esp_ble_power_type_t = 1;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL2: esp_ble_power_type_t = 2;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL3: esp_ble_power_type_t = 3;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL4: esp_ble_power_type_t = 4;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL5: esp_ble_power_type_t = 5;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL6: esp_ble_power_type_t = 6;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL7: esp_ble_power_type_t = 7;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_CONN_HDL8: esp_ble_power_type_t = 8;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_ADV: esp_ble_power_type_t = 9;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_SCAN: esp_ble_power_type_t = 10;
+pub const esp_ble_power_type_t_ESP_BLE_PWR_TYPE_DEFAULT: esp_ble_power_type_t = 11;
+
+// pthread.h, as a spin lock.
+pub type pthread_mutex_t = c_uint;
+
+const MUTEX_LOCKED: pthread_mutex_t = 1;
+const EBUSY: c_int = 16;
+
+pub unsafe fn pthread_mutex_lock(mutex: *mut pthread_mutex_t) -> c_int {
+    while unsafe { pthread_mutex_trylock(mutex) } != 0 {
+        std::thread::yield_now();
+    }
+    0
+}
+
+pub unsafe fn pthread_mutex_trylock(mutex: *mut pthread_mutex_t) -> c_int {
+    let lock = unsafe { AtomicU32::from_ptr(mutex) };
+    if lock.swap(MUTEX_LOCKED, Ordering::Acquire) == MUTEX_LOCKED {
+        EBUSY
+    } else {
+        0
+    }
+}
+
+pub unsafe fn pthread_mutex_unlock(mutex: *mut pthread_mutex_t) -> c_int {
+    unsafe { AtomicU32::from_ptr(mutex) }.store(0, Ordering::Release);
+    0
+}
+
+pub unsafe fn pthread_mutex_destroy(_mutex: *mut pthread_mutex_t) -> c_int {
+    0
+}
+
+// mbedtls/aes.h, backed by the RustCrypto implementation.
+pub const MBEDTLS_AES_ENCRYPT: u32 = 1;
+
+#[derive(Default)]
+pub struct mbedtls_aes_context {
+    cipher: Option<aes::Aes128>,
+}
+
+pub unsafe fn mbedtls_aes_init(ctx: *mut mbedtls_aes_context) {
+    unsafe { (*ctx).cipher = None };
+}
+
+pub unsafe fn mbedtls_aes_free(ctx: *mut mbedtls_aes_context) {
+    unsafe { (*ctx).cipher = None };
+}
+
+pub unsafe fn mbedtls_aes_setkey_enc(
+    ctx: *mut mbedtls_aes_context,
+    key: *const u8,
+    keybits: c_uint,
+) -> c_int {
+    use aes::cipher::KeyInit;
+
+    if keybits != 128 {
+        return -0x0020; // MBEDTLS_ERR_AES_INVALID_KEY_LENGTH
+    }
+    let key = unsafe { core::slice::from_raw_parts(key, 16) };
+    unsafe { (*ctx).cipher = Some(aes::Aes128::new(key.into())) };
+    0
+}
+
+pub unsafe fn mbedtls_aes_crypt_ecb(
+    ctx: *mut mbedtls_aes_context,
+    mode: c_int,
+    input: *const u8,
+    output: *mut u8,
+) -> c_int {
+    use aes::cipher::BlockEncrypt;
+
+    let Some(cipher) = (unsafe { &(*ctx).cipher }) else {
+        return -0x0021; // MBEDTLS_ERR_AES_BAD_INPUT_DATA
+    };
+    if mode != MBEDTLS_AES_ENCRYPT as c_int {
+        return -0x0021;
+    }
+    let mut block = aes::Block::clone_from_slice(unsafe { core::slice::from_raw_parts(input, 16) });
+    cipher.encrypt_block(&mut block);
+    unsafe { core::ptr::copy_nonoverlapping(block.as_ptr(), output, 16) };
+    0
+}
@@ -0,0 +1,82 @@
+use super::Beacon;
+use crate::BLEAdvertisementData;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+use crate::BLEExtAdvertisement;
+
+/// AltBeacon (<https://github.com/AltBeacon/spec>).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AltBeacon {
+    /// Company identifier of the beacon manufacturer.
+    pub manufacturer_id: u16,
+    pub beacon_id: [u8; 20],
+    /// RSSI at 1m in dBm.
+    pub reference_rssi: i8,
+    pub mfg_reserved: u8,
+}
+
+impl AltBeacon {
+    const BEACON_CODE: [u8; 2] = [0xBE, 0xAC];
+
+    pub fn new(manufacturer_id: u16, beacon_id: [u8; 20], reference_rssi: i8) -> Self {
+        Self {
+            manufacturer_id,
+            beacon_id,
+            reference_rssi,
+            mfg_reserved: 0,
+        }
+    }
+
+    /// Manufacturer specific data (including the company identifier).
+    pub fn manufacturer_data(&self) -> [u8; 26] {
+        let mut data = [0u8; 26];
+        data[0..2].copy_from_slice(&self.manufacturer_id.to_le_bytes());
+        data[2..4].copy_from_slice(&Self::BEACON_CODE);
+        data[4..24].copy_from_slice(&self.beacon_id);
+        data[24] = self.reference_rssi as u8;
+        data[25] = self.mfg_reserved;
+        data
+    }
+
+    /// Decode manufacturer specific data (including the company identifier).
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; 26] = data.try_into().ok()?;
+        if data[2..4] != Self::BEACON_CODE {
+            return None;
+        }
+
+        Some(Self {
+            manufacturer_id: u16::from_le_bytes([data[0], data[1]]),
+            beacon_id: data[4..24].try_into().unwrap(),
+            reference_rssi: data[24] as i8,
+            mfg_reserved: data[25],
+        })
+    }
+}
+
+impl Beacon for AltBeacon {
+    fn set_advertisement_data(&self, data: &mut BLEAdvertisementData) {
+        data.manufacturer_data(&self.manufacturer_data());
+    }
+
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    fn set_ext_advertisement(&self, advertisement: &mut BLEExtAdvertisement) {
+        advertisement.manufacturer_data(&self.manufacturer_data());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manufacturer_data() {
+        let beacon_id = core::array::from_fn(|i| i as u8);
+        let beacon = AltBeacon::new(0x0118, beacon_id, -59);
+
+        let data = beacon.manufacturer_data();
+        assert_eq!(data[..4], [0x18, 0x01, 0xbe, 0xac]);
+        assert_eq!(data[4..24], beacon_id);
+        assert_eq!(data[24..], [0xc5, 0x00]);
+        assert_eq!(AltBeacon::decode(&data), Some(beacon));
+    }
+}
@@ -0,0 +1,150 @@
+use super::ibeacon::APPLE_COMPANY_ID;
+use alloc::vec::Vec;
+
+pub(crate) const MICROSOFT_COMPANY_ID: u16 = 0x0006;
+const CDP_SCENARIO_TYPE: u8 = 0x01;
+
+/// Microsoft Connected Devices Platform beacon header.
+///
+/// Other Microsoft beacons (e.g. Swift Pair) use a different scenario type and layout,
+/// and are not decoded.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MicrosoftCdp {
+    /// Always 0x01 (Bluetooth).
+    pub scenario_type: u8,
+    pub version: u8,
+    /// e.g. 0x01: Xbox One, 0x06: Apple iPhone, 0x09: Windows 10 Desktop
+    pub device_type: u8,
+    pub flags: u8,
+    pub salt: [u8; 4],
+    pub device_hash: [u8; 16],
+}
+
+impl MicrosoftCdp {
+    /// Decode manufacturer specific data (including the company identifier).
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; 26] = data.get(..26)?.try_into().unwrap();
+        if u16::from_le_bytes([data[0], data[1]]) != MICROSOFT_COMPANY_ID
+            || data[2] != CDP_SCENARIO_TYPE
+        {
+            return None;
+        }
+
+        Some(Self {
+            scenario_type: data[2],
+            version: data[3] >> 5,
+            device_type: data[3] & 0x1F,
+            flags: data[4] & 0x1F,
+            salt: data[6..10].try_into().unwrap(),
+            device_hash: data[10..26].try_into().unwrap(),
+        })
+    }
+}
+
+/// A single message of an Apple Continuity advertisement.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContinuityMessage {
+    /// e.g. 0x02: iBeacon, 0x07: Proximity Pairing, 0x0C: Handoff, 0x10: Nearby Info, 0x12: Find My
+    pub message_type: u8,
+    pub data: Vec<u8>,
+}
+
+/// Apple Continuity advertisement (type-length-value messages).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AppleContinuity {
+    pub messages: Vec<ContinuityMessage>,
+}
+
+impl AppleContinuity {
+    /// Decode manufacturer specific data (including the company identifier).
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let (id, mut payload) = data.split_at_checked(2)?;
+        if u16::from_le_bytes([id[0], id[1]]) != APPLE_COMPANY_ID {
+            return None;
+        }
+
+        let mut messages = Vec::new();
+        while let [message_type, len, rest @ ..] = payload {
+            let (data, next) = rest.split_at_checked(*len as usize)?;
+            messages.push(ContinuityMessage {
+                message_type: *message_type,
+                data: data.to_vec(),
+            });
+            payload = next;
+        }
+
+        if messages.is_empty() || !payload.is_empty() {
+            return None;
+        }
+        Some(Self { messages })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn microsoft_cdp() {
+        // Windows 10 desktop.
+        let data = [
+            0x06, 0x00, 0x01, 0x09, 0x20, 0x02, 0x6b, 0x2d, 0x84, 0x3c, 0x9e, 0x55, 0x0a, 0x3d,
+            0x1f, 0x41, 0xb2, 0x8a, 0x7c, 0x60, 0x93, 0xe4, 0x11, 0x26, 0x5f, 0xc8,
+        ];
+        assert_eq!(
+            MicrosoftCdp::decode(&data),
+            Some(MicrosoftCdp {
+                scenario_type: 0x01,
+                version: 0,
+                device_type: 0x09,
+                flags: 0x00,
+                salt: [0x6b, 0x2d, 0x84, 0x3c],
+                device_hash: data[10..26].try_into().unwrap(),
+            })
+        );
+
+        assert_eq!(MicrosoftCdp::decode(&data[..25]), None);
+        assert_eq!(
+            MicrosoftCdp::decode(&[&[0x4c, 0x00], &data[2..]].concat()),
+            None
+        );
+    }
+
+    #[test]
+    fn microsoft_swift_pair() {
+        // Swift Pair (scenario type 0x03) has another layout.
+        let data = [
+            0x06, 0x00, 0x03, 0x00, 0x80, 0x54, 0x65, 0x73, 0x74, 0x20, 0x4d, 0x6f, 0x75, 0x73,
+            0x65, 0x20, 0x53, 0x77, 0x69, 0x66, 0x74, 0x20, 0x50, 0x61, 0x69, 0x72,
+        ];
+        assert_eq!(MicrosoftCdp::decode(&data), None);
+    }
+
+    #[test]
+    fn apple_continuity() {
+        // Handoff followed by Nearby Info.
+        let data = [
+            0x4c, 0x00, 0x0c, 0x0e, 0x00, 0xc3, 0x6f, 0x21, 0x6d, 0x8f, 0x35, 0x91, 0x0b, 0x7c,
+            0x25, 0x18, 0xa4, 0x9c, 0x10, 0x05, 0x0b, 0x1c, 0x0f, 0x4a, 0x2e,
+        ];
+        let continuity = AppleContinuity::decode(&data).unwrap();
+        assert_eq!(
+            continuity.messages,
+            [
+                ContinuityMessage {
+                    message_type: 0x0c,
+                    data: data[4..18].to_vec(),
+                },
+                ContinuityMessage {
+                    message_type: 0x10,
+                    data: data[20..25].to_vec(),
+                },
+            ]
+        );
+
+        // Truncated message.
+        assert_eq!(AppleContinuity::decode(&data[..24]), None);
+        // No message.
+        assert_eq!(AppleContinuity::decode(&data[..2]), None);
+    }
+}
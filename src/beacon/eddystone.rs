@@ -0,0 +1,568 @@
+use super::Beacon;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+use crate::BLEExtAdvertisement;
+use crate::{BLEAdvertisementData, BLEError, utilities::BleUuid};
+use alloc::{string::String, vec::Vec};
+use esp_idf_svc::sys as esp_idf_sys;
+
+/// Service UUID of the Eddystone frames.
+pub const EDDYSTONE_UUID: BleUuid = BleUuid::Uuid16(0xFEAA);
+
+pub(crate) const FRAME_TYPE_UID: u8 = 0x00;
+pub(crate) const FRAME_TYPE_URL: u8 = 0x10;
+pub(crate) const FRAME_TYPE_TLM: u8 = 0x20;
+pub(crate) const FRAME_TYPE_EID: u8 = 0x30;
+
+pub(crate) const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+pub(crate) const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net",
+    ".info", ".biz", ".gov",
+];
+
+const MAX_ENCODED_URL_LEN: usize = 17;
+
+fn set_advertisement_data(data: &mut BLEAdvertisementData, frame: &[u8]) {
+    data.add_service_uuid(EDDYSTONE_UUID);
+    data.service_data(EDDYSTONE_UUID, frame);
+}
+
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+fn set_ext_advertisement(advertisement: &mut BLEExtAdvertisement, frame: &[u8]) {
+    advertisement.complete_service(&EDDYSTONE_UUID);
+    advertisement.service_data(EDDYSTONE_UUID, frame);
+}
+
+macro_rules! impl_beacon {
+    ($t:ty) => {
+        impl Beacon for $t {
+            fn set_advertisement_data(&self, data: &mut BLEAdvertisementData) {
+                set_advertisement_data(data, &self.frame());
+            }
+
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            fn set_ext_advertisement(&self, advertisement: &mut BLEExtAdvertisement) {
+                set_ext_advertisement(advertisement, &self.frame());
+            }
+        }
+    };
+}
+
+/// Eddystone-UID frame.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EddystoneUid {
+    /// Calibrated TX power at 0m in dBm.
+    pub tx_power: i8,
+    pub namespace: [u8; 10],
+    pub instance: [u8; 6],
+}
+
+impl EddystoneUid {
+    pub fn new(namespace: [u8; 10], instance: [u8; 6], tx_power: i8) -> Self {
+        Self {
+            tx_power,
+            namespace,
+            instance,
+        }
+    }
+
+    /// Service data of the frame.
+    pub fn frame(&self) -> [u8; 20] {
+        let mut frame = [0u8; 20];
+        frame[0] = FRAME_TYPE_UID;
+        frame[1] = self.tx_power as u8;
+        frame[2..12].copy_from_slice(&self.namespace);
+        frame[12..18].copy_from_slice(&self.instance);
+        // Bytes 18..20 are reserved.
+        frame
+    }
+
+    /// Decode the service data of the frame.
+    /// The reserved bytes are optional.
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        if frame.len() < 18 || frame[0] != FRAME_TYPE_UID {
+            return None;
+        }
+
+        Some(Self {
+            tx_power: frame[1] as i8,
+            namespace: frame[2..12].try_into().unwrap(),
+            instance: frame[12..18].try_into().unwrap(),
+        })
+    }
+}
+
+impl_beacon!(EddystoneUid);
+
+/// Eddystone-URL frame.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EddystoneUrl {
+    /// Calibrated TX power at 0m in dBm.
+    pub tx_power: i8,
+    encoded: Vec<u8>,
+}
+
+impl EddystoneUrl {
+    /// Returns `BLE_HS_EINVAL` if the URL has no supported scheme or contains an unsupported character,
+    /// and `BLE_HS_EMSGSIZE` if the encoded URL is longer than 17 bytes.
+    pub fn new(url: &str, tx_power: i8) -> Result<Self, BLEError> {
+        let Some((scheme, _)) = URL_SCHEMES
+            .iter()
+            .enumerate()
+            .find(|(_, x)| url.starts_with(*x))
+        else {
+            return Err(BLEError::convert(esp_idf_sys::BLE_HS_EINVAL).unwrap_err());
+        };
+
+        let mut encoded = Vec::with_capacity(1 + MAX_ENCODED_URL_LEN);
+        encoded.push(scheme as u8);
+
+        let mut rest = &url[URL_SCHEMES[scheme].len()..];
+        while !rest.is_empty() {
+            if let Some((code, expansion)) = URL_EXPANSIONS
+                .iter()
+                .enumerate()
+                .find(|(_, x)| rest.starts_with(*x))
+            {
+                encoded.push(code as u8);
+                rest = &rest[expansion.len()..];
+                continue;
+            }
+
+            let c = rest.as_bytes()[0];
+            if !(0x21..=0x7E).contains(&c) {
+                return Err(BLEError::convert(esp_idf_sys::BLE_HS_EINVAL).unwrap_err());
+            }
+            encoded.push(c);
+            rest = &rest[1..];
+        }
+
+        if encoded.len() > 1 + MAX_ENCODED_URL_LEN {
+            return Err(BLEError::convert(esp_idf_sys::BLE_HS_EMSGSIZE).unwrap_err());
+        }
+
+        Ok(Self { tx_power, encoded })
+    }
+
+    /// Service data of the frame.
+    pub fn frame(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(2 + self.encoded.len());
+        frame.push(FRAME_TYPE_URL);
+        frame.push(self.tx_power as u8);
+        frame.extend_from_slice(&self.encoded);
+        frame
+    }
+
+    /// Decode the service data of the frame.
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let (&[FRAME_TYPE_URL, tx_power], encoded) = frame.split_at_checked(2)? else {
+            return None;
+        };
+        if encoded.is_empty()
+            || encoded.len() > 1 + MAX_ENCODED_URL_LEN
+            || encoded[0] as usize >= URL_SCHEMES.len()
+        {
+            return None;
+        }
+
+        Some(Self {
+            tx_power: tx_power as i8,
+            encoded: encoded.to_vec(),
+        })
+    }
+
+    /// The decoded URL.
+    pub fn url(&self) -> String {
+        let mut url = String::from(URL_SCHEMES[self.encoded[0] as usize]);
+        for x in &self.encoded[1..] {
+            match URL_EXPANSIONS.get(*x as usize) {
+                Some(expansion) => url.push_str(expansion),
+                None => url.push(*x as char),
+            }
+        }
+        url
+    }
+}
+
+impl_beacon!(EddystoneUrl);
+
+/// Unencrypted Eddystone-TLM frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct EddystoneTlm {
+    /// Battery voltage in mV. 0 if not supported.
+    pub battery_mv: u16,
+    /// Beacon temperature in degrees Celsius. `None` if not supported.
+    pub temperature: Option<f32>,
+    /// Number of advertising frames sent since power-up or reboot.
+    pub adv_count: u32,
+    /// Time since power-up or reboot in 0.1 second resolution.
+    pub uptime: u32,
+}
+
+impl EddystoneTlm {
+    /// Service data of the frame.
+    pub fn frame(&self) -> [u8; 14] {
+        // Signed 8.8 fixed point. 0x8000 means not supported.
+        let temperature = match self.temperature {
+            Some(x) => (x * 256.0) as i16 as u16,
+            None => 0x8000,
+        };
+
+        let mut frame = [0u8; 14];
+        frame[0] = FRAME_TYPE_TLM;
+        // Version
+        frame[1] = 0x00;
+        frame[2..4].copy_from_slice(&self.battery_mv.to_be_bytes());
+        frame[4..6].copy_from_slice(&temperature.to_be_bytes());
+        frame[6..10].copy_from_slice(&self.adv_count.to_be_bytes());
+        frame[10..14].copy_from_slice(&self.uptime.to_be_bytes());
+        frame
+    }
+
+    /// Decode the service data of the frame.
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let frame: &[u8; 14] = frame.try_into().ok()?;
+        if frame[0] != FRAME_TYPE_TLM || frame[1] != 0x00 {
+            return None;
+        }
+
+        let temperature = u16::from_be_bytes([frame[4], frame[5]]);
+        Some(Self {
+            battery_mv: u16::from_be_bytes([frame[2], frame[3]]),
+            temperature: (temperature != 0x8000).then(|| (temperature as i16) as f32 / 256.0),
+            adv_count: u32::from_be_bytes(frame[6..10].try_into().unwrap()),
+            uptime: u32::from_be_bytes(frame[10..14].try_into().unwrap()),
+        })
+    }
+}
+
+impl_beacon!(EddystoneTlm);
+
+/// Eddystone-EID frame.
+///
+/// The ephemeral identifier is computed from the identity key shared with the resolver.
+/// Update `beacon_time` and readvertise at least every `2^rotation_exponent` seconds.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct EddystoneEid {
+    pub identity_key: [u8; 16],
+    /// The identifier rotates every `2^rotation_exponent` seconds (0..=15).
+    pub rotation_exponent: u8,
+    /// Calibrated TX power at 0m in dBm.
+    pub tx_power: i8,
+    /// Beacon time counter in seconds.
+    pub beacon_time: u32,
+}
+
+impl EddystoneEid {
+    pub fn new(identity_key: [u8; 16], rotation_exponent: u8, tx_power: i8) -> Self {
+        Self {
+            identity_key,
+            rotation_exponent: rotation_exponent.min(15),
+            tx_power,
+            beacon_time: 0,
+        }
+    }
+
+    /// Compute the ephemeral identifier for the current beacon time.
+    pub fn eid(&self) -> [u8; 8] {
+        let time = self.beacon_time.to_be_bytes();
+
+        let mut block = [0u8; 16];
+        block[11] = 0xFF;
+        block[14] = time[0];
+        block[15] = time[1];
+        let temporary_key = aes128_encrypt(&self.identity_key, &block);
+
+        let k = self.rotation_exponent.min(15);
+        let mut block = [0u8; 16];
+        block[11] = k;
+        block[12..16].copy_from_slice(&((self.beacon_time >> k) << k).to_be_bytes());
+        let encrypted = aes128_encrypt(&temporary_key, &block);
+
+        let mut eid = [0u8; 8];
+        eid.copy_from_slice(&encrypted[..8]);
+        eid
+    }
+
+    /// Service data of the frame.
+    pub fn frame(&self) -> [u8; 10] {
+        let mut frame = [0u8; 10];
+        frame[0] = FRAME_TYPE_EID;
+        frame[1] = self.tx_power as u8;
+        frame[2..10].copy_from_slice(&self.eid());
+        frame
+    }
+}
+
+impl_beacon!(EddystoneEid);
+
+fn aes128_encrypt(key: &[u8; 16], block: &[u8; 16]) -> [u8; 16] {
+    let mut output = [0u8; 16];
+    unsafe {
+        let mut ctx = esp_idf_sys::mbedtls_aes_context::default();
+        esp_idf_sys::mbedtls_aes_init(&mut ctx);
+        let rc = esp_idf_sys::mbedtls_aes_setkey_enc(&mut ctx, key.as_ptr(), 128);
+        debug_assert_eq!(rc, 0);
+        let rc = esp_idf_sys::mbedtls_aes_crypt_ecb(
+            &mut ctx,
+            esp_idf_sys::MBEDTLS_AES_ENCRYPT as _,
+            block.as_ptr(),
+            output.as_mut_ptr(),
+        );
+        debug_assert_eq!(rc, 0);
+        esp_idf_sys::mbedtls_aes_free(&mut ctx);
+    }
+    output
+}
+
+/// A decoded Eddystone frame.
+#[derive(Clone, PartialEq, Debug)]
+pub enum EddystoneFrame {
+    Uid(EddystoneUid),
+    Url(EddystoneUrl),
+    Tlm(EddystoneTlm),
+    /// Encrypted Eddystone-TLM frame.
+    EncryptedTlm {
+        etlm: [u8; 12],
+        salt: u16,
+        mic: u16,
+    },
+    Eid {
+        /// Calibrated TX power at 0m in dBm.
+        tx_power: i8,
+        eid: [u8; 8],
+    },
+}
+
+impl EddystoneFrame {
+    /// Decode the service data of the Eddystone service UUID (0xFEAA).
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        match *frame.first()? {
+            FRAME_TYPE_UID => EddystoneUid::decode(frame).map(Self::Uid),
+            FRAME_TYPE_URL => EddystoneUrl::decode(frame).map(Self::Url),
+            FRAME_TYPE_TLM => match frame.get(1)? {
+                0x00 => EddystoneTlm::decode(frame).map(Self::Tlm),
+                0x01 => {
+                    let frame: &[u8; 18] = frame.try_into().ok()?;
+                    Some(Self::EncryptedTlm {
+                        etlm: frame[2..14].try_into().unwrap(),
+                        salt: u16::from_be_bytes([frame[14], frame[15]]),
+                        mic: u16::from_be_bytes([frame[16], frame[17]]),
+                    })
+                }
+                _ => None,
+            },
+            FRAME_TYPE_EID => {
+                let frame: &[u8; 10] = frame.try_into().ok()?;
+                Some(Self::Eid {
+                    tx_power: frame[1] as i8,
+                    eid: frame[2..10].try_into().unwrap(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uid_frame() {
+        let namespace = [0x8b, 0x0c, 0xa7, 0x50, 0xe7, 0xa7, 0x4e, 0x14, 0xbd, 0x99];
+        let instance = [0x00, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let uid = EddystoneUid::new(namespace, instance, -18);
+        let frame = uid.frame();
+        assert_eq!(
+            frame,
+            [
+                0x00, 0xee, 0x8b, 0x0c, 0xa7, 0x50, 0xe7, 0xa7, 0x4e, 0x14, 0xbd, 0x99, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x01, 0x00, 0x00
+            ]
+        );
+        assert_eq!(EddystoneUid::decode(&frame), Some(uid));
+    }
+
+    #[test]
+    fn url_frame() {
+        let url = EddystoneUrl::new("https://www.google.com/", -20).unwrap();
+        assert_eq!(
+            url.frame(),
+            [0x10, 0xec, 0x01, b'g', b'o', b'o', b'g', b'l', b'e', 0x00]
+        );
+        assert_eq!(url.url(), "https://www.google.com/");
+        assert_eq!(EddystoneUrl::decode(&url.frame()), Some(url));
+
+        assert!(EddystoneUrl::new("ftp://example.com", 0).is_err());
+        assert!(EddystoneUrl::new("https://exa mple.com", 0).is_err());
+        assert!(EddystoneUrl::new("https://www.example-example-example.com", 0).is_err());
+    }
+
+    #[test]
+    fn tlm_frame() {
+        let tlm = EddystoneTlm {
+            battery_mv: 3000,
+            temperature: Some(23.5),
+            adv_count: 0x0102_0304,
+            uptime: 0x0a0b_0c0d,
+        };
+        let frame = tlm.frame();
+        assert_eq!(
+            frame,
+            [
+                0x20, 0x00, 0x0b, 0xb8, 0x17, 0x80, 0x01, 0x02, 0x03, 0x04, 0x0a, 0x0b, 0x0c, 0x0d
+            ]
+        );
+        assert_eq!(EddystoneTlm::decode(&frame), Some(tlm));
+
+        let tlm = EddystoneTlm {
+            temperature: None,
+            ..tlm
+        };
+        assert_eq!(&tlm.frame()[4..6], &[0x80, 0x00]);
+        assert_eq!(EddystoneTlm::decode(&tlm.frame()), Some(tlm));
+    }
+
+    // Computed with OpenSSL's AES-128 following the Eddystone-EID computation spec.
+    const IDENTITY_KEY: [u8; 16] = [
+        0xe2, 0xe3, 0x7b, 0xa1, 0xe8, 0xfb, 0xb6, 0xb2, 0xd5, 0xd2, 0xb3, 0x1c, 0x9b, 0x5e, 0x83,
+        0xc4,
+    ];
+
+    fn eid(rotation_exponent: u8, beacon_time: u32) -> [u8; 8] {
+        let mut eid = EddystoneEid::new(IDENTITY_KEY, rotation_exponent, -10);
+        eid.beacon_time = beacon_time;
+        eid.eid()
+    }
+
+    #[test]
+    fn eid_vectors() {
+        assert_eq!(
+            eid(10, 0x0001_a4c5),
+            [0x7f, 0xc9, 0xfd, 0x7f, 0xfa, 0x67, 0x33, 0x02]
+        );
+        assert_eq!(
+            eid(10, 0x0001_a800),
+            [0x2c, 0xc8, 0x7c, 0x3a, 0xe9, 0x25, 0xd1, 0xab]
+        );
+        assert_eq!(eid(0, 0), [0x9f, 0xf6, 0x61, 0xaf, 0x74, 0xf9, 0x73, 0x8b]);
+        assert_eq!(
+            eid(15, 0x1234_5678),
+            [0xc0, 0x94, 0xd2, 0xdb, 0xd3, 0xf8, 0x62, 0x83]
+        );
+    }
+
+    #[test]
+    fn eid_rotation() {
+        // The identifier only changes every 2^10 seconds.
+        assert_eq!(eid(10, 0x0001_a400), eid(10, 0x0001_a7ff));
+        assert_ne!(eid(10, 0x0001_a7ff), eid(10, 0x0001_a800));
+
+        let mut beacon = EddystoneEid::new(IDENTITY_KEY, 10, -10);
+        beacon.beacon_time = 0x0001_a4c5;
+        let frame = beacon.frame();
+        assert_eq!(frame[..2], [0x30, 0xf6]);
+        assert_eq!(
+            EddystoneFrame::decode(&frame),
+            Some(EddystoneFrame::Eid {
+                tx_power: -10,
+                eid: beacon.eid()
+            })
+        );
+    }
+
+    #[test]
+    fn decode_uid() {
+        let frame = [
+            0x00, 0xe7, 0xed, 0xd5, 0x0b, 0x73, 0x4a, 0x0d, 0x4e, 0x5f, 0x63, 0xd9, 0x00, 0x00,
+            0x00, 0x00, 0x12, 0x34, 0x00, 0x00,
+        ];
+        assert_eq!(
+            EddystoneFrame::decode(&frame),
+            Some(EddystoneFrame::Uid(EddystoneUid {
+                tx_power: -25,
+                namespace: [0xed, 0xd5, 0x0b, 0x73, 0x4a, 0x0d, 0x4e, 0x5f, 0x63, 0xd9],
+                instance: [0x00, 0x00, 0x00, 0x00, 0x12, 0x34],
+            }))
+        );
+        // Without the reserved bytes.
+        assert!(EddystoneFrame::decode(&frame[..18]).is_some());
+        assert_eq!(EddystoneFrame::decode(&frame[..17]), None);
+    }
+
+    #[test]
+    fn decode_url() {
+        let frame = [
+            0x10, 0xeb, 0x03, b'g', b'o', b'o', b'.', b'g', b'l', b'/', b'P', b'H', b'N', b'S',
+            b'd', b'K',
+        ];
+        let Some(EddystoneFrame::Url(url)) = EddystoneFrame::decode(&frame) else {
+            panic!("not an Eddystone-URL frame");
+        };
+        assert_eq!(url.tx_power, -21);
+        assert_eq!(url.url(), "https://goo.gl/PHNSdK");
+
+        let frame = [
+            0x10, 0x00, 0x00, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0x07,
+        ];
+        let Some(EddystoneFrame::Url(url)) = EddystoneFrame::decode(&frame) else {
+            panic!("not an Eddystone-URL frame");
+        };
+        assert_eq!(url.url(), "http://www.example.com");
+
+        // Unknown scheme.
+        assert_eq!(EddystoneFrame::decode(&[0x10, 0x00, 0x04, b'a']), None);
+    }
+
+    #[test]
+    fn decode_tlm() {
+        let frame = [
+            0x20, 0x00, 0x0b, 0xea, 0x15, 0x40, 0x00, 0x00, 0x3a, 0x98, 0x00, 0x01, 0x86, 0xa0,
+        ];
+        assert_eq!(
+            EddystoneFrame::decode(&frame),
+            Some(EddystoneFrame::Tlm(EddystoneTlm {
+                battery_mv: 3050,
+                temperature: Some(21.25),
+                adv_count: 15000,
+                uptime: 100000,
+            }))
+        );
+    }
+
+    #[test]
+    fn decode_encrypted_tlm() {
+        let frame = [
+            0x20, 0x01, 0x5d, 0x3f, 0x2a, 0x91, 0x08, 0xc4, 0x7e, 0x13, 0xb6, 0x0a, 0xf2, 0x59,
+            0x1c, 0x2d, 0xa4, 0x7b,
+        ];
+        assert_eq!(
+            EddystoneFrame::decode(&frame),
+            Some(EddystoneFrame::EncryptedTlm {
+                etlm: frame[2..14].try_into().unwrap(),
+                salt: 0x1c2d,
+                mic: 0xa47b,
+            })
+        );
+        assert_eq!(EddystoneFrame::decode(&frame[..17]), None);
+    }
+
+    #[test]
+    fn decode_eid() {
+        let frame = [0x30, 0xf6, 0x7f, 0xc9, 0xfd, 0x7f, 0xfa, 0x67, 0x33, 0x02];
+        assert_eq!(
+            EddystoneFrame::decode(&frame),
+            Some(EddystoneFrame::Eid {
+                tx_power: -10,
+                eid: [0x7f, 0xc9, 0xfd, 0x7f, 0xfa, 0x67, 0x33, 0x02],
+            })
+        );
+    }
+
+    #[test]
+    fn decode_unknown_frame_type() {
+        assert_eq!(EddystoneFrame::decode(&[0x40, 0x00]), None);
+        assert_eq!(EddystoneFrame::decode(&[]), None);
+    }
+}
@@ -0,0 +1,96 @@
+use super::Beacon;
+use crate::BLEAdvertisementData;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+use crate::BLEExtAdvertisement;
+use uuid::Uuid;
+
+pub(crate) const APPLE_COMPANY_ID: u16 = 0x004C;
+pub(crate) const IBEACON_TYPE: u8 = 0x02;
+const IBEACON_LEN: u8 = 0x15;
+
+/// Apple iBeacon.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IBeacon {
+    pub proximity_uuid: Uuid,
+    pub major: u16,
+    pub minor: u16,
+    /// RSSI at 1m in dBm.
+    pub measured_power: i8,
+}
+
+impl IBeacon {
+    pub fn new(proximity_uuid: Uuid, major: u16, minor: u16, measured_power: i8) -> Self {
+        Self {
+            proximity_uuid,
+            major,
+            minor,
+            measured_power,
+        }
+    }
+
+    /// Manufacturer specific data (including the company identifier).
+    pub fn manufacturer_data(&self) -> [u8; 25] {
+        let mut data = [0u8; 25];
+        data[0..2].copy_from_slice(&APPLE_COMPANY_ID.to_le_bytes());
+        data[2] = IBEACON_TYPE;
+        data[3] = IBEACON_LEN;
+        data[4..20].copy_from_slice(self.proximity_uuid.as_bytes());
+        data[20..22].copy_from_slice(&self.major.to_be_bytes());
+        data[22..24].copy_from_slice(&self.minor.to_be_bytes());
+        data[24] = self.measured_power as u8;
+        data
+    }
+
+    /// Decode manufacturer specific data (including the company identifier).
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; 25] = data.try_into().ok()?;
+        if u16::from_le_bytes([data[0], data[1]]) != APPLE_COMPANY_ID
+            || data[2] != IBEACON_TYPE
+            || data[3] != IBEACON_LEN
+        {
+            return None;
+        }
+
+        Some(Self {
+            proximity_uuid: Uuid::from_bytes(data[4..20].try_into().unwrap()),
+            major: u16::from_be_bytes([data[20], data[21]]),
+            minor: u16::from_be_bytes([data[22], data[23]]),
+            measured_power: data[24] as i8,
+        })
+    }
+}
+
+impl Beacon for IBeacon {
+    fn set_advertisement_data(&self, data: &mut BLEAdvertisementData) {
+        data.manufacturer_data(&self.manufacturer_data());
+    }
+
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    fn set_ext_advertisement(&self, advertisement: &mut BLEExtAdvertisement) {
+        advertisement.manufacturer_data(&self.manufacturer_data());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    #[test]
+    fn manufacturer_data() {
+        let beacon = IBeacon::new(
+            uuid!("fda50693-a4e2-4fb1-afcf-c6eb07647825"),
+            10001,
+            19641,
+            -59,
+        );
+        assert_eq!(
+            beacon.manufacturer_data(),
+            [
+                0x4c, 0x00, 0x02, 0x15, 0xfd, 0xa5, 0x06, 0x93, 0xa4, 0xe2, 0x4f, 0xb1, 0xaf, 0xcf,
+                0xc6, 0xeb, 0x07, 0x64, 0x78, 0x25, 0x27, 0x11, 0x4c, 0xb9, 0xc5
+            ]
+        );
+        assert_eq!(IBeacon::decode(&beacon.manufacturer_data()), Some(beacon));
+    }
+}
@@ -0,0 +1,168 @@
+mod alt_beacon;
+pub use alt_beacon::AltBeacon;
+
+mod continuity;
+pub use continuity::*;
+
+mod eddystone;
+pub use eddystone::*;
+
+mod ibeacon;
+pub use ibeacon::IBeacon;
+
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+use crate::BLEExtAdvertisement;
+use crate::{BLEAdvertisedData, BLEAdvertisementData};
+
+/// A beacon format that can be written into advertisement data.
+pub trait Beacon {
+    /// Write the beacon into the advertisement data.
+    fn set_advertisement_data(&self, data: &mut BLEAdvertisementData);
+
+    /// Create advertisement data containing only the beacon.
+    fn advertisement_data(&self) -> BLEAdvertisementData {
+        let mut data = BLEAdvertisementData::new();
+        self.set_advertisement_data(&mut data);
+        data
+    }
+
+    /// Append the beacon to the extended advertisement payload.
+    ///
+    /// The Flags AD is not added, use [`BLEExtAdvertisement::flags`] if needed.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    fn set_ext_advertisement(&self, advertisement: &mut BLEExtAdvertisement);
+}
+
+/// A beacon decoded from advertisement data.
+#[derive(Clone, PartialEq, Debug)]
+pub enum BeaconFrame {
+    IBeacon(IBeacon),
+    AltBeacon(AltBeacon),
+    Eddystone(EddystoneFrame),
+    MicrosoftCdp(MicrosoftCdp),
+    AppleContinuity(AppleContinuity),
+}
+
+impl BeaconFrame {
+    /// Classify and decode the advertisement data.
+    pub fn decode<T: AsRef<[u8]>>(data: &BLEAdvertisedData<T>) -> Option<Self> {
+        if let Some(frame) = data
+            .service_data_list()
+            .find(|x| x.uuid == EDDYSTONE_UUID)
+            .and_then(|x| EddystoneFrame::decode(x.service_data))
+        {
+            return Some(Self::Eddystone(frame));
+        }
+
+        let manufacture_data = data.manufacture_data_raw()?;
+        if let Some(x) = IBeacon::decode(manufacture_data) {
+            Some(Self::IBeacon(x))
+        } else if let Some(x) = AltBeacon::decode(manufacture_data) {
+            Some(Self::AltBeacon(x))
+        } else if let Some(x) = MicrosoftCdp::decode(manufacture_data) {
+            Some(Self::MicrosoftCdp(x))
+        } else {
+            AppleContinuity::decode(manufacture_data).map(Self::AppleContinuity)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::uuid;
+
+    fn decode(payload: &[u8]) -> Option<BeaconFrame> {
+        BeaconFrame::decode(&BLEAdvertisedData::new(payload))
+    }
+
+    #[test]
+    fn ibeacon() {
+        let payload = [
+            0x02, 0x01, 0x06, 0x1a, 0xff, 0x4c, 0x00, 0x02, 0x15, 0xe2, 0xc5, 0x6d, 0xb5, 0xdf,
+            0xfb, 0x48, 0xd2, 0xb0, 0x60, 0xd0, 0xf5, 0xa7, 0x10, 0x96, 0xe0, 0x00, 0x01, 0x00,
+            0x02, 0xc5,
+        ];
+        assert_eq!(
+            decode(&payload),
+            Some(BeaconFrame::IBeacon(IBeacon::new(
+                uuid!("e2c56db5-dffb-48d2-b060-d0f5a71096e0"),
+                1,
+                2,
+                -59
+            )))
+        );
+    }
+
+    #[test]
+    fn alt_beacon() {
+        let payload = [
+            0x02, 0x01, 0x06, 0x1b, 0xff, 0x18, 0x01, 0xbe, 0xac, 0x2f, 0x23, 0x44, 0x54, 0xcf,
+            0x6d, 0x4a, 0x0f, 0xad, 0xf2, 0xf4, 0x91, 0x1b, 0xa9, 0xff, 0xa6, 0x00, 0x01, 0x00,
+            0x02, 0xc5, 0x00,
+        ];
+        let Some(BeaconFrame::AltBeacon(beacon)) = decode(&payload) else {
+            panic!("not an AltBeacon");
+        };
+        assert_eq!(beacon.manufacturer_id, 0x0118);
+        assert_eq!(beacon.beacon_id[..], payload[9..29]);
+        assert_eq!(beacon.reference_rssi, -59);
+        assert_eq!(beacon.mfg_reserved, 0);
+    }
+
+    #[test]
+    fn eddystone() {
+        // Eddystone-TLM after the complete list of 16-bit service UUIDs.
+        let payload = [
+            0x02, 0x01, 0x06, 0x03, 0x03, 0xaa, 0xfe, 0x11, 0x16, 0xaa, 0xfe, 0x20, 0x00, 0x0b,
+            0xb8, 0x80, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x64,
+        ];
+        assert_eq!(
+            decode(&payload),
+            Some(BeaconFrame::Eddystone(EddystoneFrame::Tlm(EddystoneTlm {
+                battery_mv: 3000,
+                temperature: None,
+                adv_count: 16,
+                uptime: 100,
+            })))
+        );
+    }
+
+    #[test]
+    fn microsoft_cdp() {
+        let payload = [
+            0x1e, 0xff, 0x06, 0x00, 0x01, 0x09, 0x20, 0x02, 0x6b, 0x2d, 0x84, 0x3c, 0x9e, 0x55,
+            0x0a, 0x3d, 0x1f, 0x41, 0xb2, 0x8a, 0x7c, 0x60, 0x93, 0xe4, 0x11, 0x26, 0x5f, 0xc8,
+            0x00, 0x00, 0x00,
+        ];
+        let Some(BeaconFrame::MicrosoftCdp(cdp)) = decode(&payload) else {
+            panic!("not a Microsoft CDP beacon");
+        };
+        assert_eq!(cdp.device_type, 0x09);
+        assert_eq!(cdp.salt, [0x6b, 0x2d, 0x84, 0x3c]);
+    }
+
+    #[test]
+    fn apple_continuity() {
+        // Nearby Info.
+        let payload = [
+            0x02, 0x01, 0x1a, 0x0a, 0xff, 0x4c, 0x00, 0x10, 0x05, 0x0b, 0x1c, 0x0f, 0x4a, 0x2e,
+        ];
+        assert_eq!(
+            decode(&payload),
+            Some(BeaconFrame::AppleContinuity(AppleContinuity {
+                messages: alloc::vec![ContinuityMessage {
+                    message_type: 0x10,
+                    data: alloc::vec![0x0b, 0x1c, 0x0f, 0x4a, 0x2e],
+                }],
+            }))
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        // Flags and a complete local name only.
+        let payload = [0x02, 0x01, 0x06, 0x05, 0x09, b't', b'e', b's', b't'];
+        assert_eq!(decode(&payload), None);
+    }
+}
@@ -2,7 +2,7 @@ use esp_idf_svc::sys::*;
 use num_enum::TryFromPrimitive;
 
 /// Bluetooth Device address type
-#[derive(PartialEq, Eq, TryFromPrimitive)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, TryFromPrimitive)]
 #[repr(u8)]
 pub enum BLEAddressType {
     Public = BLE_ADDR_PUBLIC as _,
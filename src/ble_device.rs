@@ -207,6 +207,28 @@ impl BLEDevice {
         unsafe { esp_idf_sys::ble_att_preferred_mtu() }
     }
 
+    /// Sets the default preferred PHYs used for new connections.
+    pub fn set_preferred_phy(&self, tx_phys: PhyMask, rx_phys: PhyMask) -> Result<(), BLEError> {
+        unsafe {
+            ble!(esp_idf_sys::ble_gap_set_prefered_default_le_phy(
+                tx_phys.bits(),
+                rx_phys.bits()
+            ))
+        }
+    }
+
+    /// Sets the suggested default data length used for new connections.
+    ///
+    /// * `tx_octets`: The preferred maximum payload size (27 - 251 bytes).
+    /// * `tx_time`: The preferred maximum transmission time (328 - 17040 microseconds).
+    pub fn set_preferred_data_len(&self, tx_octets: u16, tx_time: u16) -> Result<(), BLEError> {
+        unsafe {
+            ble!(esp_idf_sys::ble_gap_write_sugg_def_data_len(
+                tx_octets, tx_time
+            ))
+        }
+    }
+
     /// Get the addresses of all bonded peer device.
     pub fn bonded_addresses(&self) -> Result<Vec<BLEAddress>, BLEError> {
         let mut peer_id_addrs =
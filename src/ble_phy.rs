@@ -0,0 +1,277 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{future::Future, pin::pin, task::Poll};
+use esp_idf_svc::sys;
+
+use crate::{
+    BLEError, Signal, ble,
+    enums::{CodedPhyOption, PhyMask, SecPhy},
+    utilities::mutex::Mutex,
+};
+
+/// The PHYs of a connection after a PHY update procedure.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PhyUpdate {
+    pub tx_phy: SecPhy,
+    pub rx_phy: SecPhy,
+}
+
+/// The maximum payload sizes (in bytes) and transmission times (in microseconds) of a connection.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DataLengthChange {
+    pub max_tx_octets: u16,
+    pub max_tx_time: u16,
+    pub max_rx_octets: u16,
+    pub max_rx_time: u16,
+}
+
+type WaitSignal<T> = Signal<Result<T, BLEError>>;
+
+/// Tasks waiting for the completion of a procedure on a connection.
+struct Waiters<T>(Mutex<Vec<(u16, Arc<WaitSignal<T>>)>>);
+
+impl<T: Copy + Send> Waiters<T> {
+    const fn new() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+
+    fn register(&self, conn_handle: u16) -> Waiter<'_, T> {
+        let signal = Arc::new(Signal::new());
+        self.0.lock().push((conn_handle, signal.clone()));
+        Waiter {
+            waiters: self,
+            signal,
+        }
+    }
+
+    fn complete(&self, conn_handle: u16, result: Result<T, BLEError>) {
+        for (_, signal) in self.0.lock().iter().filter(|x| x.0 == conn_handle) {
+            signal.signal(result);
+        }
+    }
+}
+
+/// Unregisters the signal when the waiting future is dropped.
+struct Waiter<'a, T: Copy + Send> {
+    waiters: &'a Waiters<T>,
+    signal: Arc<WaitSignal<T>>,
+}
+
+impl<T: Copy + Send> Waiter<'_, T> {
+    async fn wait(&self) -> Result<T, BLEError> {
+        self.signal.wait().await
+    }
+}
+
+impl<T: Copy + Send> Drop for Waiter<'_, T> {
+    fn drop(&mut self) {
+        self.waiters
+            .0
+            .lock()
+            .retain(|x| !Arc::ptr_eq(&x.1, &self.signal));
+    }
+}
+
+static PHY_WAITERS: Waiters<PhyUpdate> = Waiters::new();
+static DATA_LEN_WAITERS: Waiters<DataLengthChange> = Waiters::new();
+
+/// The last data lengths reported for each connection.
+#[cfg(not(all(
+    esp_idf_version_major = "5",
+    any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+)))]
+static DATA_LENS: Mutex<Vec<(u16, DataLengthChange)>> = Mutex::new(Vec::new());
+
+/// The data lengths of a connection until the controller reports a change.
+#[cfg(not(all(
+    esp_idf_version_major = "5",
+    any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+)))]
+const DEFAULT_DATA_LEN: DataLengthChange = DataLengthChange {
+    max_tx_octets: 27,
+    max_tx_time: 328,
+    max_rx_octets: 27,
+    max_rx_time: 328,
+};
+
+fn to_phy(phy: u8) -> Result<SecPhy, BLEError> {
+    SecPhy::try_from(phy).map_err(|_| BLEError::convert(sys::BLE_HS_EUNKNOWN).unwrap_err())
+}
+
+pub(crate) fn phy(conn_handle: u16) -> Result<(SecPhy, SecPhy), BLEError> {
+    let mut tx_phy = 0;
+    let mut rx_phy = 0;
+    unsafe {
+        ble!(sys::ble_gap_read_le_phy(
+            conn_handle,
+            &mut tx_phy,
+            &mut rx_phy
+        ))?;
+    }
+    Ok((to_phy(tx_phy)?, to_phy(rx_phy)?))
+}
+
+pub(crate) fn set_preferred_phy(
+    conn_handle: u16,
+    tx_phys: PhyMask,
+    rx_phys: PhyMask,
+    coded: CodedPhyOption,
+) -> Result<(), BLEError> {
+    unsafe {
+        ble!(sys::ble_gap_set_prefered_le_phy(
+            conn_handle,
+            tx_phys.bits(),
+            rx_phys.bits(),
+            coded.into()
+        ))
+    }
+}
+
+/// Whether the mask allows the PHY. An empty mask allows any PHY.
+fn allows(mask: PhyMask, phy: SecPhy) -> bool {
+    mask.is_empty()
+        || mask.contains(match phy {
+            SecPhy::Phy1M => PhyMask::Phy1M,
+            SecPhy::Phy2M => PhyMask::Phy2M,
+            SecPhy::Coded => PhyMask::Coded,
+        })
+}
+
+/// Returns the current PHYs without an update if the masks already allow them.
+/// Otherwise the controller may not report the completion if the PHYs do not change,
+/// so the wait is limited by `timeout_ms`.
+pub(crate) async fn update_phy(
+    conn_handle: u16,
+    tx_phys: PhyMask,
+    rx_phys: PhyMask,
+    coded: CodedPhyOption,
+    timeout_ms: u32,
+) -> Result<PhyUpdate, BLEError> {
+    let (tx_phy, rx_phy) = phy(conn_handle)?;
+    if allows(tx_phys, tx_phy) && allows(rx_phys, rx_phy) {
+        return Ok(PhyUpdate { tx_phy, rx_phy });
+    }
+
+    let waiter = PHY_WAITERS.register(conn_handle);
+    set_preferred_phy(conn_handle, tx_phys, rx_phys, coded)?;
+    with_timeout(waiter.wait(), timeout_ms).await
+}
+
+/// Fail with `BLE_HS_ETIMEOUT` if the future does not complete within `timeout_ms`.
+async fn with_timeout<T>(
+    future: impl Future<Output = Result<T, BLEError>>,
+    timeout_ms: u32,
+) -> Result<T, BLEError> {
+    let mut future = pin!(future);
+    let mut timeout = pin!(crate::utilities::delay_ms(timeout_ms));
+    core::future::poll_fn(|cx| {
+        if let Poll::Ready(result) = future.as_mut().poll(cx) {
+            return Poll::Ready(result);
+        }
+        match timeout.as_mut().poll(cx) {
+            Poll::Ready(Ok(())) => {
+                Poll::Ready(Err(BLEError::convert(sys::BLE_HS_ETIMEOUT).unwrap_err()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+    .await
+}
+
+pub(crate) fn set_data_len(conn_handle: u16, tx_octets: u16, tx_time: u16) -> Result<(), BLEError> {
+    unsafe { ble!(sys::ble_gap_set_data_len(conn_handle, tx_octets, tx_time)) }
+}
+
+/// The data lengths of the connection, as last reported by the controller.
+#[cfg(not(all(
+    esp_idf_version_major = "5",
+    any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+)))]
+pub(crate) fn data_len(conn_handle: u16) -> DataLengthChange {
+    DATA_LENS
+        .lock()
+        .iter()
+        .find(|x| x.0 == conn_handle)
+        .map_or(DEFAULT_DATA_LEN, |x| x.1)
+}
+
+/// Returns the current lengths without an update if they are already at least the requested ones.
+/// Otherwise the controller reports no change if the requested values are already in use,
+/// so the wait is limited by `timeout_ms`.
+#[cfg(not(all(
+    esp_idf_version_major = "5",
+    any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+)))]
+pub(crate) async fn update_data_len(
+    conn_handle: u16,
+    tx_octets: u16,
+    tx_time: u16,
+    timeout_ms: u32,
+) -> Result<DataLengthChange, BLEError> {
+    let current = data_len(conn_handle);
+    if current.max_tx_octets >= tx_octets && current.max_tx_time >= tx_time {
+        return Ok(current);
+    }
+
+    let waiter = DATA_LEN_WAITERS.register(conn_handle);
+    set_data_len(conn_handle, tx_octets, tx_time)?;
+    with_timeout(waiter.wait(), timeout_ms).await
+}
+
+/// Complete the waiting PHY update of the connection.
+pub(crate) fn on_phy_update(event: &sys::ble_gap_event) -> (u16, Result<PhyUpdate, BLEError>) {
+    let phy_updated = unsafe { &event.__bindgen_anon_1.phy_updated };
+    // The status is an HCI error code.
+    let status = match phy_updated.status {
+        0 => 0,
+        status => sys::BLE_HS_ERR_HCI_BASE + status as u32,
+    };
+    let result = BLEError::convert(status).and_then(|_| {
+        Ok(PhyUpdate {
+            tx_phy: to_phy(phy_updated.tx_phy)?,
+            rx_phy: to_phy(phy_updated.rx_phy)?,
+        })
+    });
+
+    PHY_WAITERS.complete(phy_updated.conn_handle, result);
+    (phy_updated.conn_handle, result)
+}
+
+/// Complete the waiting data length update of the connection.
+#[cfg(not(all(
+    esp_idf_version_major = "5",
+    any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+)))]
+pub(crate) fn on_data_len_change(event: &sys::ble_gap_event) -> (u16, DataLengthChange) {
+    let data_len = unsafe { &event.__bindgen_anon_1.data_len_chg };
+    let change = DataLengthChange {
+        max_tx_octets: data_len.max_tx_octets,
+        max_tx_time: data_len.max_tx_time,
+        max_rx_octets: data_len.max_rx_octets,
+        max_rx_time: data_len.max_rx_time,
+    };
+
+    {
+        let mut data_lens = DATA_LENS.lock();
+        match data_lens.iter_mut().find(|x| x.0 == data_len.conn_handle) {
+            Some(x) => x.1 = change,
+            None => data_lens.push((data_len.conn_handle, change)),
+        }
+    }
+
+    DATA_LEN_WAITERS.complete(data_len.conn_handle, Ok(change));
+    (data_len.conn_handle, change)
+}
+
+/// Fail the waiting procedures of a closed connection.
+pub(crate) fn on_disconnect(conn_handle: u16) {
+    let err = BLEError::convert(sys::BLE_HS_ENOTCONN).unwrap_err();
+    PHY_WAITERS.complete(conn_handle, Err(err));
+    DATA_LEN_WAITERS.complete(conn_handle, Err(err));
+
+    #[cfg(not(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+    )))]
+    DATA_LENS.lock().retain(|x| x.0 != conn_handle);
+}
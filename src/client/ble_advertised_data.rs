@@ -2,7 +2,7 @@ use alloc::vec::Vec;
 use bstr::BStr;
 use esp_idf_svc::sys;
 
-use crate::{enums::AdvFlag, utilities::BleUuid};
+use crate::{beacon::BeaconFrame, enums::AdvFlag, utilities::BleUuid};
 
 pub struct BLEAdvertisedData<T>
 where
@@ -63,52 +63,63 @@ impl<T: AsRef<[u8]>> BLEAdvertisedData<T> {
     }
 
     pub fn service_data(&self) -> Option<BLEServiceData<'_>> {
-        for x in self.decode() {
-            match x.ty as u32 {
-                sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID16 => {
-                    if let Some((uuid, service_data)) = x.data.split_at_checked(2) {
-                        let uuid =
-                            BleUuid::from_uuid16(u16::from_le_bytes(uuid.try_into().unwrap()));
-                        return Some(BLEServiceData { uuid, service_data });
-                    } else {
-                        ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID16");
-                    }
+        self.service_data_list().next()
+    }
+
+    /// Iterate over all service data AD structures.
+    pub fn service_data_list(&self) -> impl Iterator<Item = BLEServiceData<'_>> + '_ {
+        self.decode().filter_map(|x| match x.ty as u32 {
+            sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID16 => {
+                if let Some((uuid, service_data)) = x.data.split_at_checked(2) {
+                    let uuid = BleUuid::from_uuid16(u16::from_le_bytes(uuid.try_into().unwrap()));
+                    Some(BLEServiceData { uuid, service_data })
+                } else {
+                    ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID16");
+                    None
                 }
-                sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID32 => {
-                    if let Some((uuid, service_data)) = x.data.split_at_checked(4) {
-                        let uuid =
-                            BleUuid::from_uuid32(u32::from_le_bytes(uuid.try_into().unwrap()));
-                        return Some(BLEServiceData { uuid, service_data });
-                    } else {
-                        ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID32");
-                    }
+            }
+            sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID32 => {
+                if let Some((uuid, service_data)) = x.data.split_at_checked(4) {
+                    let uuid = BleUuid::from_uuid32(u32::from_le_bytes(uuid.try_into().unwrap()));
+                    Some(BLEServiceData { uuid, service_data })
+                } else {
+                    ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID32");
+                    None
                 }
-                sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID128 => {
-                    if let Some((uuid, service_data)) = x.data.split_at_checked(16) {
-                        let uuid = BleUuid::from_uuid128(uuid.try_into().unwrap());
-                        return Some(BLEServiceData { uuid, service_data });
-                    } else {
-                        ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID128");
-                    }
+            }
+            sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID128 => {
+                if let Some((uuid, service_data)) = x.data.split_at_checked(16) {
+                    let uuid = BleUuid::from_uuid128(uuid.try_into().unwrap());
+                    Some(BLEServiceData { uuid, service_data })
+                } else {
+                    ::log::error!("Length too small for BLE_HS_ADV_TYPE_SVC_DATA_UUID128");
+                    None
                 }
-                _ => {}
             }
-        }
-        None
+            _ => None,
+        })
     }
 
     pub fn manufacture_data(&self) -> Option<ManufactureData<'_>> {
-        let data = self
-            .decode()
-            .find(|x| x.ty == (sys::BLE_HS_ADV_TYPE_MFG_DATA as _))?;
-
-        let (id, payload) = data.data.split_at_checked(2)?;
+        let (id, payload) = self.manufacture_data_raw()?.split_at_checked(2)?;
         Some(ManufactureData {
             company_identifier: u16::from_le_bytes(id.try_into().unwrap()),
             payload,
         })
     }
 
+    /// Manufacturer specific data including the company identifier.
+    pub(crate) fn manufacture_data_raw(&self) -> Option<&[u8]> {
+        self.decode()
+            .find(|x| x.ty == (sys::BLE_HS_ADV_TYPE_MFG_DATA as _))
+            .map(|x| x.data)
+    }
+
+    /// Classify and decode a known beacon format.
+    pub fn beacon(&self) -> Option<BeaconFrame> {
+        BeaconFrame::decode(self)
+    }
+
     fn decode(&self) -> AdStructureIter<'_> {
         AdStructureIter {
             payload: self.payload(),
@@ -39,6 +39,27 @@ impl BLEAdvertisedDevice {
         self.0.rssi
     }
 
+    /// Get the data status of the advertisement.
+    ///
+    /// When the scan reassembles chained extended advertisements,
+    /// this is either `Complete` or `Truncated`.
+    pub fn data_status(&self) -> DataStatus {
+        #[cfg(esp_idf_bt_nimble_ext_adv)]
+        {
+            DataStatus::try_from(self.0.data_status).unwrap_or(DataStatus::Truncated)
+        }
+
+        #[cfg(not(esp_idf_bt_nimble_ext_adv))]
+        {
+            DataStatus::Complete
+        }
+    }
+
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub(crate) fn set_data_status(&mut self, status: DataStatus) {
+        self.0.data_status = status as _;
+    }
+
     #[cfg(esp_idf_bt_nimble_ext_adv)]
     /// Get the set ID of the extended advertisement.
     pub fn sid(&self) -> u8 {
@@ -72,6 +93,7 @@ impl core::fmt::Debug for BLEAdvertisedDevice {
                 .field("addr", &self.addr())
                 .field("adv_type", &self.adv_type())
                 .field("rssi", &self.rssi())
+                .field("data_status", &self.data_status())
                 .field("sid", &self.sid())
                 .field("prim_phy", &self.prim_phy())
                 .field("sec_phy", &self.sec_phy())
@@ -1,6 +1,10 @@
+use super::{BLEMultiReader, ReliableWrite};
 use crate::{
-    BLEAddress, BLEConnDesc, BLEDevice, BLEError, BLERemoteService, Signal, ble,
+    BLEAddress, BLEConnDesc, BLEDevice, BLEError, BLERemoteService, BLEScan, BLEScanFilter,
+    DataLengthChange, PhyUpdate, Signal, ble,
     ble_device::OWN_ADDR_TYPE,
+    ble_phy,
+    enums::{CodedPhyOption, PhyMask, SecPhy},
     utilities::{ArcUnsafeCell, BleUuid, as_void_ptr, voidp_to_ref},
 };
 use alloc::{boxed::Box, vec::Vec};
@@ -13,13 +17,21 @@ pub(crate) struct BLEClientState {
     address: Option<BLEAddress>,
     pub(crate) conn_handle: u16,
     services: Option<Vec<BLERemoteService>>,
+    all_services_discovered: bool,
     signal: Signal<u32>,
+    connecting: bool,
     connect_timeout_ms: u32,
+    /// Keeps the state of a client dropped while connecting alive until the connect event.
+    keep_alive: Option<ArcUnsafeCell<BLEClientState>>,
     ble_gap_conn_params: ble_gap_conn_params,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    phy_conn_params: [ble_gap_conn_params; 3],
     on_passkey_request: Option<Box<dyn Fn() -> u32 + Send + Sync>>,
     on_confirm_pin: Option<Box<dyn Fn(u32) -> bool + Send + Sync>>,
     on_connect: Option<Box<dyn Fn(&mut BLEClient) + Send + Sync>>,
     on_disconnect: Option<Box<dyn Fn(i32) + Send + Sync>>,
+    on_phy_update: Option<Box<dyn Fn(Result<PhyUpdate, BLEError>) + Send + Sync>>,
+    on_data_len_change: Option<Box<dyn Fn(&DataLengthChange) + Send + Sync>>,
 }
 
 pub struct BLEClient {
@@ -28,27 +40,36 @@ pub struct BLEClient {
 
 impl BLEClient {
     pub(crate) fn new() -> Self {
+        let ble_gap_conn_params = ble_gap_conn_params {
+            scan_itvl: 16,
+            scan_window: 16,
+            itvl_min: (30 * 1000 / BLE_HCI_CONN_ITVL) as _,
+            itvl_max: (50 * 1000 / BLE_HCI_CONN_ITVL) as _,
+            latency: BLE_GAP_INITIAL_CONN_LATENCY as _,
+            supervision_timeout: BLE_GAP_INITIAL_SUPERVISION_TIMEOUT as _,
+            min_ce_len: BLE_GAP_INITIAL_CONN_MIN_CE_LEN as _,
+            max_ce_len: BLE_GAP_INITIAL_CONN_MAX_CE_LEN as _,
+        };
+
         Self {
             state: ArcUnsafeCell::new(BLEClientState {
                 address: None,
                 conn_handle: esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _,
                 services: None,
+                all_services_discovered: false,
+                connecting: false,
                 connect_timeout_ms: 30000,
-                ble_gap_conn_params: ble_gap_conn_params {
-                    scan_itvl: 16,
-                    scan_window: 16,
-                    itvl_min: (30 * 1000 / BLE_HCI_CONN_ITVL) as _,
-                    itvl_max: (50 * 1000 / BLE_HCI_CONN_ITVL) as _,
-                    latency: BLE_GAP_INITIAL_CONN_LATENCY as _,
-                    supervision_timeout: BLE_GAP_INITIAL_SUPERVISION_TIMEOUT as _,
-                    min_ce_len: BLE_GAP_INITIAL_CONN_MIN_CE_LEN as _,
-                    max_ce_len: BLE_GAP_INITIAL_CONN_MAX_CE_LEN as _,
-                },
+                keep_alive: None,
+                ble_gap_conn_params,
+                #[cfg(esp_idf_bt_nimble_ext_adv)]
+                phy_conn_params: [ble_gap_conn_params; 3],
                 signal: Signal::new(),
                 on_passkey_request: None,
                 on_confirm_pin: None,
                 on_disconnect: None,
                 on_connect: None,
+                on_phy_update: None,
+                on_data_len_change: None,
             }),
         }
     }
@@ -86,24 +107,155 @@ impl BLEClient {
         self
     }
 
-    pub async fn connect(&mut self, addr: &BLEAddress) -> Result<(), BLEError> {
+    /// The `arg` of [`BLEClient::handle_gap_event`] for this client.
+    pub(crate) fn gap_event_arg(&mut self) -> *mut c_void {
+        unsafe { as_void_ptr(&mut *self.state) }
+    }
+
+    /// Deliver the GAP events of the connection to this client again.
+    pub(crate) fn restore_gap_event_cb(&mut self) -> Result<(), BLEError> {
         unsafe {
-            if esp_idf_sys::ble_gap_conn_find_by_addr(&addr.value, core::ptr::null_mut()) == 0 {
-                ::log::warn!("A connection to {addr:?} already exists");
-                return BLEError::fail();
-            }
+            ble!(esp_idf_sys::ble_gap_set_event_cb(
+                self.conn_handle(),
+                Some(Self::handle_gap_event),
+                self.gap_event_arg(),
+            ))
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn take_on_disconnect(&mut self) -> Option<Box<dyn Fn(i32) + Send + Sync>> {
+        self.state.on_disconnect.take()
+    }
+
+    /// Handle the completion of a PHY update procedure, started by either side.
+    pub fn on_phy_update(
+        &mut self,
+        callback: impl Fn(Result<PhyUpdate, BLEError>) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.state.on_phy_update = Some(Box::new(callback));
+        self
+    }
 
+    /// Handle a change of the maximum data length of the connection.
+    pub fn on_data_len_change(
+        &mut self,
+        callback: impl Fn(&DataLengthChange) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.state.on_data_len_change = Some(Box::new(callback));
+        self
+    }
+
+    /// Connect to the peer.
+    ///
+    /// If the returned future is dropped before the connection is established,
+    /// the pending connection attempt is cancelled.
+    /// If it is dropped or fails after that, before the MTU exchange has completed,
+    /// the connection is terminated.
+    pub async fn connect(&mut self, addr: &BLEAddress) -> Result<(), BLEError> {
+        self.prepare_connect(addr)?;
+
+        let rc = unsafe {
             ble!(esp_idf_sys::ble_gap_connect(
                 OWN_ADDR_TYPE as _,
                 &addr.value,
                 self.state.connect_timeout_ms as _,
                 &self.state.ble_gap_conn_params,
                 Some(Self::handle_gap_event),
-                as_void_ptr(self),
-            ))?;
+                as_void_ptr(&mut *self.state),
+            ))
+        };
+
+        self.wait_connected(addr, rc).await
+    }
+
+    /// Connect to the peer using the extended connection procedure.
+    ///
+    /// * `phys`: The PHYs to initiate the connection on.
+    ///   The parameters of each PHY are set with [`BLEClient::set_phy_connection_params`].
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub async fn connect_ext(
+        &mut self,
+        addr: &BLEAddress,
+        phys: crate::enums::PhyMask,
+    ) -> Result<(), BLEError> {
+        self.prepare_connect(addr)?;
+
+        let params = &self.state.phy_conn_params;
+        let rc = unsafe {
+            ble!(esp_idf_sys::ble_gap_ext_connect(
+                OWN_ADDR_TYPE as _,
+                &addr.value,
+                self.state.connect_timeout_ms as _,
+                phys.bits(),
+                &params[0],
+                &params[1],
+                &params[2],
+                Some(Self::handle_gap_event),
+                as_void_ptr(&mut *self.state),
+            ))
+        };
+
+        self.wait_connected(addr, rc).await
+    }
+
+    /// Scan for the first device matching the filter and connect to it.
+    ///
+    /// Returns `BLE_HS_ENOENT` if no device matched within `duration_ms`.
+    pub async fn connect_by_filter(
+        ble_device: &BLEDevice,
+        ble_scan: &mut BLEScan,
+        filter: &BLEScanFilter,
+        duration_ms: i32,
+    ) -> Result<Self, BLEError> {
+        let addr = ble_scan
+            .start(ble_device, duration_ms, |device, data| {
+                filter.matches(device, &data).then(|| device.addr())
+            })
+            .await?;
+        let Some(addr) = addr else {
+            return Err(BLEError::convert(esp_idf_sys::BLE_HS_ENOENT).unwrap_err());
+        };
+
+        let mut client = ble_device.new_client();
+        client.connect(&addr).await?;
+        Ok(client)
+    }
+
+    fn prepare_connect(&mut self, addr: &BLEAddress) -> Result<(), BLEError> {
+        if unsafe { esp_idf_sys::ble_gap_conn_find_by_addr(&addr.value, core::ptr::null_mut()) }
+            == 0
+        {
+            ::log::warn!("A connection to {addr:?} already exists");
+            return BLEError::fail();
+        }
+
+        if self.state.connecting {
+            return BLEError::convert(esp_idf_sys::BLE_HS_EBUSY);
         }
 
+        self.state.signal.reset();
+        // Set before starting the procedure, the event callback may run before it returns.
+        self.state.connecting = true;
+        Ok(())
+    }
+
+    async fn wait_connected(
+        &mut self,
+        addr: &BLEAddress,
+        rc: Result<(), BLEError>,
+    ) -> Result<(), BLEError> {
+        if rc.is_err() {
+            self.state.connecting = false;
+            return rc;
+        }
+        let mut guard = PendingConnect {
+            state: self.state.clone(),
+            completed: false,
+        };
+
         ble!(self.state.signal.wait().await)?;
+        guard.completed = true;
         self.state.address = Some(*addr);
 
         let mut client = UnsafeCell::new(self);
@@ -116,6 +268,22 @@ impl BLEClient {
         Ok(())
     }
 
+    /// Cancel a pending connection attempt.
+    pub fn cancel_connect(&mut self) -> Result<(), BLEError> {
+        if !self.state.connecting {
+            return Ok(());
+        }
+
+        unsafe { ble!(esp_idf_sys::ble_gap_conn_cancel()) }
+    }
+
+    /// Set the timeout of a connection attempt in milliseconds.
+    /// Default is 30000ms.
+    pub fn set_connect_timeout(&mut self, timeout_ms: u32) -> &mut Self {
+        self.state.connect_timeout_ms = timeout_ms;
+        self
+    }
+
     pub async fn secure_connection(&mut self) -> Result<(), BLEError> {
         unsafe {
             ble!(esp_idf_sys::ble_gap_security_initiate(
@@ -177,6 +345,44 @@ impl BLEClient {
         self.state.ble_gap_conn_params.supervision_timeout = timeout;
     }
 
+    /// Set the connection parameters of a PHY used by [`BLEClient::connect_ext`].
+    ///
+    /// * `phys`: The PHYs the parameters are used on.
+    /// * `min_interval`: The minimum connection interval in 1.25ms units.
+    /// * `max_interval`: The maximum connection interval in 1.25ms units.
+    /// * `latency`: The number of packets allowed to skip (extends max interval).
+    /// * `timeout`: The timeout time in 10ms units before disconnecting.
+    /// * `scan_interval`: The scan interval to use when attempting to connect in 0.625ms units.
+    /// * `scan_window`: The scan window to use when attempting to connect in 0.625ms units.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_phy_connection_params(
+        &mut self,
+        phys: PhyMask,
+        min_interval: u16,
+        max_interval: u16,
+        latency: u16,
+        timeout: u16,
+        scan_interval: u16,
+        scan_window: u16,
+    ) {
+        // The parameters are passed to `ble_gap_ext_connect` in this order.
+        for (phy, params) in [PhyMask::Phy1M, PhyMask::Phy2M, PhyMask::Coded]
+            .into_iter()
+            .zip(self.state.phy_conn_params.iter_mut())
+        {
+            if !phys.contains(phy) {
+                continue;
+            }
+            params.scan_itvl = scan_interval;
+            params.scan_window = scan_window;
+            params.itvl_min = min_interval;
+            params.itvl_max = max_interval;
+            params.latency = latency;
+            params.supervision_timeout = timeout;
+        }
+    }
+
     /// Request an Update the connection parameters:
     /// Can only be used after a connection has been established.
     ///
@@ -225,76 +431,240 @@ impl BLEClient {
         Ok(rssi)
     }
 
+    /// Gets the current (TX, RX) PHY of the connection.
+    pub fn phy(&self) -> Result<(SecPhy, SecPhy), BLEError> {
+        ble_phy::phy(self.conn_handle())
+    }
+
+    /// Sets the preferred PHYs of the connection and starts a PHY update procedure.
+    /// The result is reported by `on_phy_update`.
+    pub fn set_preferred_phy(
+        &self,
+        tx_phys: PhyMask,
+        rx_phys: PhyMask,
+        coded: CodedPhyOption,
+    ) -> Result<(), BLEError> {
+        ble_phy::set_preferred_phy(self.conn_handle(), tx_phys, rx_phys, coded)
+    }
+
+    /// Requests a PHY update and waits for its completion.
+    /// Returns the current PHYs right away if the masks already allow them.
+    ///
+    /// Fails with `BLE_HS_ETIMEOUT` if the controller does not report the completion
+    /// within `timeout_ms`, for example because the PHYs do not change.
+    pub async fn update_phy(
+        &self,
+        tx_phys: PhyMask,
+        rx_phys: PhyMask,
+        coded: CodedPhyOption,
+        timeout_ms: u32,
+    ) -> Result<PhyUpdate, BLEError> {
+        ble_phy::update_phy(self.conn_handle(), tx_phys, rx_phys, coded, timeout_ms).await
+    }
+
+    /// Requests the data length extension for the connection.
+    ///
+    /// * `tx_octets`: The preferred maximum payload size (27 - 251 bytes).
+    /// * `tx_time`: The preferred maximum transmission time (328 - 17040 microseconds).
+    pub fn set_data_len(&self, tx_octets: u16, tx_time: u16) -> Result<(), BLEError> {
+        ble_phy::set_data_len(self.conn_handle(), tx_octets, tx_time)
+    }
+
+    /// Requests the data length extension and waits until the controller reports the new values.
+    /// Returns the current values right away if they already are at least the requested ones.
+    #[cfg(not(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+    )))]
+    pub async fn update_data_len(
+        &self,
+        tx_octets: u16,
+        tx_time: u16,
+        timeout_ms: u32,
+    ) -> Result<DataLengthChange, BLEError> {
+        ble_phy::update_data_len(self.conn_handle(), tx_octets, tx_time, timeout_ms).await
+    }
+
     pub async fn get_services(
         &mut self,
     ) -> Result<core::slice::IterMut<'_, BLERemoteService>, BLEError> {
-        if self.state.services.is_none() {
-            self.state.services = Some(Vec::new());
+        if !self.state.all_services_discovered {
+            self.state.services.get_or_insert_with(Vec::new);
             unsafe {
-                esp_idf_sys::ble_gattc_disc_all_svcs(
+                ble!(esp_idf_sys::ble_gattc_disc_all_svcs(
                     self.state.conn_handle,
                     Some(Self::service_discovered_cb),
                     as_void_ptr(self),
-                );
+                ))?;
             }
             ble!(self.state.signal.wait().await)?;
+            self.state.all_services_discovered = true;
         }
 
         Ok(self.state.services.as_mut().unwrap().iter_mut())
     }
 
+    /// Get the service object for the UUID.
+    ///
+    /// If the full service list has not been discovered yet,
+    /// only the requested service is discovered ("Discover Primary Service by Service UUID").
     pub async fn get_service(&mut self, uuid: BleUuid) -> Result<&mut BLERemoteService, BLEError> {
-        let mut iter = self.get_services().await?;
-        iter.find(|x| x.uuid() == uuid)
+        let cached = self
+            .state
+            .services
+            .iter()
+            .flatten()
+            .any(|x| x.uuid() == uuid);
+
+        if !cached && !self.state.all_services_discovered {
+            self.state.services.get_or_insert_with(Vec::new);
+            let uuid_any = esp_idf_sys::ble_uuid_any_t::from(uuid);
+            unsafe {
+                ble!(esp_idf_sys::ble_gattc_disc_svc_by_uuid(
+                    self.state.conn_handle,
+                    &uuid_any.u,
+                    Some(Self::service_discovered_cb),
+                    as_void_ptr(self),
+                ))?;
+            }
+            ble!(self.state.signal.wait().await)?;
+        }
+
+        self.state
+            .services
+            .iter_mut()
+            .flatten()
+            .find(|x| x.uuid() == uuid)
             .ok_or_else(|| BLEError::fail().unwrap_err())
     }
 
-    extern "C" fn handle_gap_event(
+    /// Drop the discovered attributes so that they are discovered again.
+    pub(crate) fn clear_services(&mut self) {
+        self.state.services = None;
+        self.state.all_services_discovered = false;
+    }
+
+    /// Discover all services, characteristics and descriptors of the peer in one pass.
+    pub async fn discover_attributes(&mut self) -> Result<(), BLEError> {
+        for service in self.get_services().await? {
+            for characteristic in service.get_characteristics().await? {
+                characteristic.get_descriptors().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read several fixed length attributes with a single "Read Multiple" request.
+    ///
+    /// `attributes` is a list of `(handle, expected length)`.
+    /// Returns the `(handle, value)` pairs in request order.
+    pub async fn read_multiple(
+        &mut self,
+        attributes: &[(u16, usize)],
+    ) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let mut reader = BLEMultiReader::new(self.conn_handle());
+        reader.read_multiple(attributes).await
+    }
+
+    /// Read several attributes with a single "Read Multiple Variable Length" request.
+    ///
+    /// Returns the `(handle, value)` pairs in request order.
+    #[cfg(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "4", esp_idf_version_minor = "5")
+    ))]
+    pub async fn read_multiple_variable(
+        &mut self,
+        handles: &[u16],
+    ) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let mut reader = BLEMultiReader::new(self.conn_handle());
+        reader.read_multiple_variable(handles).await
+    }
+
+    /// Read the value of every characteristic with the given UUID ("Read Using Characteristic UUID").
+    ///
+    /// Returns the `(handle, value)` pairs without discovering the attributes first.
+    pub async fn read_by_uuid(&mut self, uuid: BleUuid) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let mut reader = BLEMultiReader::new(self.conn_handle());
+        reader.read_by_uuid(0x0001, 0xFFFF, uuid).await
+    }
+
+    /// Start a reliable write transaction.
+    ///
+    /// ```ignore
+    /// let mut transaction = client.reliable_write();
+    /// transaction.write_characteristic(&chr1, &[1, 2]).write_characteristic(&chr2, &[3]);
+    /// transaction.commit().await?;
+    /// ```
+    pub fn reliable_write(&self) -> ReliableWrite {
+        ReliableWrite::new(self.conn_handle())
+    }
+
+    pub(crate) extern "C" fn handle_gap_event(
         event: *mut esp_idf_sys::ble_gap_event,
         arg: *mut c_void,
     ) -> i32 {
         let event = unsafe { &*event };
-        let client = unsafe { voidp_to_ref::<Self>(arg) };
+        let state = unsafe { voidp_to_ref::<BLEClientState>(arg) };
 
         match event.type_ as _ {
             BLE_GAP_EVENT_CONNECT => {
                 let connect = unsafe { &event.__bindgen_anon_1.connect };
+                state.connecting = false;
+
+                if state.keep_alive.is_some() {
+                    // The client was dropped while connecting.
+                    if connect.status == 0 {
+                        unsafe {
+                            ble_gap_set_event_cb(connect.conn_handle, None, ptr::null_mut());
+                            ble_gap_terminate(
+                                connect.conn_handle,
+                                ble_error_codes_BLE_ERR_REM_USER_CONN_TERM as _,
+                            );
+                        }
+                    }
+                    // The state may be freed here, so it must not be used afterwards.
+                    drop(state.keep_alive.take());
+                    return 0;
+                }
 
                 if connect.status == 0 {
-                    client.state.conn_handle = connect.conn_handle;
+                    state.conn_handle = connect.conn_handle;
 
                     let rc = unsafe {
                         ble_gattc_exchange_mtu(connect.conn_handle, None, core::ptr::null_mut())
                     };
 
                     if rc != 0 {
-                        client.state.signal.signal(rc as _);
+                        state.signal.signal(rc as _);
                     }
                 } else {
                     ::log::info!("connect_status {}", connect.status);
-                    client.state.conn_handle = esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _;
-                    client.state.signal.signal(connect.status as _);
+                    state.conn_handle = esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _;
+                    state.signal.signal(connect.status as _);
                 }
             }
             BLE_GAP_EVENT_DISCONNECT => {
                 let disconnect = unsafe { &event.__bindgen_anon_1.disconnect };
-                if client.state.conn_handle != disconnect.conn.conn_handle {
+                if state.conn_handle != disconnect.conn.conn_handle {
                     return 0;
                 }
-                client.state.conn_handle = esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _;
+                state.conn_handle = esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _;
+                ble_phy::on_disconnect(disconnect.conn.conn_handle);
 
                 ::log::info!(
                     "Disconnected: {:?}",
                     BLEError::convert(disconnect.reason as _)
                 );
 
-                if let Some(callback) = &client.state.on_disconnect {
+                if let Some(callback) = &state.on_disconnect {
                     callback(disconnect.reason);
                 }
             }
             BLE_GAP_EVENT_ENC_CHANGE => {
                 let enc_change = unsafe { &event.__bindgen_anon_1.enc_change };
-                if client.state.conn_handle != enc_change.conn_handle {
+                if state.conn_handle != enc_change.conn_handle {
                     return 0;
                 }
 
@@ -305,11 +675,11 @@ impl BLEClient {
                     unsafe { esp_idf_sys::ble_store_util_delete_peer(&desc.0.peer_id_addr) };
                 }
 
-                client.state.signal.signal(enc_change.status as _);
+                state.signal.signal(enc_change.status as _);
             }
             BLE_GAP_EVENT_MTU => {
                 let mtu = unsafe { &event.__bindgen_anon_1.mtu };
-                if client.state.conn_handle != mtu.conn_handle {
+                if state.conn_handle != mtu.conn_handle {
                     return 0;
                 }
                 ::log::info!(
@@ -317,15 +687,15 @@ impl BLEClient {
                     mtu.conn_handle,
                     mtu.value
                 );
-                client.state.signal.signal(0);
+                state.signal.signal(0);
             }
             BLE_GAP_EVENT_NOTIFY_RX => {
                 let notify_rx = unsafe { &event.__bindgen_anon_1.notify_rx };
-                if client.state.conn_handle != notify_rx.conn_handle {
+                if state.conn_handle != notify_rx.conn_handle {
                     return 0;
                 }
 
-                if let Some(services) = &mut client.state.services {
+                if let Some(services) = &mut state.services {
                     for service in services {
                         if service.state.end_handle < notify_rx.attr_handle {
                             continue;
@@ -346,7 +716,7 @@ impl BLEClient {
             }
             BLE_GAP_EVENT_CONN_UPDATE_REQ | BLE_GAP_EVENT_L2CAP_UPDATE_REQ => {
                 let conn_update_req = unsafe { &event.__bindgen_anon_1.conn_update_req };
-                if client.state.conn_handle != conn_update_req.conn_handle {
+                if state.conn_handle != conn_update_req.conn_handle {
                     return 0;
                 }
                 unsafe {
@@ -362,7 +732,7 @@ impl BLEClient {
             }
             BLE_GAP_EVENT_PASSKEY_ACTION => {
                 let passkey = unsafe { &event.__bindgen_anon_1.passkey };
-                if client.state.conn_handle != passkey.conn_handle {
+                if state.conn_handle != passkey.conn_handle {
                     return 0;
                 }
                 let mut pkey = esp_idf_sys::ble_sm_io {
@@ -378,7 +748,7 @@ impl BLEClient {
                         ::log::debug!("BLE_SM_IOACT_DISP; ble_sm_inject_io result: {rc}");
                     }
                     esp_idf_sys::BLE_SM_IOACT_NUMCMP => {
-                        if let Some(callback) = &client.state.on_confirm_pin {
+                        if let Some(callback) = &state.on_confirm_pin {
                             pkey.__bindgen_anon_1.numcmp_accept =
                                 callback(passkey.params.numcmp) as _;
                         } else {
@@ -390,7 +760,7 @@ impl BLEClient {
                         ::log::debug!("BLE_SM_IOACT_NUMCMP; ble_sm_inject_io result: {rc}");
                     }
                     esp_idf_sys::BLE_SM_IOACT_INPUT => {
-                        if let Some(callback) = &client.state.on_passkey_request {
+                        if let Some(callback) = &state.on_passkey_request {
                             pkey.__bindgen_anon_1.passkey = callback();
                         } else {
                             ::log::warn!("on_passkey_request is not setted");
@@ -408,6 +778,28 @@ impl BLEClient {
                     }
                 }
             }
+            BLE_GAP_EVENT_PHY_UPDATE_COMPLETE => {
+                let (conn_handle, result) = ble_phy::on_phy_update(event);
+                if state.conn_handle != conn_handle {
+                    return 0;
+                }
+                if let Some(callback) = &state.on_phy_update {
+                    callback(result);
+                }
+            }
+            #[cfg(not(all(
+                esp_idf_version_major = "5",
+                any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+            )))]
+            BLE_GAP_EVENT_DATA_LEN_CHG => {
+                let (conn_handle, change) = ble_phy::on_data_len_change(event);
+                if state.conn_handle != conn_handle {
+                    return 0;
+                }
+                if let Some(callback) = &state.on_data_len_change {
+                    callback(&change);
+                }
+            }
             _ => {
                 ::log::warn!("unhandled event: {}", event.type_);
             }
@@ -431,8 +823,15 @@ impl BLEClient {
         if error.status == 0 {
             let service = unsafe { &*service };
             // Found a service - add it to the vector
-            let service = BLERemoteService::new(ArcUnsafeCell::downgrade(&client.state), service);
-            client.state.services.as_mut().unwrap().push(service);
+            let services = client.state.services.as_mut().unwrap();
+            if !services
+                .iter()
+                .any(|x| x.state.start_handle == service.start_handle)
+            {
+                let service =
+                    BLERemoteService::new(ArcUnsafeCell::downgrade(&client.state), service);
+                client.state.services.as_mut().unwrap().push(service);
+            }
             return 0;
         }
 
@@ -453,6 +852,37 @@ impl Drop for BLEClient {
             unsafe {
                 esp_idf_sys::ble_gap_set_event_cb(self.conn_handle(), None, ptr::null_mut());
             }
+        } else if self.state.connecting {
+            // The cancelled connection is still reported to the event callback,
+            // which releases the state.
+            self.state.keep_alive = Some(self.state.clone());
+            unsafe { esp_idf_sys::ble_gap_conn_cancel() };
+        }
+    }
+}
+
+/// Cancels the connection attempt if the connect future is dropped or fails,
+/// and terminates the connection if it is established but the MTU exchange has not completed.
+struct PendingConnect {
+    state: ArcUnsafeCell<BLEClientState>,
+    completed: bool,
+}
+
+impl Drop for PendingConnect {
+    fn drop(&mut self) {
+        if self.state.connecting {
+            ::log::debug!("connect cancelled");
+            unsafe { esp_idf_sys::ble_gap_conn_cancel() };
+        } else if !self.completed
+            && self.state.conn_handle != (esp_idf_sys::BLE_HS_CONN_HANDLE_NONE as _)
+        {
+            ::log::debug!("connect cancelled during the MTU exchange");
+            unsafe {
+                esp_idf_sys::ble_gap_terminate(
+                    self.state.conn_handle,
+                    esp_idf_sys::ble_error_codes_BLE_ERR_REM_USER_CONN_TERM as _,
+                )
+            };
         }
     }
 }
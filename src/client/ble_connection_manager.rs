@@ -0,0 +1,315 @@
+use crate::{BLEAddress, BLEClient, BLEError, ble, utilities::mutex::Mutex};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::ffi::c_void;
+use esp_idf_svc::sys as esp_idf_sys;
+
+const MAX_CONNECTIONS: usize = esp_idf_sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as _;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ConnectionStats {
+    /// Number of connected clients.
+    pub connected: usize,
+    /// Number of queued connect requests.
+    pub queued: usize,
+    pub max_connections: usize,
+    pub connect_attempts: u32,
+    pub connect_failures: u32,
+    pub disconnects: u32,
+}
+
+#[derive(Default)]
+struct Counters {
+    connect_attempts: u32,
+    connect_failures: u32,
+    disconnects: u32,
+}
+
+type DisconnectCallback = Arc<dyn Fn(&BLEAddress, i32) + Send + Sync>;
+
+struct Route {
+    conn_handle: u16,
+    addr: BLEAddress,
+    /// The `arg` of [`BLEClient::handle_gap_event`] for the client of the connection.
+    arg: *mut c_void,
+}
+
+// The client state the pointer refers to is owned by the manager.
+unsafe impl Send for Route {}
+
+/// Delivers the GAP events of the managed connections to their clients.
+struct Router {
+    routes: Mutex<Vec<Route>>,
+    counters: Mutex<Counters>,
+    on_disconnect: Mutex<Option<DisconnectCallback>>,
+}
+
+impl Router {
+    fn new() -> Self {
+        Self {
+            routes: Mutex::new(Vec::new()),
+            counters: Mutex::new(Counters::default()),
+            on_disconnect: Mutex::new(None),
+        }
+    }
+
+    extern "C" fn handle_gap_event(
+        event: *mut esp_idf_sys::ble_gap_event,
+        arg: *mut c_void,
+    ) -> i32 {
+        let router = unsafe { &*(arg as *const Self) };
+        let Some(conn_handle) = event_conn_handle(unsafe { &*event }) else {
+            ::log::debug!("unrouted event: {}", unsafe { (*event).type_ });
+            return 0;
+        };
+
+        let route = router
+            .routes
+            .lock()
+            .iter()
+            .find(|x| x.conn_handle == conn_handle)
+            .map(|x| (x.addr, x.arg));
+        let Some((addr, client)) = route else {
+            return 0;
+        };
+
+        let rc = BLEClient::handle_gap_event(event, client);
+
+        let event = unsafe { &*event };
+        if event.type_ == esp_idf_sys::BLE_GAP_EVENT_DISCONNECT as _ {
+            router
+                .routes
+                .lock()
+                .retain(|x| x.conn_handle != conn_handle);
+            router.counters.lock().disconnects += 1;
+
+            let reason = unsafe { event.__bindgen_anon_1.disconnect.reason };
+            // Not called with the lock held, the callback may use the manager.
+            let callback = router.on_disconnect.lock().clone();
+            if let Some(callback) = callback {
+                callback(&addr, reason);
+            }
+        }
+        rc
+    }
+}
+
+fn event_conn_handle(event: &esp_idf_sys::ble_gap_event) -> Option<u16> {
+    let event_data = &event.__bindgen_anon_1;
+    let conn_handle = unsafe {
+        match event.type_ as _ {
+            esp_idf_sys::BLE_GAP_EVENT_DISCONNECT => event_data.disconnect.conn.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_CONN_UPDATE => event_data.conn_update.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_CONN_UPDATE_REQ
+            | esp_idf_sys::BLE_GAP_EVENT_L2CAP_UPDATE_REQ => event_data.conn_update_req.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_ENC_CHANGE => event_data.enc_change.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_PASSKEY_ACTION => event_data.passkey.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_NOTIFY_RX => event_data.notify_rx.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_MTU => event_data.mtu.conn_handle,
+            esp_idf_sys::BLE_GAP_EVENT_PHY_UPDATE_COMPLETE => event_data.phy_updated.conn_handle,
+            #[cfg(not(all(
+                esp_idf_version_major = "5",
+                any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+            )))]
+            esp_idf_sys::BLE_GAP_EVENT_DATA_LEN_CHG => event_data.data_len_chg.conn_handle,
+            _ => return None,
+        }
+    };
+    Some(conn_handle)
+}
+
+/// Manages the connections of several [`BLEClient`]s.
+///
+/// Connect requests are queued and started one at a time,
+/// because the controller can only have a single pending connection.
+/// The number of simultaneous connections is limited by `max_connections`.
+/// The GAP events of the connections are routed to their clients by conn handle.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut manager = ConnectionManager::new(8);
+/// for addr in sensors {
+///   manager.request_connect(addr);
+/// }
+/// for (addr, result) in manager.process_queue().await {
+///   ::log::info!("{addr}: {result:?}");
+/// }
+/// ```
+pub struct ConnectionManager {
+    max_connections: usize,
+    clients: Vec<(BLEAddress, BLEClient)>,
+    queue: VecDeque<BLEAddress>,
+    // Dropped after the clients, which unregister the event callbacks of their connections.
+    router: Arc<Router>,
+}
+
+impl ConnectionManager {
+    /// `max_connections` is capped at `CONFIG_BT_NIMBLE_MAX_CONNECTIONS`.
+    pub fn new(max_connections: usize) -> Self {
+        Self {
+            max_connections: max_connections.min(MAX_CONNECTIONS),
+            clients: Vec::new(),
+            queue: VecDeque::new(),
+            router: Arc::new(Router::new()),
+        }
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Called after the `on_disconnect` callback of the client.
+    pub fn on_disconnect(
+        &mut self,
+        callback: impl Fn(&BLEAddress, i32) + Send + Sync + 'static,
+    ) -> &mut Self {
+        *self.router.on_disconnect.lock() = Some(Arc::new(callback));
+        self
+    }
+
+    /// Queue a connect request. Use [`ConnectionManager::process_queue`] to connect.
+    pub fn request_connect(&mut self, addr: BLEAddress) -> &mut Self {
+        if !self.queue.contains(&addr) {
+            self.queue.push_back(addr);
+        }
+        self
+    }
+
+    /// Connect to the queued peers in request order.
+    ///
+    /// Requests which do not fit in the connection budget stay in the queue.
+    /// Failed requests are reported in the result and queued again for the next call,
+    /// use [`ConnectionManager::remove`] to give up on a peer.
+    pub async fn process_queue(&mut self) -> Vec<(BLEAddress, Result<(), BLEError>)> {
+        let mut results = Vec::new();
+
+        for _ in 0..self.queue.len() {
+            if self.connected_count() >= self.max_connections {
+                break;
+            }
+            let Some(addr) = self.queue.pop_front() else {
+                break;
+            };
+
+            let result = self.connect(&addr).await.map(|_| ());
+            if result.is_err() {
+                self.queue.push_back(addr);
+            }
+            results.push((addr, result));
+        }
+
+        results
+    }
+
+    /// Connect to the peer, reusing the client of a previous connection to the same address.
+    pub async fn connect(&mut self, addr: &BLEAddress) -> Result<&mut BLEClient, BLEError> {
+        let idx = match self.clients.iter().position(|x| x.0 == *addr) {
+            Some(idx) if self.clients[idx].1.connected() => return Ok(&mut self.clients[idx].1),
+            Some(idx) => idx,
+            None => {
+                self.clients.push((*addr, BLEClient::new()));
+                self.clients.len() - 1
+            }
+        };
+
+        if self.connected_count() >= self.max_connections {
+            return Err(BLEError::convert(esp_idf_sys::BLE_HS_ENOMEM).unwrap_err());
+        }
+
+        self.router.counters.lock().connect_attempts += 1;
+        let client = &mut self.clients[idx].1;
+        if let Err(err) = client.connect(addr).await {
+            self.router.counters.lock().connect_failures += 1;
+            return Err(err);
+        }
+
+        let conn_handle = client.conn_handle();
+        self.router.routes.lock().push(Route {
+            conn_handle,
+            addr: *addr,
+            arg: client.gap_event_arg(),
+        });
+        let rc = unsafe {
+            ble!(esp_idf_sys::ble_gap_set_event_cb(
+                conn_handle,
+                Some(Router::handle_gap_event),
+                Arc::as_ptr(&self.router) as *mut c_void,
+            ))
+        };
+        if let Err(err) = rc {
+            self.router
+                .routes
+                .lock()
+                .retain(|x| x.conn_handle != conn_handle);
+            return Err(err);
+        }
+
+        Ok(client)
+    }
+
+    /// Disconnect from the peer.
+    pub fn disconnect(&mut self, addr: &BLEAddress) -> Result<(), BLEError> {
+        match self.get_by_address(addr) {
+            Some(client) => client.disconnect(),
+            None => BLEError::convert(esp_idf_sys::BLE_HS_ENOTCONN),
+        }
+    }
+
+    /// Remove the client of the peer from the manager.
+    /// The connection is kept open, call `disconnect` on the returned client to close it.
+    pub fn remove(&mut self, addr: &BLEAddress) -> Option<BLEClient> {
+        self.queue.retain(|x| x != addr);
+        let idx = self.clients.iter().position(|x| x.0 == *addr)?;
+        let mut client = self.clients.swap_remove(idx).1;
+
+        if client.connected() {
+            let conn_handle = client.conn_handle();
+            self.router
+                .routes
+                .lock()
+                .retain(|x| x.conn_handle != conn_handle);
+            if let Err(err) = client.restore_gap_event_cb() {
+                ::log::warn!("can't restore the event callback: {err:?}");
+            }
+        }
+        Some(client)
+    }
+
+    pub fn get_by_address(&mut self, addr: &BLEAddress) -> Option<&mut BLEClient> {
+        self.clients
+            .iter_mut()
+            .find(|x| x.0 == *addr)
+            .map(|x| &mut x.1)
+    }
+
+    pub fn get_by_conn_handle(&mut self, conn_handle: u16) -> Option<&mut BLEClient> {
+        self.clients
+            .iter_mut()
+            .map(|x| &mut x.1)
+            .find(|x| x.connected() && x.conn_handle() == conn_handle)
+    }
+
+    /// Iterate over the connected clients.
+    pub fn connected_clients(&mut self) -> impl Iterator<Item = (&BLEAddress, &mut BLEClient)> {
+        self.clients
+            .iter_mut()
+            .filter(|x| x.1.connected())
+            .map(|(addr, client)| (&*addr, client))
+    }
+
+    pub fn connected_count(&self) -> usize {
+        self.clients.iter().filter(|x| x.1.connected()).count()
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let counters = self.router.counters.lock();
+        ConnectionStats {
+            connected: self.connected_count(),
+            queued: self.queue.len(),
+            max_connections: self.max_connections,
+            connect_attempts: counters.connect_attempts,
+            connect_failures: counters.connect_failures,
+            disconnects: counters.disconnects,
+        }
+    }
+}
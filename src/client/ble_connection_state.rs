@@ -0,0 +1,265 @@
+use crate::utilities::mutex::Mutex;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Poll, Waker},
+};
+
+/// Backoff used between reconnection attempts.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnection attempt in milliseconds.
+    pub initial_delay_ms: u32,
+    /// Upper bound of the delay in milliseconds.
+    pub max_delay_ms: u32,
+    /// Factor the delay is multiplied by after each failed attempt.
+    pub multiplier: u32,
+    /// Number of consecutive failed attempts before giving up. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    pub(crate) fn delay_ms(&self, attempt: u32) -> u32 {
+        let mut delay = self.initial_delay_ms;
+        for _ in 0..attempt {
+            delay = delay.saturating_mul(self.multiplier);
+            if delay >= self.max_delay_ms {
+                return self.max_delay_ms;
+            }
+        }
+        delay.min(self.max_delay_ms)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: 1000,
+            max_delay_ms: 60000,
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    /// Waiting for the backoff delay before the next attempt.
+    WaitingToReconnect,
+}
+
+#[allow(clippy::type_complexity)]
+struct ConnectionStateInner {
+    /// The state and the number of changes so far.
+    state: Mutex<(ConnectionState, u32)>,
+    wakers: Mutex<Vec<Waker>>,
+    on_change: Mutex<Option<Box<dyn FnMut(ConnectionState) + Send + Sync>>>,
+}
+
+/// Observable connection state of a [`BLEManagedClient`](crate::BLEManagedClient).
+///
+/// Every clone tracks the changes it has seen, so any number of tasks can wait for changes.
+pub struct ConnectionStateWatch {
+    inner: Arc<ConnectionStateInner>,
+    seen: AtomicU32,
+}
+
+impl ConnectionStateWatch {
+    pub(crate) fn new() -> Self {
+        Self {
+            inner: Arc::new(ConnectionStateInner {
+                state: Mutex::new((ConnectionState::Disconnected, 0)),
+                wakers: Mutex::new(Vec::new()),
+                on_change: Mutex::new(None),
+            }),
+            seen: AtomicU32::new(0),
+        }
+    }
+
+    /// Current connection state.
+    pub fn get(&self) -> ConnectionState {
+        self.inner.state.lock().0
+    }
+
+    /// Wait until the connection state changes and return the new state.
+    ///
+    /// Changes since the last call are reported immediately.
+    /// If the state changed several times in between, only the latest state is returned.
+    pub async fn changed(&self) -> ConnectionState {
+        core::future::poll_fn(|cx| {
+            let (state, version) = *self.inner.state.lock();
+            if version != self.seen.load(Ordering::Relaxed) {
+                self.seen.store(version, Ordering::Relaxed);
+                return Poll::Ready(state);
+            }
+
+            let mut wakers = self.inner.wakers.lock();
+            if !wakers.iter().any(|x| x.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            drop(wakers);
+
+            // The state may have changed before the waker was registered.
+            if self.inner.state.lock().1 != version {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn on_change(&self, callback: Box<dyn FnMut(ConnectionState) + Send + Sync>) {
+        *self.inner.on_change.lock() = Some(callback);
+    }
+
+    pub(crate) fn set(&self, state: ConnectionState) {
+        {
+            let mut current = self.inner.state.lock();
+            if current.0 == state {
+                return;
+            }
+            *current = (state, current.1.wrapping_add(1));
+        }
+
+        for waker in core::mem::take(&mut *self.inner.wakers.lock()) {
+            waker.wake();
+        }
+
+        // Called without the lock, so the callback can use the watch.
+        let callback = self.inner.on_change.lock().take();
+        if let Some(mut callback) = callback {
+            callback(state);
+            self.inner.on_change.lock().get_or_insert(callback);
+        }
+    }
+}
+
+impl Clone for ConnectionStateWatch {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            seen: AtomicU32::new(self.seen.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::task::Wake;
+    use core::{future::Future, pin::pin, task::Context};
+    use std::{thread, time::Duration};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = ReconnectPolicy {
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            multiplier: 3,
+            max_attempts: None,
+        };
+        let delays: Vec<u32> = (0..5).map(|x| policy.delay_ms(x)).collect();
+        assert_eq!(delays, [100, 300, 900, 1000, 1000]);
+        assert_eq!(policy.delay_ms(u32::MAX), 1000);
+
+        let policy = ReconnectPolicy {
+            multiplier: 1,
+            ..policy
+        };
+        assert_eq!(policy.delay_ms(10), 100);
+
+        let policy = ReconnectPolicy {
+            initial_delay_ms: 5000,
+            ..policy
+        };
+        assert_eq!(policy.delay_ms(0), 1000);
+
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay_ms(0), 1000);
+        assert_eq!(policy.delay_ms(5), 32000);
+        assert_eq!(policy.delay_ms(6), 60000);
+    }
+
+    #[test]
+    fn several_waiters() {
+        let watch = ConnectionStateWatch::new();
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let watch = watch.clone();
+                thread::spawn(move || block_on(watch.changed()))
+            })
+            .collect();
+
+        // Let the waiters register their wakers.
+        thread::sleep(Duration::from_millis(50));
+        watch.set(ConnectionState::Connecting);
+
+        for waiter in waiters {
+            assert_eq!(waiter.join().unwrap(), ConnectionState::Connecting);
+        }
+    }
+
+    #[test]
+    fn changes_are_tracked_per_clone() {
+        let watch = ConnectionStateWatch::new();
+        let other = watch.clone();
+
+        watch.set(ConnectionState::Connecting);
+        watch.set(ConnectionState::Connected);
+        // Only the latest state is reported.
+        assert_eq!(block_on(watch.changed()), ConnectionState::Connected);
+        assert_eq!(block_on(other.changed()), ConnectionState::Connected);
+
+        // Setting the same state is not a change.
+        watch.set(ConnectionState::Connected);
+        let waiter = thread::spawn(move || block_on(other.changed()));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+
+        watch.set(ConnectionState::Disconnected);
+        assert_eq!(waiter.join().unwrap(), ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn callback_can_use_the_watch() {
+        let watch = ConnectionStateWatch::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let (inner, log) = (watch.clone(), seen.clone());
+        watch.on_change(Box::new(move |state| {
+            assert_eq!(inner.get(), state);
+            log.lock().push(state);
+        }));
+
+        watch.set(ConnectionState::Connecting);
+        watch.set(ConnectionState::Connected);
+        assert_eq!(
+            *seen.lock(),
+            [ConnectionState::Connecting, ConnectionState::Connected]
+        );
+    }
+}
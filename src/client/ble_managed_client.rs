@@ -0,0 +1,236 @@
+use crate::{
+    BLEAddress, BLEAdvertisedData, BLEAdvertisedDevice, BLEClient, BLEDevice, BLEError, BLEScan,
+    ConnectionState, ConnectionStateWatch, ReconnectPolicy, Signal,
+    utilities::{BleUuid, delay_ms, mutex::Mutex},
+};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+
+#[allow(clippy::type_complexity)]
+struct ManagedSubscription {
+    service: BleUuid,
+    characteristic: BleUuid,
+    indicate: bool,
+    callback: Arc<Mutex<Box<dyn FnMut(&[u8]) + Send + Sync>>>,
+}
+
+#[allow(clippy::type_complexity)]
+struct Rescan {
+    duration_ms: i32,
+    filter: Box<dyn FnMut(&BLEAdvertisedDevice, &BLEAdvertisedData<&[u8]>) -> bool + Send + Sync>,
+}
+
+/// A [`BLEClient`] that keeps the connection to a peer alive.
+///
+/// After a disconnect the client reconnects with the [`ReconnectPolicy`] backoff,
+/// optionally scans for the peer again (e.g. when its resolvable private address changed),
+/// and restores the registered subscriptions.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut client = BLEManagedClient::new(ble_device.new_client(), address);
+/// client.subscribe(service_uuid, characteristic_uuid, false, |data| {
+///   ::log::info!("{data:?}");
+/// });
+/// client.run().await?;
+/// ```
+pub struct BLEManagedClient {
+    client: BLEClient,
+    address: BLEAddress,
+    policy: ReconnectPolicy,
+    rescan: Option<Rescan>,
+    subscriptions: Vec<ManagedSubscription>,
+    disconnected: Arc<Signal<i32>>,
+    state: ConnectionStateWatch,
+}
+
+impl BLEManagedClient {
+    /// An `on_disconnect` callback already set on the client is still called.
+    /// Setting it again through [`BLEManagedClient::client`] stops the reconnection,
+    /// use [`BLEManagedClient::on_state_change`] instead.
+    pub fn new(mut client: BLEClient, address: BLEAddress) -> Self {
+        let disconnected = Arc::new(Signal::new());
+        let signal = disconnected.clone();
+        let on_disconnect = client.take_on_disconnect();
+        client.on_disconnect(move |reason| {
+            if let Some(callback) = &on_disconnect {
+                callback(reason);
+            }
+            signal.signal(reason);
+        });
+
+        Self {
+            client,
+            address,
+            policy: ReconnectPolicy::default(),
+            rescan: None,
+            subscriptions: Vec::new(),
+            disconnected,
+            state: ConnectionStateWatch::new(),
+        }
+    }
+
+    pub fn client(&mut self) -> &mut BLEClient {
+        &mut self.client
+    }
+
+    /// Address of the peer. Updated when the peer is found again by a rescan.
+    pub fn address(&self) -> BLEAddress {
+        self.address
+    }
+
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) -> &mut Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Scan for the peer when a direct reconnection attempt fails.
+    /// The first device for which `filter` returns true is used as the new peer address.
+    pub fn rescan(
+        &mut self,
+        duration_ms: i32,
+        filter: impl FnMut(&BLEAdvertisedDevice, &BLEAdvertisedData<&[u8]>) -> bool
+        + Send
+        + Sync
+        + 'static,
+    ) -> &mut Self {
+        self.rescan = Some(Rescan {
+            duration_ms,
+            filter: Box::new(filter),
+        });
+        self
+    }
+
+    /// Subscribe to a characteristic on every connection.
+    ///
+    /// * `indicate`: Subscribe to indications instead of notifications.
+    pub fn subscribe(
+        &mut self,
+        service: BleUuid,
+        characteristic: BleUuid,
+        indicate: bool,
+        callback: impl FnMut(&[u8]) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.subscriptions.push(ManagedSubscription {
+            service,
+            characteristic,
+            indicate,
+            callback: Arc::new(Mutex::new(Box::new(callback))),
+        });
+        self
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// A handle to observe the connection state from another task.
+    pub fn state_watch(&self) -> ConnectionStateWatch {
+        self.state.clone()
+    }
+
+    pub fn on_state_change(
+        &mut self,
+        callback: impl FnMut(ConnectionState) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.state.on_change(Box::new(callback));
+        self
+    }
+
+    /// Connect to the peer and restore the subscriptions,
+    /// retrying with the reconnect policy.
+    pub async fn connect(&mut self) -> Result<(), BLEError> {
+        let mut attempt = 0;
+
+        loop {
+            self.state.set(ConnectionState::Connecting);
+            match self.try_connect().await {
+                Ok(()) => {
+                    self.state.set(ConnectionState::Connected);
+                    return Ok(());
+                }
+                Err(err) => {
+                    ::log::warn!("connect attempt {attempt} failed: {err:?}");
+                    // Connecting again while the link is still up fails,
+                    // so wait until it is gone.
+                    if self.client.connected() && self.client.disconnect().is_ok() {
+                        self.disconnected.wait().await;
+                    }
+
+                    attempt += 1;
+                    if self.policy.max_attempts.is_some_and(|max| attempt >= max) {
+                        self.state.set(ConnectionState::Disconnected);
+                        return Err(err);
+                    }
+                }
+            }
+
+            self.state.set(ConnectionState::WaitingToReconnect);
+            delay_ms(self.policy.delay_ms(attempt - 1)).await?;
+        }
+    }
+
+    /// Keep the connection alive.
+    ///
+    /// Only returns when the reconnect policy gives up.
+    /// Drop the future to stop reconnecting.
+    pub async fn run(&mut self) -> Result<(), BLEError> {
+        loop {
+            if !self.client.connected() {
+                self.connect().await?;
+            }
+
+            let reason = self.disconnected.wait().await;
+            ::log::info!("managed client disconnected: {reason}");
+            self.state.set(ConnectionState::Disconnected);
+        }
+    }
+
+    async fn try_connect(&mut self) -> Result<(), BLEError> {
+        self.disconnected.reset();
+
+        if let Err(err) = self.client.connect(&self.address).await {
+            let Some(rescan) = &mut self.rescan else {
+                return Err(err);
+            };
+
+            let mut scan = BLEScan::new();
+            let found = scan
+                .start(BLEDevice::take(), rescan.duration_ms, |device, data| {
+                    (rescan.filter)(device, &data).then(|| device.addr())
+                })
+                .await?;
+            let Some(address) = found else {
+                return Err(err);
+            };
+
+            self.address = address;
+            self.client.connect(&self.address).await?;
+        }
+
+        self.restore_subscriptions().await
+    }
+
+    async fn restore_subscriptions(&mut self) -> Result<(), BLEError> {
+        // Attribute handles may change between connections.
+        self.client.clear_services();
+
+        for subscription in &self.subscriptions {
+            let service = self.client.get_service(subscription.service).await?;
+            let characteristic = service
+                .get_characteristic(subscription.characteristic)
+                .await?;
+
+            let callback = subscription.callback.clone();
+            characteristic.on_notify(move |data| (callback.lock().as_mut())(data));
+
+            if subscription.indicate {
+                characteristic.subscribe_indicate(true).await?;
+            } else {
+                characteristic.subscribe_notify(true).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -4,7 +4,7 @@ use esp_idf_svc::sys as esp_idf_sys;
 
 use crate::{
     BLEError, Signal, ble,
-    utilities::{OsMBuf, voidp_to_ref},
+    utilities::{BleUuid, OsMBuf, voidp_to_ref},
 };
 
 pub struct BLEReader {
@@ -67,3 +67,186 @@ impl BLEReader {
         error.status as _
     }
 }
+
+/// Reads several attributes of a peer with a single ATT request.
+pub struct BLEMultiReader {
+    conn_handle: u16,
+    signal: Signal<u32>,
+}
+
+impl BLEMultiReader {
+    pub fn new(conn_handle: u16) -> Self {
+        Self {
+            conn_handle,
+            signal: Signal::new(),
+        }
+    }
+
+    /// Read Multiple Characteristic Values.
+    ///
+    /// The response does not carry the length of each value,
+    /// so the expected length of every attribute has to be given.
+    /// The values are split in request order; a value cut off by the ATT MTU is returned truncated.
+    pub async fn read_multiple(
+        &mut self,
+        attributes: &[(u16, usize)],
+    ) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let handles: Vec<u16> = attributes.iter().map(|x| x.0).collect();
+        let data = Vec::<u8>::new();
+        let mut arg = (self, data);
+
+        unsafe {
+            ble!(esp_idf_sys::ble_gattc_read_mult(
+                arg.0.conn_handle,
+                handles.as_ptr(),
+                handles.len() as _,
+                Some(Self::on_read_mult_cb),
+                core::ptr::addr_of_mut!(arg) as _,
+            ))?;
+        }
+
+        ble!(arg.0.signal.wait().await)?;
+
+        let mut rest = arg.1.as_slice();
+        let mut result = Vec::with_capacity(attributes.len());
+        for (handle, len) in attributes {
+            let (value, next) = rest.split_at((*len).min(rest.len()));
+            result.push((*handle, value.to_vec()));
+            rest = next;
+        }
+        Ok(result)
+    }
+
+    /// Read Multiple Variable Length Characteristic Values.
+    #[cfg(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "4", esp_idf_version_minor = "5")
+    ))]
+    pub async fn read_multiple_variable(
+        &mut self,
+        handles: &[u16],
+    ) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let data = Vec::<(u16, Vec<u8>)>::new();
+        let mut arg = (self, data);
+
+        unsafe {
+            ble!(esp_idf_sys::ble_gattc_read_mult_var(
+                arg.0.conn_handle,
+                handles.as_ptr(),
+                handles.len() as _,
+                Some(Self::on_read_mult_var_cb),
+                core::ptr::addr_of_mut!(arg) as _,
+            ))?;
+        }
+
+        ble!(arg.0.signal.wait().await)?;
+        Ok(arg.1)
+    }
+
+    /// Read Using Characteristic UUID.
+    ///
+    /// Returns the handle and value of every characteristic with the given UUID
+    /// in the handle range.
+    pub async fn read_by_uuid(
+        &mut self,
+        start_handle: u16,
+        end_handle: u16,
+        uuid: BleUuid,
+    ) -> Result<Vec<(u16, Vec<u8>)>, BLEError> {
+        let uuid = esp_idf_sys::ble_uuid_any_t::from(uuid);
+        let data = Vec::<(u16, Vec<u8>)>::new();
+        let mut arg = (self, data);
+
+        unsafe {
+            ble!(esp_idf_sys::ble_gattc_read_by_uuid(
+                arg.0.conn_handle,
+                start_handle,
+                end_handle,
+                &uuid.u,
+                Some(Self::on_read_by_uuid_cb),
+                core::ptr::addr_of_mut!(arg) as _,
+            ))?;
+        }
+
+        ble!(arg.0.signal.wait().await)?;
+        Ok(arg.1)
+    }
+
+    extern "C" fn on_read_mult_cb(
+        conn_handle: u16,
+        error: *const esp_idf_sys::ble_gatt_error,
+        attr: *mut esp_idf_sys::ble_gatt_attr,
+        arg: *mut c_void,
+    ) -> i32 {
+        let (reader, data) = unsafe { voidp_to_ref::<(&mut Self, Vec<u8>)>(arg) };
+        if conn_handle != reader.conn_handle {
+            return 0;
+        }
+
+        let error = unsafe { &*error };
+
+        if error.status == 0
+            && let Some(attr) = unsafe { attr.as_ref() }
+        {
+            for om in OsMBuf(attr.om).iter() {
+                data.extend_from_slice(om.as_slice());
+            }
+        }
+
+        reader.signal.signal(error.status as _);
+        error.status as _
+    }
+
+    #[cfg(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "4", esp_idf_version_minor = "5")
+    ))]
+    extern "C" fn on_read_mult_var_cb(
+        conn_handle: u16,
+        error: *const esp_idf_sys::ble_gatt_error,
+        attrs: *mut esp_idf_sys::ble_gatt_attr,
+        num_attrs: u8,
+        arg: *mut c_void,
+    ) -> i32 {
+        let (reader, data) = unsafe { voidp_to_ref::<(&mut Self, Vec<(u16, Vec<u8>)>)>(arg) };
+        if conn_handle != reader.conn_handle {
+            return 0;
+        }
+
+        let error = unsafe { &*error };
+
+        if error.status == 0 && !attrs.is_null() {
+            let attrs = unsafe { core::slice::from_raw_parts(attrs, num_attrs as _) };
+            for attr in attrs {
+                data.push((attr.handle, OsMBuf(attr.om).as_flat().as_slice().to_vec()));
+            }
+        }
+
+        reader.signal.signal(error.status as _);
+        error.status as _
+    }
+
+    extern "C" fn on_read_by_uuid_cb(
+        conn_handle: u16,
+        error: *const esp_idf_sys::ble_gatt_error,
+        attr: *mut esp_idf_sys::ble_gatt_attr,
+        arg: *mut c_void,
+    ) -> i32 {
+        let (reader, data) = unsafe { voidp_to_ref::<(&mut Self, Vec<(u16, Vec<u8>)>)>(arg) };
+        if conn_handle != reader.conn_handle {
+            return 0;
+        }
+
+        let error = unsafe { &*error };
+
+        if error.status == 0
+            && let Some(attr) = unsafe { attr.as_ref() }
+        {
+            data.push((attr.handle, OsMBuf(attr.om).as_flat().as_slice().to_vec()));
+            return 0;
+        }
+
+        reader.signal.signal(error.status as _);
+        error.status as _
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{
+    BLEError, BLERemoteCharacteristic, Signal, ble,
+    utilities::{OsMBuf, voidp_to_ref},
+};
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use esp_idf_svc::sys as esp_idf_sys;
+
+/// A reliable write transaction ("Reliable Writes" procedure).
+///
+/// Values are queued locally and sent as prepared writes when [`ReliableWrite::commit`] is called.
+/// Every echoed value is compared with the queued data;
+/// on mismatch the queue on the peer is cancelled and `BLE_HS_EBADDATA` is returned.
+pub struct ReliableWrite {
+    conn_handle: u16,
+    attrs: Vec<(u16, Vec<u8>)>,
+    signal: Signal<u32>,
+}
+
+impl ReliableWrite {
+    pub(crate) fn new(conn_handle: u16) -> Self {
+        Self {
+            conn_handle,
+            attrs: Vec::new(),
+            signal: Signal::new(),
+        }
+    }
+
+    /// Queue a write to the attribute handle.
+    pub fn write(&mut self, handle: u16, data: &[u8]) -> &mut Self {
+        self.attrs.push((handle, data.to_vec()));
+        self
+    }
+
+    /// Queue a write to the characteristic value.
+    pub fn write_characteristic(
+        &mut self,
+        characteristic: &BLERemoteCharacteristic,
+        data: &[u8],
+    ) -> &mut Self {
+        self.write(characteristic.handle(), data)
+    }
+
+    /// Number of queued writes.
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+
+    /// Discard the queued writes without sending anything.
+    ///
+    /// Nothing is sent to the peer before [`ReliableWrite::commit`],
+    /// so there is no queue on the peer to cancel.
+    /// A commit in progress cancels the queue on the peer by itself when an echoed value does not match.
+    pub fn cancel(mut self) {
+        self.attrs.clear();
+    }
+
+    /// Send the queued writes and execute them atomically.
+    pub async fn commit(mut self) -> Result<(), BLEError> {
+        if self.attrs.is_empty() {
+            return Ok(());
+        }
+
+        let mut attrs: Vec<esp_idf_sys::ble_gatt_attr> = Vec::with_capacity(self.attrs.len());
+        for (handle, data) in &self.attrs {
+            let om = OsMBuf::from_flat(data);
+            if om.0.is_null() {
+                for attr in &attrs {
+                    OsMBuf(attr.om).free_chain();
+                }
+                return BLEError::convert(esp_idf_sys::BLE_HS_ENOMEM);
+            }
+            attrs.push(esp_idf_sys::ble_gatt_attr {
+                handle: *handle,
+                offset: 0,
+                om: om.0,
+            });
+        }
+
+        unsafe {
+            // The mbufs are consumed by NimBLE even when the call fails.
+            ble!(esp_idf_sys::ble_gattc_write_reliable(
+                self.conn_handle,
+                attrs.as_mut_ptr(),
+                attrs.len() as _,
+                Some(Self::on_write_reliable_cb),
+                core::ptr::addr_of_mut!(self) as _,
+            ))?;
+        }
+
+        ble!(self.signal.wait().await)
+    }
+
+    extern "C" fn on_write_reliable_cb(
+        conn_handle: u16,
+        error: *const esp_idf_sys::ble_gatt_error,
+        _attrs: *mut esp_idf_sys::ble_gatt_attr,
+        _num_attrs: u8,
+        arg: *mut c_void,
+    ) -> i32 {
+        let writer = unsafe { voidp_to_ref::<Self>(arg) };
+        if writer.conn_handle != conn_handle {
+            return 0;
+        }
+
+        writer.signal.signal(unsafe { (*error).status as _ });
+        0
+    }
+}
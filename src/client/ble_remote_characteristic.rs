@@ -5,6 +5,7 @@ use super::ble_remote_service::BLERemoteServiceState;
 use super::{BLEReader, BLEWriter};
 use crate::BLEAttribute;
 use crate::utilities::OsMBuf;
+use crate::utilities::fragmentation::{FragmentFormat, Reassembler};
 use crate::{
     BLEError, BLERemoteDescriptor, Signal, ble,
     utilities::{ArcUnsafeCell, BleUuid, WeakUnsafeCell, as_void_ptr, voidp_to_ref},
@@ -91,6 +92,11 @@ impl BLERemoteCharacteristic {
         self.state.uuid
     }
 
+    /// Handle of the characteristic value.
+    pub fn handle(&self) -> u16 {
+        self.state.handle
+    }
+
     pub fn properties(&self) -> GattCharacteristicProperties {
         self.state.properties
     }
@@ -227,6 +233,23 @@ impl BLERemoteCharacteristic {
         self
     }
 
+    /// Reassemble values sent with [`crate::BLECharacteristic::notify_fragmented`].
+    ///
+    /// * `max_len`: Maximum length of a reassembled value.
+    pub fn on_notify_fragmented(
+        &mut self,
+        format: FragmentFormat,
+        max_len: usize,
+        mut callback: impl FnMut(&[u8]) + Send + Sync + 'static,
+    ) -> &mut Self {
+        let mut reassembler = Reassembler::new(format, max_len);
+        self.on_notify(move |fragment| match reassembler.push(fragment) {
+            Ok(Some(value)) => callback(&value),
+            Ok(None) => {}
+            Err(err) => ::log::warn!("fragment error: {err:?}"),
+        })
+    }
+
     pub fn can_notify(&self) -> bool {
         self.properties()
             .contains(GattCharacteristicProperties::NOTIFY)
@@ -10,9 +10,11 @@ use esp_idf_svc::sys as esp_idf_sys;
 pub struct BLERemoteServiceState {
     client: WeakUnsafeCell<BLEClientState>,
     pub(crate) uuid: BleUuid,
-    start_handle: u16,
+    pub(crate) start_handle: u16,
     pub(crate) end_handle: u16,
     pub(crate) characteristics: Option<Vec<BLERemoteCharacteristic>>,
+    all_characteristics_discovered: bool,
+    included_services: Option<Vec<BLERemoteService>>,
     signal: Signal<u32>,
 }
 
@@ -39,6 +41,8 @@ impl BLERemoteService {
                 start_handle: service.start_handle,
                 end_handle: service.end_handle,
                 characteristics: None,
+                all_characteristics_discovered: false,
+                included_services: None,
                 signal: Signal::new(),
             }),
         }
@@ -52,8 +56,8 @@ impl BLERemoteService {
     pub async fn get_characteristics(
         &mut self,
     ) -> Result<core::slice::IterMut<'_, BLERemoteCharacteristic>, BLEError> {
-        if self.state.characteristics.is_none() {
-            self.state.characteristics = Some(Vec::new());
+        if !self.state.all_characteristics_discovered {
+            self.state.characteristics.get_or_insert_with(Vec::new);
             unsafe {
                 ble!(esp_idf_sys::ble_gattc_disc_all_chrs(
                     self.state.conn_handle(),
@@ -64,21 +68,72 @@ impl BLERemoteService {
                 ))?;
             }
             ble!(self.state.signal.wait().await)?;
+            self.state.all_characteristics_discovered = true;
         }
 
         Ok(self.state.characteristics.as_mut().unwrap().iter_mut())
     }
 
     /// Get the characteristic object for the UUID.
+    ///
+    /// If the full characteristic list has not been discovered yet,
+    /// only the requested characteristic is discovered ("Discover Characteristics by UUID").
     pub async fn get_characteristic(
         &mut self,
         uuid: BleUuid,
     ) -> Result<&mut BLERemoteCharacteristic, BLEError> {
-        let mut iter = self.get_characteristics().await?;
-        iter.find(|x| x.uuid() == uuid)
+        let cached = self
+            .state
+            .characteristics
+            .iter()
+            .flatten()
+            .any(|x| x.uuid() == uuid);
+
+        if !cached && !self.state.all_characteristics_discovered {
+            self.state.characteristics.get_or_insert_with(Vec::new);
+            let uuid_any = esp_idf_sys::ble_uuid_any_t::from(uuid);
+            unsafe {
+                ble!(esp_idf_sys::ble_gattc_disc_chrs_by_uuid(
+                    self.state.conn_handle(),
+                    self.state.start_handle,
+                    self.state.end_handle,
+                    &uuid_any.u,
+                    Some(Self::characteristic_disc_cb),
+                    as_void_ptr(self),
+                ))?;
+            }
+            ble!(self.state.signal.wait().await)?;
+        }
+
+        self.state
+            .characteristics
+            .iter_mut()
+            .flatten()
+            .find(|x| x.uuid() == uuid)
             .ok_or_else(|| BLEError::fail().unwrap_err())
     }
 
+    /// Get the services included by this service.
+    pub async fn get_included_services(
+        &mut self,
+    ) -> Result<core::slice::IterMut<'_, BLERemoteService>, BLEError> {
+        if self.state.included_services.is_none() {
+            self.state.included_services = Some(Vec::new());
+            unsafe {
+                ble!(esp_idf_sys::ble_gattc_find_inc_svcs(
+                    self.state.conn_handle(),
+                    self.state.start_handle,
+                    self.state.end_handle,
+                    Some(Self::included_service_disc_cb),
+                    as_void_ptr(self),
+                ))?;
+            }
+            ble!(self.state.signal.wait().await)?;
+        }
+
+        Ok(self.state.included_services.as_mut().unwrap().iter_mut())
+    }
+
     extern "C" fn characteristic_disc_cb(
         conn_handle: u16,
         error: *const esp_idf_sys::ble_gatt_error,
@@ -93,8 +148,43 @@ impl BLERemoteService {
 
         if error.status == 0 {
             let chr = unsafe { &*chr };
-            let chr = BLERemoteCharacteristic::new(ArcUnsafeCell::downgrade(&service.state), chr);
-            service.state.characteristics.as_mut().unwrap().push(chr);
+            let characteristics = service.state.characteristics.as_mut().unwrap();
+            if !characteristics
+                .iter()
+                .any(|x| x.state().handle == chr.val_handle)
+            {
+                let chr =
+                    BLERemoteCharacteristic::new(ArcUnsafeCell::downgrade(&service.state), chr);
+                service.state.characteristics.as_mut().unwrap().push(chr);
+            }
+            return 0;
+        }
+
+        service.state.signal.signal(error.status as _);
+        error.status as _
+    }
+
+    extern "C" fn included_service_disc_cb(
+        conn_handle: u16,
+        error: *const esp_idf_sys::ble_gatt_error,
+        included: *const esp_idf_sys::ble_gatt_svc,
+        arg: *mut c_void,
+    ) -> i32 {
+        let service = unsafe { voidp_to_ref::<Self>(arg) };
+        if service.state.conn_handle() != conn_handle {
+            return 0;
+        }
+        let error = unsafe { &*error };
+
+        if error.status == 0 {
+            let included = unsafe { &*included };
+            let included = BLERemoteService::new(service.state.client.clone(), included);
+            service
+                .state
+                .included_services
+                .as_mut()
+                .unwrap()
+                .push(included);
             return 0;
         }
 
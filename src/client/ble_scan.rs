@@ -1,5 +1,8 @@
-use crate::{BLEAdvertisedData, BLEDevice};
+use crate::{BLEAddress, BLEAdvertisedData, BLEDevice, BLEScanFilter, DuplicateKey};
 use crate::{BLEAdvertisedDevice, BLEError, Signal, ble, enums::*, utilities::voidp_to_ref};
+use alloc::collections::VecDeque;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+use alloc::vec::Vec;
 use core::ffi::c_void;
 use esp_idf_svc::sys;
 
@@ -26,8 +29,61 @@ use esp_idf_svc::sys;
 pub struct BLEScan {
     scan_params: sys::ble_gap_disc_params,
     signal: Signal<()>,
+    filter: Option<BLEScanFilter>,
+    duplicate_key: Option<DuplicateKey>,
+    duplicate_cache: VecDeque<u64>,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    phys: PhyMask,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    coded_itvl: Option<u16>,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    coded_window: Option<u16>,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    reassemble: bool,
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    fragments: Vec<FragmentChain>,
+    /// Chains dropped to make room for newer ones, whose remaining fragments are discarded.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    evicted_fragments: VecDeque<(BLEAddress, u8)>,
 }
 
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+struct FragmentChain {
+    addr: BLEAddress,
+    sid: u8,
+    data: Vec<u8>,
+    /// The chain exceeded the maximum length, the remaining fragments are discarded.
+    truncated: bool,
+}
+
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+enum Fragment {
+    /// Waiting for more data.
+    Buffered,
+    /// The report is not part of a chain.
+    Single,
+    /// The reassembled data of a chain.
+    Assembled(Vec<u8>),
+    /// The beginning of a chain that exceeded the maximum length,
+    /// or nothing for a chain that was evicted.
+    Truncated(Vec<u8>),
+}
+
+/// Number of chained extended advertisements reassembled at the same time.
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+const MAX_FRAGMENT_CHAINS: usize = 4;
+
+/// Number of evicted chains remembered until their last fragment.
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+const MAX_EVICTED_CHAINS: usize = 16;
+
+/// Maximum length of extended advertising data.
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+const MAX_EXT_ADV_DATA_LEN: usize = 1650;
+
+/// Number of entries kept by the host-side duplicate filter.
+const DUPLICATE_CACHE_SIZE: usize = 256;
+
 type CbArgType<'a> = (
     &'a mut BLEScan,
     &'a mut dyn FnMut(&mut BLEScan, &BLEAdvertisedDevice, BLEAdvertisedData<&[u8]>),
@@ -43,6 +99,21 @@ impl BLEScan {
                 ..Default::default()
             },
             signal: Signal::new(),
+            filter: None,
+            duplicate_key: None,
+            duplicate_cache: VecDeque::new(),
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            phys: PhyMask::Phy1M | PhyMask::Coded,
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            coded_itvl: None,
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            coded_window: None,
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            reassemble: true,
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            fragments: Vec::new(),
+            #[cfg(esp_idf_bt_nimble_ext_adv)]
+            evicted_fragments: VecDeque::new(),
         };
         ret.limited(false);
         ret.filter_duplicates(true);
@@ -73,6 +144,31 @@ impl BLEScan {
         self
     }
 
+    /// Only report advertisements matching the filter.
+    /// The filter runs in the NimBLE host task before the callback of [`BLEScan::start`].
+    pub fn filter(&mut self, filter: Option<BLEScanFilter>) -> &mut Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Drop repeated advertisements in the host before the filter and the callback run.
+    ///
+    /// Unlike `filter_duplicates`, which uses the controller's duplicate filter,
+    /// this allows reporting a device again when its advertising data changes.
+    /// The cache is cleared when a scan is started.
+    pub fn host_duplicate_filter(&mut self, key: Option<DuplicateKey>) -> &mut Self {
+        self.duplicate_key = key;
+        self
+    }
+
+    /// Only process advertisements from the given addresses in the controller.
+    ///
+    /// Sets the controller's accept list and the `UseWl` filter policy.
+    pub fn accept_list(&mut self, addresses: &[BLEAddress]) -> Result<&mut Self, BLEError> {
+        BLEDevice::take().set_white_list(addresses)?;
+        Ok(self.filter_policy(ScanFilterPolicy::UseWl))
+    }
+
     /// Set the interval to scan.
     pub fn interval(&mut self, interval_msecs: u16) -> &mut Self {
         self.scan_params.itvl = ((interval_msecs as f32) / 0.625) as u16;
@@ -85,6 +181,40 @@ impl BLEScan {
         self
     }
 
+    /// Set the PHYs to scan on. Only `Phy1M` and `Coded` are valid.
+    /// Default is both.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub fn phys(&mut self, phys: PhyMask) -> &mut Self {
+        self.phys = phys;
+        self
+    }
+
+    /// Set the interval to scan on the Coded PHY.
+    /// If not set, the value of `interval` is used.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub fn coded_interval(&mut self, interval_msecs: u16) -> &mut Self {
+        self.coded_itvl = Some(((interval_msecs as f32) / 0.625) as u16);
+        self
+    }
+
+    /// Set the window to scan on the Coded PHY.
+    /// If not set, the value of `window` is used.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub fn coded_window(&mut self, window_msecs: u16) -> &mut Self {
+        self.coded_window = Some(((window_msecs as f32) / 0.625) as u16);
+        self
+    }
+
+    /// Set whether chained extended advertisements are reassembled before being reported.
+    /// Default is true.
+    ///
+    /// If disabled, every fragment is reported, see [`BLEAdvertisedDevice::data_status`].
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub fn reassemble(&mut self, val: bool) -> &mut Self {
+        self.reassemble = val;
+        self
+    }
+
     /// The callback function must return Option type.
     /// If it returns None, the scan continues.
     /// If Some(r) is returned, the scan stops and the start function returns the return value of the callback.
@@ -111,16 +241,41 @@ impl BLEScan {
                 }
             };
 
+        self.duplicate_cache.clear();
+        #[cfg(esp_idf_bt_nimble_ext_adv)]
+        {
+            self.fragments.clear();
+            self.evicted_fragments.clear();
+        }
         let cb_arg: CbArgType = (self, &mut on_result);
 
         #[cfg(esp_idf_bt_nimble_ext_adv)]
         {
-            let mut scan_params = sys::ble_gap_ext_disc_params {
-                itvl: cb_arg.0.scan_params.itvl,
-                window: cb_arg.0.scan_params.window,
+            let scan = &cb_arg.0;
+            let mut uncoded_params = sys::ble_gap_ext_disc_params {
+                itvl: scan.scan_params.itvl,
+                window: scan.scan_params.window,
+                ..Default::default()
+            };
+            uncoded_params.set_passive(scan.scan_params.passive());
+            let mut coded_params = sys::ble_gap_ext_disc_params {
+                itvl: scan.coded_itvl.unwrap_or(scan.scan_params.itvl),
+                window: scan.coded_window.unwrap_or(scan.scan_params.window),
                 ..Default::default()
             };
-            scan_params.set_passive(cb_arg.0.scan_params.passive());
+            coded_params.set_passive(scan.scan_params.passive());
+
+            let uncoded_params: *const _ = if scan.phys.contains(PhyMask::Phy1M) {
+                &uncoded_params
+            } else {
+                core::ptr::null()
+            };
+            let coded_params: *const _ = if scan.phys.contains(PhyMask::Coded) {
+                &coded_params
+            } else {
+                core::ptr::null()
+            };
+
             unsafe {
                 ble!(sys::ble_gap_ext_disc(
                     crate::ble_device::OWN_ADDR_TYPE as _,
@@ -129,8 +284,8 @@ impl BLEScan {
                     cb_arg.0.scan_params.filter_duplicates(),
                     cb_arg.0.scan_params.filter_policy,
                     cb_arg.0.scan_params.limited(),
-                    &scan_params,
-                    &scan_params,
+                    uncoded_params,
+                    coded_params,
                     Some(Self::handle_gap_event),
                     core::ptr::addr_of!(cb_arg) as _,
                 ))?;
@@ -162,6 +317,68 @@ impl BLEScan {
         Ok(())
     }
 
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    fn reassemble_fragment(&mut self, device: &BLEAdvertisedDevice, data: &[u8]) -> Fragment {
+        let addr = device.addr();
+        let sid = device.sid();
+        let idx = self
+            .fragments
+            .iter()
+            .position(|x| x.addr == addr && x.sid == sid);
+        let evicted = self
+            .evicted_fragments
+            .iter()
+            .position(|x| *x == (addr, sid));
+
+        match device.data_status() {
+            DataStatus::Incomplete if evicted.is_some() => Fragment::Buffered,
+            DataStatus::Incomplete => {
+                let idx = idx.unwrap_or_else(|| {
+                    if self.fragments.len() == MAX_FRAGMENT_CHAINS {
+                        ::log::debug!("dropping incomplete extended advertisement");
+                        let chain = self.fragments.remove(0);
+                        if self.evicted_fragments.len() == MAX_EVICTED_CHAINS {
+                            self.evicted_fragments.pop_front();
+                        }
+                        self.evicted_fragments.push_back((chain.addr, chain.sid));
+                    }
+                    self.fragments.push(FragmentChain {
+                        addr,
+                        sid,
+                        data: Vec::new(),
+                        truncated: false,
+                    });
+                    self.fragments.len() - 1
+                });
+
+                let chain = &mut self.fragments[idx];
+                if chain.truncated || chain.data.len() + data.len() > MAX_EXT_ADV_DATA_LEN {
+                    chain.truncated = true;
+                } else {
+                    chain.data.extend_from_slice(data);
+                }
+                Fragment::Buffered
+            }
+            DataStatus::Complete | DataStatus::Truncated => match idx {
+                Some(idx) => {
+                    let mut chain = self.fragments.remove(idx);
+                    if chain.truncated || chain.data.len() + data.len() > MAX_EXT_ADV_DATA_LEN {
+                        return Fragment::Truncated(chain.data);
+                    }
+                    chain.data.extend_from_slice(data);
+                    Fragment::Assembled(chain.data)
+                }
+                None => match evicted {
+                    Some(evicted) => {
+                        self.evicted_fragments.remove(evicted);
+                        Fragment::Truncated(Vec::new())
+                    }
+                    None => Fragment::Single,
+                },
+            },
+        }
+    }
+
     extern "C" fn handle_gap_event(event: *mut sys::ble_gap_event, arg: *mut c_void) -> i32 {
         let event = unsafe { &*event };
         let (scan, on_result) = unsafe { voidp_to_ref::<CbArgType>(arg) };
@@ -174,11 +391,55 @@ impl BLEScan {
                 #[cfg(not(esp_idf_bt_nimble_ext_adv))]
                 let disc = unsafe { &event.__bindgen_anon_1.disc };
 
-                let data = BLEAdvertisedData::new(unsafe {
-                    core::slice::from_raw_parts(disc.data, disc.length_data as _)
-                });
+                #[cfg_attr(not(esp_idf_bt_nimble_ext_adv), allow(unused_mut))]
+                let mut advertised_device: &BLEAdvertisedDevice =
+                    unsafe { core::mem::transmute(disc) };
+
+                #[cfg(esp_idf_bt_nimble_ext_adv)]
+                let assembled: Vec<u8>;
+                #[cfg(esp_idf_bt_nimble_ext_adv)]
+                let mut truncated_device: BLEAdvertisedDevice;
+                #[cfg_attr(not(esp_idf_bt_nimble_ext_adv), allow(unused_mut))]
+                let mut payload =
+                    unsafe { core::slice::from_raw_parts(disc.data, disc.length_data as _) };
+
+                #[cfg(esp_idf_bt_nimble_ext_adv)]
+                if scan.reassemble {
+                    match scan.reassemble_fragment(advertised_device, payload) {
+                        Fragment::Buffered => return 0,
+                        Fragment::Single => {}
+                        Fragment::Assembled(data) => {
+                            assembled = data;
+                            payload = assembled.as_slice();
+                        }
+                        Fragment::Truncated(data) => {
+                            assembled = data;
+                            payload = assembled.as_slice();
+                            truncated_device = *advertised_device;
+                            truncated_device.set_data_status(DataStatus::Truncated);
+                            advertised_device = &truncated_device;
+                        }
+                    }
+                }
+
+                let data = BLEAdvertisedData::new(payload);
+
+                if let Some(key) = scan.duplicate_key {
+                    let hash = key.hash(advertised_device, data.payload());
+                    if scan.duplicate_cache.contains(&hash) {
+                        return 0;
+                    }
+                    if scan.duplicate_cache.len() == DUPLICATE_CACHE_SIZE {
+                        scan.duplicate_cache.pop_front();
+                    }
+                    scan.duplicate_cache.push_back(hash);
+                }
 
-                let advertised_device: &BLEAdvertisedDevice = unsafe { core::mem::transmute(disc) };
+                if let Some(filter) = &scan.filter
+                    && !filter.matches(advertised_device, &data)
+                {
+                    return 0;
+                }
 
                 on_result(scan, advertised_device, data);
             }
@@ -0,0 +1,227 @@
+use crate::{
+    BLEAddress, BLEAddressType, BLEAdvertisedData, BLEAdvertisedDevice, BLEError, enums::AdvType,
+    utilities::BleUuid,
+};
+use alloc::{string::String, vec::Vec};
+use bstr::ByteSlice;
+use esp_idf_svc::sys as esp_idf_sys;
+
+/// How [`BLEScanFilter`] matches the advertised local name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NamePattern {
+    Exact(String),
+    Prefix(String),
+    Contains(String),
+}
+
+impl NamePattern {
+    fn matches(&self, name: &[u8]) -> bool {
+        match self {
+            NamePattern::Exact(x) => name == x.as_bytes(),
+            NamePattern::Prefix(x) => name.starts_with(x.as_bytes()),
+            NamePattern::Contains(x) => name.contains_str(x),
+        }
+    }
+}
+
+/// A declarative filter for advertisements.
+///
+/// An advertisement matches when every condition that is set matches.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut filter = BLEScanFilter::new();
+/// filter
+///   .service_uuid(uuid128!("fafafafa-fafa-fafa-fafa-fafafafafafa"))
+///   .name(NamePattern::Prefix("Sensor".into()))
+///   .min_rssi(-70);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BLEScanFilter {
+    service_uuids: Vec<BleUuid>,
+    service_data_uuids: Vec<BleUuid>,
+    manufacturer_id: Option<u16>,
+    manufacturer_data: Option<(Vec<u8>, Vec<u8>)>,
+    name: Option<NamePattern>,
+    min_rssi: Option<i8>,
+    address: Option<BLEAddress>,
+    address_type: Option<BLEAddressType>,
+    adv_types: Vec<AdvType>,
+}
+
+impl BLEScanFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match devices advertising the service UUID.
+    /// If several UUIDs are added, advertising any of them matches.
+    pub fn service_uuid(&mut self, uuid: BleUuid) -> &mut Self {
+        self.service_uuids.push(uuid);
+        self
+    }
+
+    /// Match devices advertising service data for the UUID.
+    /// If several UUIDs are added, advertising any of them matches.
+    pub fn service_data_uuid(&mut self, uuid: BleUuid) -> &mut Self {
+        self.service_data_uuids.push(uuid);
+        self
+    }
+
+    /// Match the company identifier of the manufacturer specific data.
+    pub fn manufacturer_id(&mut self, company_identifier: u16) -> &mut Self {
+        self.manufacturer_id = Some(company_identifier);
+        self
+    }
+
+    /// Match the manufacturer specific data following the company identifier.
+    ///
+    /// Only the bits set in `mask` are compared.
+    /// Returns `BLE_HS_EINVAL` if `mask` does not have the same length as `data`.
+    pub fn manufacturer_data(
+        &mut self,
+        company_identifier: u16,
+        data: &[u8],
+        mask: &[u8],
+    ) -> Result<&mut Self, BLEError> {
+        if data.len() != mask.len() {
+            return Err(BLEError::convert(esp_idf_sys::BLE_HS_EINVAL).unwrap_err());
+        }
+
+        self.manufacturer_id = Some(company_identifier);
+        self.manufacturer_data = Some((data.to_vec(), mask.to_vec()));
+        Ok(self)
+    }
+
+    /// Match the complete or shortened local name.
+    pub fn name(&mut self, pattern: NamePattern) -> &mut Self {
+        self.name = Some(pattern);
+        self
+    }
+
+    /// Match devices with a RSSI greater than or equal to `rssi`.
+    pub fn min_rssi(&mut self, rssi: i8) -> &mut Self {
+        self.min_rssi = Some(rssi);
+        self
+    }
+
+    /// Match the device address.
+    pub fn address(&mut self, address: BLEAddress) -> &mut Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Match the type of the device address.
+    pub fn address_type(&mut self, address_type: BLEAddressType) -> &mut Self {
+        self.address_type = Some(address_type);
+        self
+    }
+
+    /// Match the advertisement type.
+    /// If several types are added, any of them matches.
+    pub fn adv_type(&mut self, adv_type: AdvType) -> &mut Self {
+        self.adv_types.push(adv_type);
+        self
+    }
+
+    pub fn matches(&self, device: &BLEAdvertisedDevice, data: &BLEAdvertisedData<&[u8]>) -> bool {
+        if let Some(address) = &self.address
+            && *address != device.addr()
+        {
+            return false;
+        }
+
+        if let Some(address_type) = self.address_type
+            && address_type != device.addr().addr_type()
+        {
+            return false;
+        }
+
+        if !self.adv_types.is_empty() && !self.adv_types.contains(&device.adv_type()) {
+            return false;
+        }
+
+        if let Some(min_rssi) = self.min_rssi
+            && device.rssi() < min_rssi
+        {
+            return false;
+        }
+
+        if !self.service_uuids.is_empty()
+            && !self
+                .service_uuids
+                .iter()
+                .any(|x| data.is_advertising_service(x))
+        {
+            return false;
+        }
+
+        if !self.service_data_uuids.is_empty()
+            && !data
+                .service_data_list()
+                .any(|x| self.service_data_uuids.contains(&x.uuid))
+        {
+            return false;
+        }
+
+        if let Some(manufacturer_id) = self.manufacturer_id {
+            let Some(manufacture_data) = data.manufacture_data() else {
+                return false;
+            };
+            if manufacture_data.company_identifier != manufacturer_id {
+                return false;
+            }
+
+            if let Some((expected, mask)) = &self.manufacturer_data {
+                let payload = manufacture_data.payload;
+                if payload.len() < expected.len()
+                    || payload
+                        .iter()
+                        .zip(expected.iter().zip(mask))
+                        .any(|(x, (e, m))| (x & m) != (e & m))
+                {
+                    return false;
+                }
+            }
+        }
+
+        if let Some(pattern) = &self.name
+            && data.name().is_none_or(|x| !pattern.matches(x))
+        {
+            return false;
+        }
+
+        true
+    }
+}
+
+/// Key used by the host-side duplicate filter of [`crate::BLEScan`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DuplicateKey {
+    /// Report each address only once.
+    Address,
+    /// Report each address again when its advertising data changes.
+    AddressAndData,
+}
+
+impl DuplicateKey {
+    /// FNV-1a hash of the fields selected by the key.
+    pub(crate) fn hash(&self, device: &BLEAdvertisedDevice, data: &[u8]) -> u64 {
+        let addr = device.addr();
+        let mut hash = 0xcbf29ce484222325_u64;
+        let mut feed = |bytes: &[u8]| {
+            for byte in bytes {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        feed(&addr.as_le_bytes());
+        feed(&[addr.addr_type() as u8]);
+        if *self == DuplicateKey::AddressAndData {
+            feed(data);
+        }
+        hash
+    }
+}
@@ -10,6 +10,15 @@ pub(crate) use self::ble_attribute::*;
 mod ble_client;
 pub use self::ble_client::BLEClient;
 
+mod ble_connection_manager;
+pub use self::ble_connection_manager::*;
+
+mod ble_connection_state;
+pub use self::ble_connection_state::*;
+
+mod ble_managed_client;
+pub use self::ble_managed_client::*;
+
 mod ble_remote_characteristic;
 pub use self::ble_remote_characteristic::*;
 
@@ -19,11 +28,17 @@ pub use self::ble_remote_descriptor::BLERemoteDescriptor;
 mod ble_remote_service;
 pub use self::ble_remote_service::BLERemoteService;
 
+mod ble_reliable_write;
+pub use self::ble_reliable_write::ReliableWrite;
+
+mod ble_scan_filter;
+pub use self::ble_scan_filter::*;
+
 mod ble_scan;
 pub use self::ble_scan::BLEScan;
 
 mod ble_reader;
-use ble_reader::BLEReader;
+use ble_reader::{BLEMultiReader, BLEReader};
 
 mod ble_writer;
 use ble_writer::BLEWriter;
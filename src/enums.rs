@@ -233,3 +233,40 @@ pub enum SecPhy {
     /// Coded phy
     Coded = BLE_HCI_LE_PHY_CODED as _,
 }
+
+bitflags! {
+  #[repr(transparent)]
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct PhyMask: u8 {
+    /// 1Mbps phy
+    const Phy1M = BLE_GAP_LE_PHY_1M_MASK as _;
+    /// 2Mbps phy
+    const Phy2M = BLE_GAP_LE_PHY_2M_MASK as _;
+    /// Coded phy
+    const Coded = BLE_GAP_LE_PHY_CODED_MASK as _;
+  }
+}
+
+/// Data status of an extended advertising report.
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, Debug, TryFromPrimitive)]
+pub enum DataStatus {
+    /// All data received
+    Complete = BLE_GAP_EXT_ADV_DATA_STATUS_COMPLETE as _,
+    /// More data to come
+    Incomplete = BLE_GAP_EXT_ADV_DATA_STATUS_INCOMPLETE as _,
+    /// Data truncated, no more data to come
+    Truncated = BLE_GAP_EXT_ADV_DATA_STATUS_TRUNCATED as _,
+}
+
+/// Preferred coding when the Coded PHY is used.
+#[repr(u16)]
+#[derive(Copy, Clone, PartialEq, Debug, TryFromPrimitive, IntoPrimitive)]
+pub enum CodedPhyOption {
+    /// No preference
+    Any = BLE_GAP_LE_PHY_CODED_ANY as _,
+    /// 500kbps, S=2 coding
+    S2 = BLE_GAP_LE_PHY_CODED_S2 as _,
+    /// 125kbps, S=8 coding
+    S8 = BLE_GAP_LE_PHY_CODED_S8 as _,
+}
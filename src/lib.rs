@@ -32,6 +32,9 @@ mod ble_error;
 pub use self::ble_error::BLEError;
 pub(crate) use self::ble_error::ble;
 
+mod ble_phy;
+pub use self::ble_phy::{DataLengthChange, PhyUpdate};
+
 mod ble_security;
 pub use self::ble_security::BLESecurity;
 
@@ -43,6 +46,8 @@ pub use self::client::*;
 mod server;
 pub use self::server::*;
 
+pub mod beacon;
+
 pub mod l2cap;
 
 pub mod utilities;
@@ -1,12 +1,64 @@
 use alloc::vec::Vec;
+use esp_idf_svc::sys;
+
+const ATTR_MAX_LEN: usize = sys::BLE_ATT_ATTR_MAX_LEN as _;
 
 pub struct AttValue {
     value: Vec<u8>,
+    max_len: usize,
+    fixed_len: bool,
 }
 
 impl AttValue {
     pub(super) const fn new() -> Self {
-        Self { value: Vec::new() }
+        Self {
+            value: Vec::new(),
+            max_len: ATTR_MAX_LEN,
+            fixed_len: false,
+        }
+    }
+
+    /// An empty value with the same length constraints.
+    pub(super) fn empty_like(&self) -> Self {
+        Self {
+            value: Vec::new(),
+            max_len: self.max_len,
+            fixed_len: self.fixed_len,
+        }
+    }
+
+    /// Limit the length of the value, up to 512 bytes.
+    /// Longer writes from peers are rejected, longer local values are truncated.
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len.min(ATTR_MAX_LEN);
+        self.fixed_len = false;
+        self.value.truncate(self.max_len);
+    }
+
+    /// Only accept writes from peers of exactly `len` bytes.
+    pub fn set_fixed_len(&mut self, len: usize) {
+        self.set_max_len(len);
+        self.fixed_len = true;
+    }
+
+    #[inline]
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    #[inline]
+    pub fn fixed_len(&self) -> Option<usize> {
+        self.fixed_len.then_some(self.max_len)
+    }
+
+    /// Whether a written value of this length satisfies the constraints.
+    #[inline]
+    pub fn accepts_len(&self, len: usize) -> bool {
+        if self.fixed_len {
+            len == self.max_len
+        } else {
+            len <= self.max_len
+        }
     }
 
     #[inline]
@@ -54,10 +106,18 @@ impl AttValue {
         self.value.clear();
     }
 
+    /// Replace the value. It is truncated to [`Self::max_len`] bytes.
     #[inline]
     pub fn set_value(&mut self, value: &[u8]) {
         self.value.clear();
-        self.value.extend_from_slice(value);
+        self.extend(value);
+    }
+
+    /// Replace the value like [`Self::set_value`], returning whether it changed.
+    pub(super) fn update(&mut self, value: &[u8]) -> bool {
+        let unchanged = self.value == value[..value.len().min(self.max_len)];
+        self.set_value(value);
+        !unchanged
     }
 
     #[deprecated(note = "Please use `set_value` + zerocopy::IntoBytes")]
@@ -69,8 +129,47 @@ impl AttValue {
         self.set_value(slice);
     }
 
+    /// Append to the value. The value is truncated to [`Self::max_len`] bytes.
     #[inline]
     pub fn extend(&mut self, value: &[u8]) {
-        self.value.extend_from_slice(value);
+        let len = value
+            .len()
+            .min(self.max_len.saturating_sub(self.value.len()));
+        if len < value.len() {
+            ::log::warn!("value truncated to {} bytes", self.max_len);
+        }
+        self.value.extend_from_slice(&value[..len]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncation() {
+        let mut value = AttValue::new();
+        value.set_value(&[0; ATTR_MAX_LEN + 1]);
+        assert_eq!(value.len(), ATTR_MAX_LEN);
+
+        value.set_max_len(4);
+        value.set_value(&[1, 2, 3]);
+        value.extend(&[4, 5]);
+        assert_eq!(value.as_slice(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn update() {
+        let mut value = AttValue::new();
+        assert!(!value.update(&[]));
+        assert!(value.update(&[1, 2]));
+        assert!(!value.update(&[1, 2]));
+        assert!(value.update(&[1]));
+
+        // Equal once truncated.
+        value.set_max_len(2);
+        value.set_value(&[1, 2]);
+        assert!(!value.update(&[1, 2, 3]));
+        assert_eq!(value.as_slice(), [1, 2]);
     }
 }
@@ -1,7 +1,88 @@
-use crate::{BLEDevice, enums::PowerType, utilities::BleUuid};
+use crate::{BLEDevice, BLEError, enums::PowerType, utilities::BleUuid};
 use alloc::{string::String, vec::Vec};
 use esp_idf_svc::sys as esp_idf_sys;
 
+const BLE_HS_ADV_MAX_SZ: usize = esp_idf_sys::BLE_HS_ADV_MAX_SZ as usize;
+
+/// A field of the advertisement data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AdField {
+    Flags,
+    ServiceUuids16,
+    ServiceUuids32,
+    ServiceUuids128,
+    Name,
+    TxPower,
+    ServiceData16,
+    Appearance,
+    ServiceData32,
+    ServiceData128,
+    ManufacturerData,
+}
+
+impl AdField {
+    /// All fields in the order they are encoded.
+    pub const ALL: [AdField; 11] = [
+        AdField::Flags,
+        AdField::ServiceUuids16,
+        AdField::ServiceUuids32,
+        AdField::ServiceUuids128,
+        AdField::Name,
+        AdField::TxPower,
+        AdField::ServiceData16,
+        AdField::Appearance,
+        AdField::ServiceData32,
+        AdField::ServiceData128,
+        AdField::ManufacturerData,
+    ];
+}
+
+/// An error returned when setting the advertisement data.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AdvDataError {
+    /// The advertisement data does not fit in a legacy advertising packet.
+    TooLarge {
+        /// The first field that does not fit.
+        field: AdField,
+        /// The encoded size of the whole advertisement data.
+        payload_len: usize,
+    },
+    /// The stack rejected the data.
+    Ble(BLEError),
+}
+
+impl core::fmt::Display for AdvDataError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TooLarge { field, payload_len } => write!(
+                f,
+                "{field:?} does not fit in the advertisement data ({payload_len} > {BLE_HS_ADV_MAX_SZ} bytes)"
+            ),
+            Self::Ble(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AdvDataError {}
+
+impl From<BLEError> for AdvDataError {
+    fn from(err: BLEError) -> Self {
+        Self::Ble(err)
+    }
+}
+
+impl From<AdvDataError> for BLEError {
+    fn from(err: AdvDataError) -> Self {
+        match err {
+            AdvDataError::TooLarge { .. } => {
+                BLEError::convert(esp_idf_sys::BLE_HS_EMSGSIZE).unwrap_err()
+            }
+            AdvDataError::Ble(err) => err,
+        }
+    }
+}
+
 pub struct BLEAdvertisementData {
     // 0x01 - Flags
     pub(crate) flags: u8,
@@ -126,56 +207,52 @@ impl BLEAdvertisementData {
         self
     }
 
-    pub(crate) fn payload_len(&self) -> usize {
-        let mut payload_len: usize = if self.flags > 0 { 2 + 1 } else { 0 };
-
-        if !self.service_uuids_16.is_empty() {
-            payload_len += 2 + 2 * self.service_uuids_16.len();
-        }
-        if !self.service_uuids_32.is_empty() {
-            payload_len += 2 + 4 * self.service_uuids_32.len();
-        }
-        if !self.service_uuids_128.is_empty() {
-            payload_len += 2 + 16 * self.service_uuids_128.len();
-        }
-
-        if !self.name.is_empty() {
-            payload_len += 2 + self.name.len();
-        }
-
-        if self.tx_pwr_lvl_is_present {
-            payload_len += 2 + (esp_idf_sys::BLE_HS_ADV_TX_PWR_LVL_LEN as usize);
-        }
-
-        if !self.svc_data_uuid16.is_empty() {
-            payload_len += 2 + self.svc_data_uuid16.len();
-        }
-
-        if !self.svc_data_uuid32.is_empty() {
-            payload_len += 2 + self.svc_data_uuid32.len();
-        }
+    /// Get the encoded size of the field, including its length and type bytes.
+    /// Returns 0 if the field is not set.
+    pub fn field_len(&self, field: AdField) -> usize {
+        let data_len = match field {
+            AdField::Flags => (self.flags > 0) as usize,
+            AdField::ServiceUuids16 => 2 * self.service_uuids_16.len(),
+            AdField::ServiceUuids32 => 4 * self.service_uuids_32.len(),
+            AdField::ServiceUuids128 => 16 * self.service_uuids_128.len(),
+            AdField::Name => self.name.len(),
+            AdField::TxPower => {
+                (esp_idf_sys::BLE_HS_ADV_TX_PWR_LVL_LEN as usize)
+                    * self.tx_pwr_lvl_is_present as usize
+            }
+            AdField::ServiceData16 => self.svc_data_uuid16.len(),
+            AdField::Appearance => {
+                (esp_idf_sys::BLE_HS_ADV_APPEARANCE_LEN as usize)
+                    * self.appearance.is_some() as usize
+            }
+            AdField::ServiceData32 => self.svc_data_uuid32.len(),
+            AdField::ServiceData128 => self.svc_data_uuid128.len(),
+            AdField::ManufacturerData => self.mfg_data.len(),
+        };
 
-        if !self.svc_data_uuid128.is_empty() {
-            payload_len += 2 + self.svc_data_uuid128.len();
-        }
+        if data_len > 0 { 2 + data_len } else { 0 }
+    }
 
-        if self.appearance.is_some() {
-            payload_len += 2 + (esp_idf_sys::BLE_HS_ADV_APPEARANCE_LEN as usize);
-        }
+    /// Get the encoded size of the advertisement data.
+    pub fn payload_len(&self) -> usize {
+        AdField::ALL.iter().map(|x| self.field_len(*x)).sum()
+    }
 
-        if !self.mfg_data.is_empty() {
-            payload_len += 2 + self.mfg_data.len();
+    /// Check that the data fits in a legacy advertising packet.
+    ///
+    /// Returns the first field, in encoding order, that does not fit anymore.
+    pub fn validate(&self) -> Result<(), AdvDataError> {
+        let mut payload_len = 0;
+        for field in AdField::ALL {
+            payload_len += self.field_len(field);
+            if payload_len > BLE_HS_ADV_MAX_SZ {
+                return Err(AdvDataError::TooLarge {
+                    field,
+                    payload_len: self.payload_len(),
+                });
+            }
         }
-
-        // if self.uri_len > 0 {
-        //   payload_len += 2 + adv_data.uri_len;
-        // }
-
-        // if !adv_data.slave_itvl_range.is_null() {
-        //   payload_len += 2 + (esp_idf_sys::BLE_HS_ADV_SLAVE_ITVL_RANGE_LEN as _);
-        // }
-
-        payload_len
+        Ok(())
     }
 
     pub(crate) fn as_ble_hs_adv_fields(&self) -> esp_idf_sys::ble_hs_adv_fields {
@@ -2,7 +2,7 @@ use core::ffi::{c_int, c_void};
 use esp_idf_svc::sys as esp_idf_sys;
 
 use crate::{
-    BLEAdvertisementData, BLEError, BLEServer, ble,
+    AdField, AdvDataError, BLEAddress, BLEAdvertisementData, BLEError, BLEServer, ble,
     enums::*,
     utilities::{as_void_ptr, voidp_to_ref},
 };
@@ -17,6 +17,9 @@ const BLE_HS_FOREVER: i32 = i32::MAX;
 pub struct BLEAdvertising {
     adv_params: esp_idf_sys::ble_gap_adv_params,
     scan_response: bool,
+    auto_scan_response: bool,
+    direct_addr: Option<BLEAddress>,
+    fast_reconnect: bool,
     on_complete: Option<Box<dyn FnMut(c_int) + Send + Sync>>,
 }
 
@@ -26,6 +29,9 @@ impl BLEAdvertising {
         let mut ret = Self {
             adv_params: esp_idf_sys::ble_gap_adv_params::default(),
             scan_response: true,
+            auto_scan_response: false,
+            direct_addr: None,
+            fast_reconnect: false,
             on_complete: None,
         };
 
@@ -41,11 +47,24 @@ impl BLEAdvertising {
         self.adv_params.conn_mode = esp_idf_sys::BLE_GAP_CONN_MODE_UND as _;
         self.adv_params.disc_mode = esp_idf_sys::BLE_GAP_DISC_MODE_GEN as _;
         self.scan_response = true;
+        self.auto_scan_response = false;
+        self.direct_addr = None;
 
         Ok(())
     }
 
+    /// Set the advertisement data.
+    ///
+    /// Use [`Self::try_set_data`] to find out which field does not fit.
     pub fn set_data(&mut self, data: &mut BLEAdvertisementData) -> Result<(), BLEError> {
+        Ok(self.try_set_data(data)?)
+    }
+
+    /// Set the advertisement data.
+    ///
+    /// Returns [`AdvDataError::TooLarge`] with the first field that does not fit
+    /// if the data cannot be advertised.
+    pub fn try_set_data(&mut self, data: &mut BLEAdvertisementData) -> Result<(), AdvDataError> {
         if self.adv_params.conn_mode == (ConnMode::Non as _) && !self.scan_response {
             data.flags = 0;
         } else {
@@ -58,7 +77,12 @@ impl BLEAdvertising {
 
         let mut payload_len = data.payload_len();
 
-        if payload_len > BLE_HS_ADV_MAX_SZ {
+        if payload_len > BLE_HS_ADV_MAX_SZ && self.auto_scan_response {
+            if !self.scan_response {
+                return Err(data.validate().unwrap_err());
+            }
+            Self::spill(data, &mut adv_data, &mut scan_data)?;
+        } else if payload_len > BLE_HS_ADV_MAX_SZ {
             if self.scan_response {
                 scan_data.name = adv_data.name;
                 scan_data.name_len = adv_data.name_len;
@@ -79,6 +103,11 @@ impl BLEAdvertising {
                 }
 
                 if payload_len > BLE_HS_ADV_MAX_SZ {
+                    if (adv_data.name_len as usize) < payload_len - BLE_HS_ADV_MAX_SZ {
+                        let err = data.validate().unwrap_err();
+                        ::log::error!("{err}");
+                        return Err(err);
+                    }
                     adv_data.name_len -= (payload_len - BLE_HS_ADV_MAX_SZ) as u8;
                     adv_data.set_name_is_complete(0);
                 }
@@ -90,10 +119,71 @@ impl BLEAdvertising {
                 ble!(esp_idf_sys::ble_gap_adv_rsp_set_fields(&scan_data))?;
             }
 
-            ble!(esp_idf_sys::ble_gap_adv_set_fields(&adv_data))
+            let rc = esp_idf_sys::ble_gap_adv_set_fields(&adv_data);
+            if rc == esp_idf_sys::BLE_HS_EMSGSIZE as _
+                && let Err(err) = data.validate()
+            {
+                ::log::error!("{err}");
+                return Err(err);
+            }
+            Ok(ble!(rc)?)
         }
     }
 
+    /// Move fields to the scan response until the advertisement data fits.
+    fn spill(
+        data: &BLEAdvertisementData,
+        adv_data: &mut esp_idf_sys::ble_hs_adv_fields,
+        scan_data: &mut esp_idf_sys::ble_hs_adv_fields,
+    ) -> Result<(), AdvDataError> {
+        let mut adv_len = data.payload_len();
+        let mut scan_len = 0;
+
+        // The name goes first, shortened in the scan response if needed.
+        let len = data.field_len(AdField::Name);
+        if adv_len > BLE_HS_ADV_MAX_SZ && len > 0 {
+            let name_len = (len - 2).min(BLE_HS_ADV_MAX_SZ - 2);
+            scan_data.name = adv_data.name;
+            scan_data.name_len = name_len as _;
+            scan_data.set_name_is_complete((name_len == len - 2) as _);
+            adv_data.name = core::ptr::null();
+            adv_data.name_len = 0;
+            scan_len += 2 + name_len;
+            adv_len -= len;
+        }
+
+        for field in SPILL_ORDER {
+            if adv_len <= BLE_HS_ADV_MAX_SZ {
+                break;
+            }
+
+            let len = data.field_len(field.into());
+            if len == 0 || scan_len + len > BLE_HS_ADV_MAX_SZ {
+                continue;
+            }
+            move_field(field, adv_data, scan_data);
+            scan_len += len;
+            adv_len -= len;
+        }
+
+        if adv_len > BLE_HS_ADV_MAX_SZ {
+            let err = data.validate().unwrap_err();
+            ::log::error!("{err}");
+            return Err(err);
+        }
+
+        // Advertise a shortened name if there is room left.
+        let name_len = data.field_len(AdField::Name);
+        if name_len > 0 && adv_data.name.is_null() && adv_len + 2 < BLE_HS_ADV_MAX_SZ {
+            let short_len = (name_len - 2).min(BLE_HS_ADV_MAX_SZ - adv_len - 2);
+            adv_data.name = scan_data.name;
+            adv_data.name_len = short_len as _;
+            adv_data.set_name_is_complete((short_len == name_len - 2) as _);
+        }
+
+        Ok(())
+    }
+
     pub fn set_raw_data(&mut self, data: &[u8]) -> Result<(), BLEError> {
         let rc =
             unsafe { esp_idf_sys::ble_gap_adv_set_data(data.as_ptr(), data.len() as i32) } as u32;
@@ -117,8 +207,26 @@ impl BLEAdvertising {
     }
 
     /// Set the type of advertisment to use.
+    ///
+    /// Any other type than [`ConnMode::Dir`] drops the peer and duty cycle set by [`Self::directed`].
     pub fn advertisement_type(&mut self, adv_type: ConnMode) -> &mut Self {
         self.adv_params.conn_mode = adv_type as _;
+        if adv_type != ConnMode::Dir {
+            self.adv_params.set_high_duty_cycle(0);
+            self.direct_addr = None;
+        }
+        self
+    }
+
+    /// Use directed connectable advertising to the peer.
+    ///
+    /// High duty cycle directed advertising is stopped by the controller after 1.28 seconds.
+    ///
+    /// * `addr`: The address of the peer, or its identity address if it is bonded.
+    pub fn directed(&mut self, addr: &BLEAddress, high_duty_cycle: bool) -> &mut Self {
+        self.adv_params.conn_mode = ConnMode::Dir as _;
+        self.adv_params.set_high_duty_cycle(high_duty_cycle as _);
+        self.direct_addr = Some(*addr);
         self
     }
 
@@ -152,12 +260,28 @@ impl BLEAdvertising {
         self
     }
 
+    /// The minimum and maximum advertising intervals.
+    pub(crate) fn intervals(&self) -> (u16, u16) {
+        (self.adv_params.itvl_min, self.adv_params.itvl_max)
+    }
+
     /// Set if scan response is available.
     pub fn scan_response(&mut self, value: bool) -> &mut Self {
         self.scan_response = value;
         self
     }
 
+    /// Move fields to the scan response when the advertisement data is too large,
+    /// instead of only moving the name.
+    ///
+    /// Fields are moved in this order: name (a shortened name is advertised if there is room),
+    /// 128-bit and 32-bit service UUIDs, TX power, appearance, service data, manufacturer data,
+    /// 16-bit service UUIDs. Requires the scan response.
+    pub fn auto_scan_response(&mut self, value: bool) -> &mut Self {
+        self.auto_scan_response = value;
+        self
+    }
+
     /// Set the filtering for the scan filter.
     pub fn filter_policy(&mut self, value: AdvFilterPolicy) -> &mut Self {
         self.adv_params.filter_policy = value.into();
@@ -185,6 +309,17 @@ impl BLEAdvertising {
             self.adv_params.disc_mode = esp_idf_sys::BLE_GAP_DISC_MODE_GEN as _;
         }
 
+        self.fast_reconnect = false;
+
+        let direct_addr = if self.adv_params.conn_mode == (ConnMode::Dir as _) {
+            let Some(addr) = self.direct_addr else {
+                return BLEError::convert(esp_idf_sys::BLE_HS_EINVAL);
+            };
+            Some(esp_idf_sys::ble_addr_t::from(addr))
+        } else {
+            None
+        };
+
         let handle_gap_event = if server.is_some() {
             BLEServer::handle_gap_event
         } else {
@@ -193,7 +328,9 @@ impl BLEAdvertising {
         unsafe {
             ble!(esp_idf_sys::ble_gap_adv_start(
                 crate::ble_device::OWN_ADDR_TYPE as _,
-                core::ptr::null(),
+                direct_addr
+                    .as_ref()
+                    .map_or(core::ptr::null(), |x| x as *const _),
                 duration_ms,
                 &self.adv_params,
                 Some(handle_gap_event),
@@ -204,6 +341,21 @@ impl BLEAdvertising {
         Ok(())
     }
 
+    /// Advertise to the peer with high duty cycle directed advertising.
+    /// When it times out, the configured advertising is started.
+    pub(crate) fn start_fast_reconnect(&mut self, addr: &BLEAddress) -> Result<(), BLEError> {
+        let adv_params = self.adv_params;
+        let direct_addr = self.direct_addr;
+
+        self.directed(addr, true);
+        let result = self.start_with_duration(BLE_HS_FOREVER);
+
+        self.adv_params = adv_params;
+        self.direct_addr = direct_addr;
+        self.fast_reconnect = result.is_ok();
+        result
+    }
+
     pub fn stop(&self) -> Result<(), BLEError> {
         unsafe { ble!(esp_idf_sys::ble_gap_adv_stop()) }
     }
@@ -227,6 +379,16 @@ impl BLEAdvertising {
         let event = unsafe { &*event };
         let adv = unsafe { voidp_to_ref::<Self>(arg) };
 
+        if event.type_ == esp_idf_sys::BLE_GAP_EVENT_ADV_COMPLETE as _ && adv.fast_reconnect {
+            adv.fast_reconnect = false;
+            if unsafe { event.__bindgen_anon_1.adv_complete.reason } != 0
+                && let Err(err) = adv.start()
+            {
+                ::log::warn!("can't start advertising: {err:?}");
+            }
+            return 0;
+        }
+
         if event.type_ == esp_idf_sys::BLE_GAP_EVENT_ADV_COMPLETE as _
             && let Some(callback) = adv.on_complete.as_mut()
         {
@@ -238,3 +400,109 @@ impl BLEAdvertising {
 }
 
 unsafe impl Send for BLEAdvertising {}
+
+/// The fields that can be moved to the scan response as a whole.
+#[derive(Copy, Clone)]
+enum SpillField {
+    ServiceUuids16,
+    ServiceUuids32,
+    ServiceUuids128,
+    TxPower,
+    ServiceData16,
+    Appearance,
+    ServiceData32,
+    ServiceData128,
+    ManufacturerData,
+}
+
+impl From<SpillField> for AdField {
+    fn from(field: SpillField) -> Self {
+        match field {
+            SpillField::ServiceUuids16 => AdField::ServiceUuids16,
+            SpillField::ServiceUuids32 => AdField::ServiceUuids32,
+            SpillField::ServiceUuids128 => AdField::ServiceUuids128,
+            SpillField::TxPower => AdField::TxPower,
+            SpillField::ServiceData16 => AdField::ServiceData16,
+            SpillField::Appearance => AdField::Appearance,
+            SpillField::ServiceData32 => AdField::ServiceData32,
+            SpillField::ServiceData128 => AdField::ServiceData128,
+            SpillField::ManufacturerData => AdField::ManufacturerData,
+        }
+    }
+}
+
+const SPILL_ORDER: [SpillField; 9] = [
+    SpillField::ServiceUuids128,
+    SpillField::ServiceUuids32,
+    SpillField::TxPower,
+    SpillField::Appearance,
+    SpillField::ServiceData128,
+    SpillField::ServiceData32,
+    SpillField::ServiceData16,
+    SpillField::ManufacturerData,
+    SpillField::ServiceUuids16,
+];
+
+fn move_field(
+    field: SpillField,
+    from: &mut esp_idf_sys::ble_hs_adv_fields,
+    to: &mut esp_idf_sys::ble_hs_adv_fields,
+) {
+    match field {
+        SpillField::ServiceUuids16 => {
+            to.uuids16 = from.uuids16;
+            to.num_uuids16 = from.num_uuids16;
+            to.set_uuids16_is_complete(from.uuids16_is_complete());
+            from.uuids16 = core::ptr::null();
+            from.num_uuids16 = 0;
+        }
+        SpillField::ServiceUuids32 => {
+            to.uuids32 = from.uuids32;
+            to.num_uuids32 = from.num_uuids32;
+            to.set_uuids32_is_complete(from.uuids32_is_complete());
+            from.uuids32 = core::ptr::null();
+            from.num_uuids32 = 0;
+        }
+        SpillField::ServiceUuids128 => {
+            to.uuids128 = from.uuids128;
+            to.num_uuids128 = from.num_uuids128;
+            to.set_uuids128_is_complete(from.uuids128_is_complete());
+            from.uuids128 = core::ptr::null();
+            from.num_uuids128 = 0;
+        }
+        SpillField::TxPower => {
+            to.tx_pwr_lvl = from.tx_pwr_lvl;
+            to.set_tx_pwr_lvl_is_present(1);
+            from.set_tx_pwr_lvl_is_present(0);
+        }
+        SpillField::ServiceData16 => {
+            to.svc_data_uuid16 = from.svc_data_uuid16;
+            to.svc_data_uuid16_len = from.svc_data_uuid16_len;
+            from.svc_data_uuid16 = core::ptr::null();
+            from.svc_data_uuid16_len = 0;
+        }
+        SpillField::Appearance => {
+            to.appearance = from.appearance;
+            to.set_appearance_is_present(1);
+            from.set_appearance_is_present(0);
+        }
+        SpillField::ServiceData32 => {
+            to.svc_data_uuid32 = from.svc_data_uuid32;
+            to.svc_data_uuid32_len = from.svc_data_uuid32_len;
+            from.svc_data_uuid32 = core::ptr::null();
+            from.svc_data_uuid32_len = 0;
+        }
+        SpillField::ServiceData128 => {
+            to.svc_data_uuid128 = from.svc_data_uuid128;
+            to.svc_data_uuid128_len = from.svc_data_uuid128_len;
+            from.svc_data_uuid128 = core::ptr::null();
+            from.svc_data_uuid128_len = 0;
+        }
+        SpillField::ManufacturerData => {
+            to.mfg_data = from.mfg_data;
+            to.mfg_data_len = from.mfg_data_len;
+            from.mfg_data = core::ptr::null();
+            from.mfg_data_len = 0;
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    BLEAdvertisementData, BLEAdvertising, BLEDevice, BLEError,
+    enums::ConnMode,
+    utilities::{delay_ms, mutex::Mutex},
+};
+use alloc::{boxed::Box, vec::Vec};
+use esp_idf_svc::sys as esp_idf_sys;
+
+/// A payload advertised by [`BLEAdvertisingScheduler`].
+pub struct AdvertisingSlot {
+    data: BLEAdvertisementData,
+    conn_mode: ConnMode,
+    scan_response: bool,
+    duration_ms: u32,
+    min_interval: u16,
+    max_interval: u16,
+    on_update: Option<Box<dyn FnMut(&mut BLEAdvertisementData) + Send + Sync>>,
+}
+
+impl AdvertisingSlot {
+    fn new(data: BLEAdvertisementData) -> Self {
+        Self {
+            data,
+            conn_mode: ConnMode::Und,
+            scan_response: true,
+            duration_ms: 1000,
+            min_interval: 0,
+            max_interval: 0,
+            on_update: None,
+        }
+    }
+
+    /// Set the type of advertisment to use.
+    pub fn advertisement_type(&mut self, adv_type: ConnMode) -> &mut Self {
+        self.conn_mode = adv_type;
+        self
+    }
+
+    /// Set if scan response is available.
+    pub fn scan_response(&mut self, value: bool) -> &mut Self {
+        self.scan_response = value;
+        self
+    }
+
+    /// Set how long the slot is advertised before switching to the next slot.
+    pub fn duration_ms(&mut self, duration_ms: u32) -> &mut Self {
+        self.duration_ms = duration_ms;
+        self
+    }
+
+    /// Set the minimum advertising interval.
+    ///
+    /// * `interval`: advertising interval in 0.625ms units,
+    ///   0 = use the interval set on `BLEAdvertising` when the scheduler started.
+    pub fn min_interval(&mut self, interval: u16) -> &mut Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Set the maximum advertising interval.
+    ///
+    /// * `interval`: advertising interval in 0.625ms units,
+    ///   0 = use the interval set on `BLEAdvertising` when the scheduler started.
+    pub fn max_interval(&mut self, interval: u16) -> &mut Self {
+        self.max_interval = interval;
+        self
+    }
+
+    /// Called every time before the slot is advertised, to refresh the data.
+    pub fn on_update(
+        &mut self,
+        callback: impl FnMut(&mut BLEAdvertisementData) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_update = Some(Box::new(callback));
+        self
+    }
+
+    fn start(
+        &mut self,
+        advertising: &Mutex<BLEAdvertising>,
+        (min_interval, max_interval): (u16, u16),
+    ) -> Result<(), BLEError> {
+        if let Some(callback) = self.on_update.as_mut() {
+            callback(&mut self.data);
+        }
+
+        let mut advertising = advertising.lock();
+        if advertising.is_advertising() {
+            advertising.stop()?;
+        }
+
+        advertising
+            .advertisement_type(self.conn_mode)
+            .scan_response(self.scan_response)
+            .min_interval(match self.min_interval {
+                0 => min_interval,
+                x => x,
+            })
+            .max_interval(match self.max_interval {
+                0 => max_interval,
+                x => x,
+            });
+        advertising.set_data(&mut self.data)?;
+        advertising.start()
+    }
+}
+
+/// Rotates several legacy advertising payloads.
+///
+/// Each slot is advertised for its duration, then the next slot is started.
+/// If a connection is made while a connectable slot is advertised,
+/// `BLEServer::advertise_on_disconnect` restarts that slot after the disconnection
+/// until the scheduler switches to the next slot.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut scheduler = BLEAdvertisingScheduler::new();
+/// scheduler.add_slot(service_data).duration_ms(2000);
+/// scheduler
+///   .add_slot(ibeacon.advertisement_data())
+///   .advertisement_type(ConnMode::Non)
+///   .scan_response(false);
+/// scheduler
+///   .add_slot(tlm.advertisement_data())
+///   .advertisement_type(ConnMode::Non)
+///   .scan_response(false)
+///   .on_update(move |data| {
+///     tlm.battery_mv = read_battery();
+///     *data = tlm.advertisement_data();
+///   });
+/// scheduler.run().await?;
+/// ```
+pub struct BLEAdvertisingScheduler {
+    advertising: &'static Mutex<BLEAdvertising>,
+    slots: Vec<AdvertisingSlot>,
+}
+
+impl BLEAdvertisingScheduler {
+    pub fn new() -> Self {
+        Self {
+            advertising: BLEDevice::take().get_advertising(),
+            slots: Vec::new(),
+        }
+    }
+
+    /// Add a slot. Slots are advertised in the order they were added.
+    pub fn add_slot(&mut self, data: BLEAdvertisementData) -> &mut AdvertisingSlot {
+        self.slots.push(AdvertisingSlot::new(data));
+        self.slots.last_mut().unwrap()
+    }
+
+    /// Rotate the slots.
+    ///
+    /// A slot that fails to start is skipped for its duration.
+    /// Drop the future to stop rotating; the current slot stays advertised until it is stopped.
+    pub async fn run(&mut self) -> Result<(), BLEError> {
+        if self.slots.is_empty() {
+            return BLEError::convert(esp_idf_sys::BLE_HS_EINVAL);
+        }
+
+        let intervals = self.advertising.lock().intervals();
+        loop {
+            for slot in self.slots.iter_mut() {
+                if let Err(err) = slot.start(self.advertising, intervals) {
+                    ::log::warn!("can't start advertising slot: {err:?}");
+                }
+                delay_ms(slot.duration_ms).await?;
+            }
+        }
+    }
+}
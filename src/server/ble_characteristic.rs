@@ -1,20 +1,30 @@
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use bitflags::bitflags;
-use core::{cell::UnsafeCell, ffi::c_void};
+use core::{cell::UnsafeCell, ffi::c_void, future::poll_fn, pin::pin, task::Poll};
 use esp_idf_svc::sys;
-#[cfg(not(cpfd))]
 use zerocopy::IntoBytes;
 
 use crate::{
     AttValue, BLEConnDesc, BLEDescriptor, BLEDevice, BLEError, DescriptorProperties, OnWriteArgs,
-    ble,
+    PreparedWrite, WriteStream, ble,
     cpfd::Cpfd,
+    descriptors::{
+        AGGREGATE_FORMAT_UUID16, ES_CONFIGURATION_UUID16, ES_MEASUREMENT_UUID16,
+        ES_TRIGGER_SETTING_UUID16, EXTENDED_PROPERTIES_UUID16, EsConfiguration, EsMeasurement,
+        EsTriggerSetting, ExtendedProperties, OUT_OF_RANGE, PRESENTATION_FORMAT_UUID16,
+        REPORT_REFERENCE_UUID16, ReportType, SERVER_CONFIGURATION_UUID16, USER_DESCRIPTION_UUID16,
+        VALID_RANGE_UUID16, ValidRange,
+    },
     utilities::{
-        BleUuid, OsMBuf, ble_npl_hw_enter_critical, ble_npl_hw_exit_critical, mutex::Mutex,
+        BleUuid, OsMBuf, ble_npl_hw_enter_critical, ble_npl_hw_exit_critical, delay_ms,
+        fragmentation::{FragmentFormat, fragments},
+        mutex::Mutex,
         voidp_to_ref,
     },
 };
 
+use super::NULL_HANDLE;
+
 cfg_if::cfg_if! {
   if #[cfg(any(
     all(
@@ -40,8 +50,6 @@ cfg_if::cfg_if! {
   }
 }
 
-const NULL_HANDLE: u16 = 0xFFFF;
-
 cfg_if::cfg_if! {
   if #[cfg(any(
     all(
@@ -87,6 +95,10 @@ bitflags! {
     const NOTIFY = sys::BLE_GATT_CHR_F_NOTIFY as _;
     /// Indications are Sent from Server to Client where Server expects a Response
     const INDICATE = sys::BLE_GATT_CHR_F_INDICATE as _;
+    /// Reliable Writes Permitted
+    const RELIABLE_WRITE = sys::BLE_GATT_CHR_F_RELIABLE_WRITE as _;
+    /// Writable Auxiliaries Permitted
+    const AUX_WRITE = sys::BLE_GATT_CHR_F_AUX_WRITE as _;
 
     #[cfg(all(
       esp_idf_version_major = "5",
@@ -166,12 +178,21 @@ bitflags! {
 pub struct BLECharacteristic {
     pub(crate) uuid: sys::ble_uuid_any_t,
     pub(crate) handle: u16,
+    pub(crate) def_handle: u16,
     pub(crate) properties: NimbleProperties,
     value: AttValue,
+    per_connection_value: bool,
+    conn_values: Vec<(u16, AttValue)>,
     on_read: Option<Box<dyn FnMut(&mut Self, &BLEConnDesc) + Send + Sync>>,
     on_write: Option<Box<dyn FnMut(&mut OnWriteArgs) + Send + Sync>>,
+    on_validate: Option<Box<dyn FnMut(&[u8], &BLEConnDesc) -> Result<(), u8> + Send + Sync>>,
+    on_value_changed: Option<Box<dyn FnMut(&[u8], Option<u16>) + Send + Sync>>,
+    on_fast_write: Option<Box<dyn Fn(u16, &[u8]) + Send + Sync>>,
     pub(crate) on_notify_tx: Option<Box<dyn FnMut(NotifyTx) + Send + Sync>>,
-    descriptors: Vec<Arc<Mutex<BLEDescriptor>>>,
+    pub(crate) descriptors: Vec<Arc<Mutex<BLEDescriptor>>>,
+    valid_range: Option<ValidRange>,
+    cpfd_descriptors: Vec<Arc<Mutex<BLEDescriptor>>>,
+    aggregate_format: Option<Arc<Mutex<BLEDescriptor>>>,
     svc_def_descriptors: Vec<sys::ble_gatt_dsc_def>,
     subscribed_list: Vec<(u16, NimbleSub)>,
     on_subscribe: Option<Box<dyn FnMut(&Self, &BLEConnDesc, NimbleSub) + Send + Sync>>,
@@ -184,12 +205,21 @@ impl BLECharacteristic {
         Self {
             uuid: sys::ble_uuid_any_t::from(uuid),
             handle: NULL_HANDLE,
+            def_handle: NULL_HANDLE,
             properties,
             value: AttValue::new(),
+            per_connection_value: false,
+            conn_values: Vec::new(),
             on_read: None,
             on_write: None,
+            on_validate: None,
+            on_value_changed: None,
+            on_fast_write: None,
             on_notify_tx: None,
             descriptors: Vec::new(),
+            valid_range: None,
+            cpfd_descriptors: Vec::new(),
+            aggregate_format: None,
             svc_def_descriptors: Vec::new(),
             subscribed_list: Vec::new(),
             on_subscribe: None,
@@ -202,8 +232,34 @@ impl BLECharacteristic {
         BleUuid::from(self.uuid)
     }
 
+    /// Set the value.
+    ///
+    /// Values longer than the maximum length, 512 bytes unless limited with [`Self::max_len`],
+    /// are truncated and a warning is logged.
     pub fn set_value(&mut self, value: &[u8]) -> &mut Self {
-        self.value.set_value(value);
+        if self.value.update(value)
+            && let Some(callback) = &mut self.on_value_changed
+        {
+            callback(self.value.as_slice(), None);
+        }
+        self
+    }
+
+    /// Reject writes longer than `max_len` with `BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN`.
+    pub fn max_len(&mut self, max_len: usize) -> &mut Self {
+        self.value.set_max_len(max_len);
+        for (_, conn_value) in &mut self.conn_values {
+            conn_value.set_max_len(max_len);
+        }
+        self
+    }
+
+    /// Reject writes of any other length than `len` with `BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN`.
+    pub fn fixed_len(&mut self, len: usize) -> &mut Self {
+        self.value.set_fixed_len(len);
+        for (_, conn_value) in &mut self.conn_values {
+            conn_value.set_fixed_len(len);
+        }
         self
     }
 
@@ -218,6 +274,47 @@ impl BLECharacteristic {
         &mut self.value
     }
 
+    /// Give each connected peer its own copy of the value.
+    ///
+    /// The copy is a snapshot of the value set with [`Self::set_value`] when the peer connects,
+    /// or, for peers connected before this is enabled, on the first write from the peer or
+    /// [`Self::set_value_for`]. It is dropped when the peer disconnects.
+    /// Reads, writes and [`Self::notify`] use the copy of the peer.
+    pub fn per_connection_value(&mut self, enable: bool) -> &mut Self {
+        self.per_connection_value = enable;
+        if !enable {
+            self.conn_values.clear();
+        }
+        self
+    }
+
+    /// The value seen by the connection.
+    /// Returns the default value if the peer has no copy of its own.
+    pub fn value_for(&self, conn_handle: u16) -> &[u8] {
+        self.conn_values
+            .iter()
+            .find(|x| x.0 == conn_handle)
+            .map_or(self.value.as_slice(), |x| x.1.as_slice())
+    }
+
+    /// Set the value seen by the connection.
+    /// Sets the shared value if per-connection values are disabled.
+    /// Long values are truncated like with [`Self::set_value`].
+    pub fn set_value_for(&mut self, conn_handle: u16, value: &[u8]) -> &mut Self {
+        let (changed, value) = if !self.per_connection_value {
+            (self.value.update(value), &self.value)
+        } else {
+            let idx = self.conn_value_idx(conn_handle);
+            let changed = self.conn_values[idx].1.update(value);
+            (changed, &self.conn_values[idx].1)
+        };
+
+        if changed && let Some(callback) = &mut self.on_value_changed {
+            callback(value.as_slice(), Some(conn_handle));
+        }
+        self
+    }
+
     pub fn on_read(
         &mut self,
         callback: impl FnMut(&mut Self, &BLEConnDesc) + Send + Sync + 'static,
@@ -227,6 +324,12 @@ impl BLECharacteristic {
     }
 
     /// This characteristic is locked while the callback is executing. If you call `.lock()` on this characteristic from inside the callback, it will never execute.
+    ///
+    /// Prepared writes (long writes and reliable writes) are queued by NimBLE.
+    /// The callback is called once with the reassembled value when the client executes the queue.
+    /// Rejecting the value makes the "Execute Write" request fail with the given error code,
+    /// but the queued writes to other characteristics applied before it are not rolled back,
+    /// unless they are held for [`crate::BLEServer::on_execute_write`].
     pub fn on_write(
         &mut self,
         callback: impl FnMut(&mut OnWriteArgs) + Send + Sync + 'static,
@@ -235,6 +338,62 @@ impl BLECharacteristic {
         self
     }
 
+    /// Validate written values before `on_write` is called.
+    /// Return an ATT error code to reject the value.
+    ///
+    /// The length constraints and the valid range are checked first.
+    pub fn on_validate(
+        &mut self,
+        callback: impl FnMut(&[u8], &BLEConnDesc) -> Result<(), u8> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_validate = Some(Box::new(callback));
+        self
+    }
+
+    /// Called after the value is changed by a write from a peer, [`Self::set_value`]
+    /// or [`Self::set_value_for`].
+    ///
+    /// The connection handle is the peer that wrote the value or the connection passed to
+    /// [`Self::set_value_for`], and `None` for [`Self::set_value`].
+    ///
+    /// Not called if the new value equals the current one, e.g. when `on_read` refreshes
+    /// a value that did not change.
+    pub fn on_value_changed(
+        &mut self,
+        callback: impl FnMut(&[u8], Option<u16>) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_value_changed = Some(Box::new(callback));
+        self
+    }
+
+    /// Hand written values to the callback with the connection handle,
+    /// without locking this characteristic or storing the value.
+    ///
+    /// Intended for high throughput Write Without Response streams.
+    /// The value is a zero-copy slice of the received buffer unless it is fragmented.
+    /// Length constraints, validators, `on_write` and `on_value_changed` are skipped.
+    /// The callback runs in the NimBLE host task and is read without locking,
+    /// so it can only be set before the server is started, `BLE_HS_EBUSY` is returned after.
+    pub fn on_fast_write(
+        &mut self,
+        callback: impl Fn(u16, &[u8]) + Send + Sync + 'static,
+    ) -> Result<&mut Self, BLEError> {
+        if self.handle != NULL_HANDLE {
+            return Err(BLEError::convert(sys::BLE_HS_EBUSY).unwrap_err());
+        }
+
+        self.on_fast_write = Some(Box::new(callback));
+        Ok(self)
+    }
+
+    /// Push written values into the stream, see [`Self::on_fast_write`].
+    pub fn fast_write_stream<const N: usize>(
+        &mut self,
+        stream: &'static WriteStream<N>,
+    ) -> Result<&mut Self, BLEError> {
+        self.on_fast_write(move |conn_handle, value| stream.push(conn_handle, value))
+    }
+
     pub fn on_notify_tx(
         &mut self,
         callback: impl FnMut(NotifyTx) + Send + Sync + 'static,
@@ -257,7 +416,158 @@ impl BLECharacteristic {
         descriptor
     }
 
+    /// Create a Characteristic User Description descriptor (0x2901).
+    ///
+    /// If `writable`, clients can change the description,
+    /// and the Writable Auxiliaries extended property is set.
+    pub fn create_user_description(
+        &mut self,
+        description: &str,
+        writable: bool,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        let mut properties = DescriptorProperties::READ;
+        if writable {
+            properties |= DescriptorProperties::WRITE;
+            self.extended_properties(ExtendedProperties::WRITABLE_AUXILIARIES);
+        }
+
+        let descriptor =
+            self.create_descriptor(BleUuid::Uuid16(USER_DESCRIPTION_UUID16), properties);
+        descriptor.lock().set_value(description.as_bytes());
+        descriptor
+    }
+
+    /// Create a Valid Range descriptor (0x2906).
+    ///
+    /// Written values of a different size or outside of the range
+    /// are rejected with [`OUT_OF_RANGE`] before `on_write` is called.
+    /// Calling it again replaces the range and updates the existing descriptor.
+    pub fn create_valid_range(&mut self, range: ValidRange) -> Arc<Mutex<BLEDescriptor>> {
+        self.valid_range = Some(range);
+
+        let uuid = BleUuid::Uuid16(VALID_RANGE_UUID16);
+        let descriptor = match self.descriptors.iter().find(|x| x.lock().uuid() == uuid) {
+            Some(descriptor) => descriptor.clone(),
+            None => self.create_descriptor(uuid, DescriptorProperties::READ),
+        };
+        descriptor.lock().set_value(&range.value());
+        descriptor
+    }
+
+    /// Add extended properties, and the Characteristic Extended Properties descriptor (0x2900).
+    pub fn extended_properties(
+        &mut self,
+        properties: ExtendedProperties,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        if properties.contains(ExtendedProperties::RELIABLE_WRITE) {
+            self.properties.insert(NimbleProperties::RELIABLE_WRITE);
+        }
+        if properties.contains(ExtendedProperties::WRITABLE_AUXILIARIES) {
+            self.properties.insert(NimbleProperties::AUX_WRITE);
+        }
+
+        let uuid = BleUuid::Uuid16(EXTENDED_PROPERTIES_UUID16);
+        let descriptor = match self.descriptors.iter().find(|x| x.lock().uuid() == uuid) {
+            Some(descriptor) => descriptor.clone(),
+            None => self.create_descriptor(uuid, DescriptorProperties::READ),
+        };
+
+        {
+            let mut descriptor = descriptor.lock();
+            let current = descriptor
+                .value_mut()
+                .as_slice()
+                .try_into()
+                .map_or(0, u16::from_le_bytes);
+            let value = ExtendedProperties::from_bits_truncate(current) | properties;
+            descriptor.set_value(&value.bits().to_le_bytes());
+        }
+        descriptor
+    }
+
+    /// Create a Report Reference descriptor (0x2908).
+    pub fn create_report_reference(
+        &mut self,
+        report_id: u8,
+        report_type: ReportType,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        let descriptor = self.create_descriptor(
+            BleUuid::Uuid16(REPORT_REFERENCE_UUID16),
+            DescriptorProperties::READ,
+        );
+        descriptor.lock().set_value(&[report_id, report_type as u8]);
+        descriptor
+    }
+
+    /// Create an Environmental Sensing Measurement descriptor (0x290C).
+    pub fn create_es_measurement(
+        &mut self,
+        measurement: &EsMeasurement,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        let descriptor = self.create_descriptor(
+            BleUuid::Uuid16(ES_MEASUREMENT_UUID16),
+            DescriptorProperties::READ,
+        );
+        descriptor.lock().set_value(&measurement.value());
+        descriptor
+    }
+
+    /// Create an Environmental Sensing Trigger Setting descriptor (0x290D).
+    ///
+    /// A characteristic can have up to 3 trigger settings.
+    pub fn create_es_trigger_setting(
+        &mut self,
+        trigger: &EsTriggerSetting,
+        writable: bool,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        let mut properties = DescriptorProperties::READ;
+        if writable {
+            properties |= DescriptorProperties::WRITE;
+        }
+
+        let descriptor =
+            self.create_descriptor(BleUuid::Uuid16(ES_TRIGGER_SETTING_UUID16), properties);
+        descriptor.lock().set_value(&trigger.value());
+        descriptor
+    }
+
+    /// Create an Environmental Sensing Configuration descriptor (0x290B).
+    pub fn create_es_configuration(
+        &mut self,
+        configuration: EsConfiguration,
+        writable: bool,
+    ) -> Arc<Mutex<BLEDescriptor>> {
+        let mut properties = DescriptorProperties::READ;
+        if writable {
+            properties |= DescriptorProperties::WRITE;
+        }
+
+        let descriptor =
+            self.create_descriptor(BleUuid::Uuid16(ES_CONFIGURATION_UUID16), properties);
+        descriptor.lock().set_value(&[configuration as u8]);
+        descriptor
+    }
+
+    /// Create a Server Characteristic Configuration descriptor (0x2903),
+    /// and set the broadcast property.
+    ///
+    /// Clients enable broadcasting by writing [`crate::descriptors::ServerConfiguration::BROADCAST`].
+    /// Use `on_write` of the descriptor to add the value to the advertisement data.
+    pub fn create_server_configuration(&mut self) -> Arc<Mutex<BLEDescriptor>> {
+        self.properties.insert(NimbleProperties::BROADCAST);
+
+        let descriptor = self.create_descriptor(
+            BleUuid::Uuid16(SERVER_CONFIGURATION_UUID16),
+            DescriptorProperties::READ | DescriptorProperties::WRITE,
+        );
+        descriptor.lock().set_value(&[0, 0]);
+        descriptor
+    }
+
     pub(crate) fn construct_svc_def_descriptors(&mut self) -> *mut sys::ble_gatt_dsc_def {
+        #[cfg(cpfd)]
+        self.move_cpfd_to_aggregate();
+
         if self.descriptors.is_empty() {
             return core::ptr::null_mut();
         }
@@ -289,7 +599,7 @@ impl BLECharacteristic {
 
     pub fn notify(&self) {
         for it in &self.subscribed_list {
-            if let Err(err) = self.send_value(self.value.as_slice(), it.0, it.1) {
+            if let Err(err) = self.send_value(self.value_for(it.0), it.0, it.1) {
                 ::log::warn!("notify error({}): {:?}", it.0, err);
             }
         }
@@ -305,27 +615,134 @@ impl BLECharacteristic {
         if flag.contains(NimbleSub::INDICATE)
             && self.properties.contains(NimbleProperties::INDICATE)
         {
-            if !server.set_indicate_wait(conn_handle) {
+            if server.indicate_pending(conn_handle) {
                 ::log::error!("prior Indication in progress");
                 return BLEError::convert(sys::BLE_HS_EBUSY);
             }
 
             let om = OsMBuf::from_flat(value);
-            let rc = unsafe { sys::ble_gatts_indicate_custom(conn_handle, self.handle, om.0) };
-            if rc != 0 {
-                server.clear_indicate_wait(conn_handle);
+            if om.0.is_null() {
+                return BLEError::convert(sys::BLE_HS_ENOMEM);
             }
-            BLEError::convert(rc as _)
+
+            ble!(unsafe { sys::ble_gatts_indicate_custom(conn_handle, self.handle, om.0) })
         } else if flag.contains(NimbleSub::NOTIFY)
             && self.properties.contains(NimbleProperties::NOTIFY)
         {
             let om = OsMBuf::from_flat(value);
+            if om.0.is_null() {
+                return BLEError::convert(sys::BLE_HS_ENOMEM);
+            }
             ble!(unsafe { sys::ble_gatts_notify_custom(conn_handle, self.handle, om.0) })
         } else {
             BLEError::convert(sys::BLE_HS_EINVAL)
         }
     }
 
+    /// Notify the value to all subscribers that enabled notifications.
+    ///
+    /// Waits for free mbufs instead of failing when the host is out of buffers.
+    /// The characteristic is not locked while waiting.
+    /// Returns the result for each subscriber.
+    pub async fn notify_async(
+        characteristic: &Mutex<Self>,
+        value: &[u8],
+    ) -> Vec<(u16, Result<(), BLEError>)> {
+        Self::send_async(characteristic, value, NimbleSub::NOTIFY).await
+    }
+
+    /// Indicate the value to all subscribers that enabled indications.
+    ///
+    /// Waits for free mbufs, for prior indications on the same connection,
+    /// and for the confirmation of the peer.
+    /// The characteristic is not locked while waiting.
+    /// Returns the result for each subscriber.
+    pub async fn indicate_async(
+        characteristic: &Mutex<Self>,
+        value: &[u8],
+    ) -> Vec<(u16, Result<(), BLEError>)> {
+        Self::send_async(characteristic, value, NimbleSub::INDICATE).await
+    }
+
+    async fn send_async(
+        characteristic: &Mutex<Self>,
+        value: &[u8],
+        flag: NimbleSub,
+    ) -> Vec<(u16, Result<(), BLEError>)> {
+        let (handle, subscribers) = {
+            let characteristic = characteristic.lock();
+            let subscribers: Vec<u16> = characteristic
+                .subscribed_list
+                .iter()
+                .filter(|x| x.1.contains(flag))
+                .map(|x| x.0)
+                .collect();
+            (characteristic.handle, subscribers)
+        };
+
+        let mut results = Vec::with_capacity(subscribers.len());
+        for conn_handle in subscribers {
+            let result = send_value_async(handle, value, conn_handle, flag).await;
+            results.push((conn_handle, result));
+        }
+        results
+    }
+
+    /// Notify a value of any length to all subscribers that enabled notifications,
+    /// split into fragments that fit the MTU of each connection.
+    ///
+    /// Use [`crate::BLERemoteCharacteristic::on_notify_fragmented`] with the same format to reassemble it.
+    pub async fn notify_fragmented(
+        characteristic: &Mutex<Self>,
+        value: &[u8],
+        format: FragmentFormat,
+    ) -> Vec<(u16, Result<(), BLEError>)> {
+        let (handle, subscribers) = {
+            let characteristic = characteristic.lock();
+            let subscribers: Vec<u16> = characteristic
+                .subscribed_list
+                .iter()
+                .filter(|x| x.1.contains(NimbleSub::NOTIFY))
+                .map(|x| x.0)
+                .collect();
+            (characteristic.handle, subscribers)
+        };
+
+        let mut results = Vec::with_capacity(subscribers.len());
+        for conn_handle in subscribers {
+            // 0 if the connection is gone.
+            let fragment_size =
+                (unsafe { sys::ble_att_mtu(conn_handle) } as usize).saturating_sub(3);
+            if fragment_size == 0 {
+                results.push((conn_handle, BLEError::convert(sys::BLE_HS_ENOTCONN)));
+                continue;
+            }
+
+            let result = match fragments(value, fragment_size, format) {
+                Ok(fragments) => {
+                    let mut result = Ok(());
+                    for fragment in fragments {
+                        result =
+                            send_value_async(handle, &fragment, conn_handle, NimbleSub::NOTIFY)
+                                .await;
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                    result
+                }
+                Err(err) => Err(err),
+            };
+            results.push((conn_handle, result));
+        }
+        results
+    }
+
+    /// The connections subscribed to this characteristic.
+    pub(crate) fn subscribers(&self) -> impl Iterator<Item = (u16, NimbleSub)> + '_ {
+        self.subscribed_list.iter().copied()
+    }
+
     #[cfg(cpfd)]
     /// Set the Characteristic Presentation Format.
     pub fn cpfd(&mut self, cpfd: Cpfd) {
@@ -343,12 +760,78 @@ impl BLECharacteristic {
     #[cfg(not(cpfd))]
     /// Set the Characteristic Presentation Format.
     pub fn cpfd(&mut self, cpfd: Cpfd) {
+        self.add_cpfd(cpfd);
+    }
+
+    /// Add a Characteristic Presentation Format descriptor (0x2904)
+    /// for the next field of a composite value.
+    ///
+    /// The formats added with this function are listed by the aggregate format descriptor.
+    pub fn add_cpfd(&mut self, cpfd: Cpfd) -> Arc<Mutex<BLEDescriptor>> {
         let descriptor = Arc::new(Mutex::new(BLEDescriptor::new(
-            BleUuid::Uuid16(0x2904),
+            BleUuid::Uuid16(PRESENTATION_FORMAT_UUID16),
             DescriptorProperties::READ,
         )));
         descriptor.lock().set_value(cpfd.as_bytes());
-        self.descriptors.push(descriptor);
+        self.descriptors.push(descriptor.clone());
+        self.cpfd_descriptors.push(descriptor.clone());
+        descriptor
+    }
+
+    /// Create a Characteristic Aggregate Format descriptor (0x2905).
+    ///
+    /// Its value, the handles of the formats added with [`Self::add_cpfd`] in order,
+    /// is set when the server is started. A format set with [`Self::cpfd`] is listed first.
+    pub fn create_aggregate_format(&mut self) -> Arc<Mutex<BLEDescriptor>> {
+        if let Some(descriptor) = &self.aggregate_format {
+            return descriptor.clone();
+        }
+
+        let descriptor = self.create_descriptor(
+            BleUuid::Uuid16(AGGREGATE_FORMAT_UUID16),
+            DescriptorProperties::READ,
+        );
+        self.aggregate_format = Some(descriptor.clone());
+        descriptor
+    }
+
+    /// The handle of the format NimBLE adds is not known, so with an aggregate format
+    /// it is added as a descriptor of its own, first in the list.
+    #[cfg(cpfd)]
+    fn move_cpfd_to_aggregate(&mut self) {
+        if self.aggregate_format.is_none() || self.cpfd[0].format == 0 {
+            return;
+        }
+
+        let cpfd = core::mem::take(&mut self.cpfd[0]);
+        let mut value = Vec::with_capacity(7);
+        value.push(cpfd.format);
+        value.push(cpfd.exponent as u8);
+        value.extend_from_slice(&cpfd.unit.to_le_bytes());
+        value.push(cpfd.name_space);
+        value.extend_from_slice(&cpfd.description.to_le_bytes());
+
+        let descriptor = Arc::new(Mutex::new(BLEDescriptor::new(
+            BleUuid::Uuid16(PRESENTATION_FORMAT_UUID16),
+            DescriptorProperties::READ,
+        )));
+        descriptor.lock().set_value(&value);
+        self.descriptors.push(descriptor.clone());
+        self.cpfd_descriptors.insert(0, descriptor);
+    }
+
+    /// Set the aggregate format value once the descriptors are registered.
+    pub(crate) fn resolve_aggregate_format(&self) {
+        let Some(descriptor) = &self.aggregate_format else {
+            return;
+        };
+
+        let value: Vec<u8> = self
+            .cpfd_descriptors
+            .iter()
+            .flat_map(|x| x.lock().handle.to_le_bytes())
+            .collect();
+        descriptor.lock().set_value(&value);
     }
 
     pub(super) extern "C" fn handle_gap_event(
@@ -361,6 +844,15 @@ impl BLECharacteristic {
 
         let mutex = unsafe { voidp_to_ref::<Mutex<Self>>(arg) };
 
+        // Only set before the characteristic is registered, see `on_fast_write`.
+        if ctxt.op as u32 == sys::BLE_GATT_ACCESS_OP_WRITE_CHR
+            && let Some(callback) = &unsafe { mutex.raw() }.on_fast_write
+        {
+            let buf = OsMBuf(ctxt.om).as_flat();
+            callback(conn_handle, buf.as_slice());
+            return 0;
+        }
+
         if crate::utilities::ble_gap_conn_find(conn_handle).is_err() {
             ::log::warn!("the conn handle does not exist");
             return sys::BLE_ATT_ERR_UNLIKELY as _;
@@ -379,7 +871,7 @@ impl BLECharacteristic {
 
                 unsafe {
                     if (*(ctxt.om)).om_pkthdr_len > 8
-                        || characteristic.value.len() <= (desc.mtu() - 3) as _
+                        || characteristic.value_for(conn_handle).len() <= (desc.mtu() - 3) as _
                     {
                         let characteristic = UnsafeCell::new(&mut characteristic);
                         if let Some(callback) = &mut (&mut (*characteristic.get())).on_read {
@@ -389,7 +881,7 @@ impl BLECharacteristic {
                 }
 
                 ble_npl_hw_enter_critical();
-                let value = characteristic.value.as_slice();
+                let value = characteristic.value_for(conn_handle);
                 let rc = OsMBuf(ctxt.om).append(value);
                 ble_npl_hw_exit_critical();
                 if rc == 0 {
@@ -402,29 +894,36 @@ impl BLECharacteristic {
                 let om = OsMBuf(ctxt.om);
                 let buf = om.as_flat();
 
-                let mut notify = false;
+                let server = BLEDevice::take().get_server();
+                let hold = characteristic
+                    .properties
+                    .contains(NimbleProperties::RELIABLE_WRITE)
+                    && server.holds_prepared_writes();
 
-                unsafe {
-                    let characteristic = UnsafeCell::new(&mut characteristic);
-                    if let Some(callback) = &mut (&mut (*characteristic.get())).on_write {
-                        let desc = crate::utilities::ble_gap_conn_find(conn_handle).unwrap();
-                        let mut arg = OnWriteArgs {
-                            current_data: (&(*characteristic.get())).value.as_slice(),
-                            recv_data: buf.as_slice(),
-                            desc: &desc,
-                            reject: false,
-                            error_code: 0,
-                            notify: false,
-                        };
-                        callback(&mut arg);
-
-                        if arg.reject {
-                            return arg.error_code as _;
+                let notify = match characteristic.check_write(conn_handle, buf.as_slice()) {
+                    Ok(notify) => notify,
+                    Err(error_code) => {
+                        if hold {
+                            // NimBLE drops the rest of the queue.
+                            server.discard_prepared_writes(conn_handle);
                         }
-                        notify = arg.notify;
+                        return error_code as _;
                     }
+                };
+
+                if hold {
+                    server.hold_prepared_write(PreparedWrite {
+                        conn_handle,
+                        characteristic: &*mutex,
+                        handle: characteristic.handle,
+                        uuid: characteristic.uuid(),
+                        value: buf.as_slice().to_vec(),
+                        notify,
+                    });
+                    return 0;
                 }
-                characteristic.set_value(buf.as_slice());
+
+                characteristic.set_value_for(conn_handle, buf.as_slice());
                 if notify {
                     characteristic.notify();
                 }
@@ -435,6 +934,47 @@ impl BLECharacteristic {
         }
     }
 
+    /// Run the checks and `on_write` for a written value.
+    /// Returns whether `on_write` requested a notification, or the ATT error code.
+    fn check_write(&mut self, conn_handle: u16, value: &[u8]) -> Result<bool, u8> {
+        if !self.value.accepts_len(value.len()) {
+            return Err(sys::BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as _);
+        }
+
+        if let Some(range) = &self.valid_range
+            && !range.contains(value)
+        {
+            return Err(OUT_OF_RANGE);
+        }
+
+        let desc = crate::utilities::ble_gap_conn_find(conn_handle).unwrap();
+        if let Some(callback) = &mut self.on_validate {
+            callback(value, &desc)?;
+        }
+
+        unsafe {
+            let characteristic = UnsafeCell::new(self);
+            if let Some(callback) = &mut (&mut (*characteristic.get())).on_write {
+                let mut arg = OnWriteArgs {
+                    current_data: (&(*characteristic.get())).value_for(conn_handle),
+                    recv_data: value,
+                    desc: &desc,
+                    reject: false,
+                    error_code: 0,
+                    notify: false,
+                };
+                callback(&mut arg);
+
+                if arg.reject {
+                    return Err(arg.error_code);
+                }
+                return Ok(arg.notify);
+            }
+        }
+
+        Ok(false)
+    }
+
     pub(super) fn subscribe(&mut self, subscribe: &Subscribe) {
         let Ok(desc) = crate::utilities::ble_gap_conn_find(subscribe.conn_handle) else {
             return;
@@ -470,6 +1010,31 @@ impl BLECharacteristic {
         }
     }
 
+    /// Index of the copy of the connection, snapshotted from the default value if it has none yet.
+    fn conn_value_idx(&mut self, conn_handle: u16) -> usize {
+        match self.conn_values.iter().position(|x| x.0 == conn_handle) {
+            Some(idx) => idx,
+            None => {
+                let mut conn_value = self.value.empty_like();
+                conn_value.set_value(self.value.as_slice());
+                self.conn_values.push((conn_handle, conn_value));
+                self.conn_values.len() - 1
+            }
+        }
+    }
+
+    /// Snapshot the value for the connection.
+    pub(super) fn connect(&mut self, conn_handle: u16) {
+        if self.per_connection_value {
+            self.conn_value_idx(conn_handle);
+        }
+    }
+
+    /// Drop the value of the connection.
+    pub(super) fn disconnect(&mut self, conn_handle: u16) {
+        self.conn_values.retain(|x| x.0 != conn_handle);
+    }
+
     /// Do not call `lock` on this characteristic inside the callback, use the first input instead.
     /// In the future, this characteristic could be locked while the callback executes.
     /// * `callback` - Function to call when a subscription event is recieved, including subscribe and unsubscribe events
@@ -487,6 +1052,76 @@ impl BLECharacteristic {
     }
 }
 
+/// Longest wait for a `NOTIFY_TX` before a send that ran out of buffers is retried.
+const ENOMEM_RETRY_MS: u32 = 100;
+
+/// Send the value to the subscriber, waiting for resources and the indication confirmation.
+pub(crate) async fn send_value_async(
+    handle: u16,
+    value: &[u8],
+    conn_handle: u16,
+    flag: NimbleSub,
+) -> Result<(), BLEError> {
+    let server = BLEDevice::take().get_server();
+    let indicate = flag.contains(NimbleSub::INDICATE);
+
+    loop {
+        let version = server.notify_tx_version();
+        crate::utilities::ble_gap_conn_find(conn_handle)?;
+
+        let slot = if indicate {
+            match server.set_indicate_wait(conn_handle) {
+                Some(slot) => Some(slot),
+                None => {
+                    server.wait_notify_tx(version).await;
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        let om = OsMBuf::from_flat(value);
+        let rc = if om.0.is_null() {
+            sys::BLE_HS_ENOMEM as _
+        } else if indicate {
+            unsafe { sys::ble_gatts_indicate_custom(conn_handle, handle, om.0) }
+        } else {
+            unsafe { sys::ble_gatts_notify_custom(conn_handle, handle, om.0) }
+        };
+
+        if rc != 0 {
+            if indicate {
+                server.clear_indicate_wait(conn_handle);
+            }
+            if rc == sys::BLE_HS_ENOMEM as _ {
+                // The buffers may be held by other traffic that sends no `NOTIFY_TX`,
+                // so retry after a while anyway.
+                let mut notified = pin!(server.wait_notify_tx(version));
+                let mut retry = pin!(delay_ms(ENOMEM_RETRY_MS));
+                poll_fn(|cx| {
+                    if notified.as_mut().poll(cx).is_ready() || retry.as_mut().poll(cx).is_ready() {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                })
+                .await;
+                continue;
+            }
+            return BLEError::convert(rc as _);
+        }
+
+        let Some(slot) = slot else {
+            return Ok(());
+        };
+        return match server.wait_indicate(slot).await {
+            sys::BLE_HS_EDONE => Ok(()),
+            status => BLEError::convert(status),
+        };
+    }
+}
+
 impl core::fmt::Debug for BLECharacteristic {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("BLECharacteristic")
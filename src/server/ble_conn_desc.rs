@@ -1,4 +1,7 @@
-use crate::{BLEAddress, BLEError, ble};
+use crate::{
+    BLEAddress, BLEError, PhyUpdate, ble, ble_phy,
+    enums::{CodedPhyOption, PhyMask, SecPhy},
+};
 use esp_idf_svc::sys as esp_idf_sys;
 use esp_idf_sys::ble_gap_conn_desc;
 
@@ -84,6 +87,60 @@ impl BLEConnDesc {
         }
         Ok(rssi)
     }
+
+    /// Gets the current (TX, RX) PHY of this connection.
+    pub fn phy(&self) -> Result<(SecPhy, SecPhy), BLEError> {
+        ble_phy::phy(self.0.conn_handle)
+    }
+
+    /// Sets the preferred PHYs of this connection and starts a PHY update procedure.
+    /// The result is reported by `on_phy_update`.
+    pub fn set_preferred_phy(
+        &self,
+        tx_phys: PhyMask,
+        rx_phys: PhyMask,
+        coded: CodedPhyOption,
+    ) -> Result<(), BLEError> {
+        ble_phy::set_preferred_phy(self.0.conn_handle, tx_phys, rx_phys, coded)
+    }
+
+    /// Requests a PHY update and waits for its completion.
+    /// Returns the current PHYs right away if the masks already allow them.
+    ///
+    /// Fails with `BLE_HS_ETIMEOUT` if the controller does not report the completion
+    /// within `timeout_ms`, for example because the PHYs do not change.
+    pub async fn update_phy(
+        &self,
+        tx_phys: PhyMask,
+        rx_phys: PhyMask,
+        coded: CodedPhyOption,
+        timeout_ms: u32,
+    ) -> Result<PhyUpdate, BLEError> {
+        ble_phy::update_phy(self.0.conn_handle, tx_phys, rx_phys, coded, timeout_ms).await
+    }
+
+    /// Requests the data length extension for this connection.
+    ///
+    /// * `tx_octets`: The preferred maximum payload size (27 - 251 bytes).
+    /// * `tx_time`: The preferred maximum transmission time (328 - 17040 microseconds).
+    pub fn set_data_len(&self, tx_octets: u16, tx_time: u16) -> Result<(), BLEError> {
+        ble_phy::set_data_len(self.0.conn_handle, tx_octets, tx_time)
+    }
+
+    /// Requests the data length extension and waits until the controller reports the new values.
+    /// Returns the current values right away if they already are at least the requested ones.
+    #[cfg(not(all(
+        esp_idf_version_major = "5",
+        any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
+    )))]
+    pub async fn update_data_len(
+        &self,
+        tx_octets: u16,
+        tx_time: u16,
+        timeout_ms: u32,
+    ) -> Result<crate::DataLengthChange, BLEError> {
+        ble_phy::update_data_len(self.0.conn_handle, tx_octets, tx_time, timeout_ms).await
+    }
 }
 
 impl core::fmt::Debug for BLEConnDesc {
@@ -12,8 +12,11 @@ use crate::{
     },
 };
 
+use super::NULL_HANDLE;
+
 bitflags! {
   #[repr(transparent)]
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
   pub struct DescriptorProperties: u8 {
     const READ = esp_idf_sys::BLE_ATT_F_READ as _;
     const READ_ENC = esp_idf_sys::BLE_ATT_F_READ_ENC as _;
@@ -30,6 +33,7 @@ bitflags! {
 pub struct BLEDescriptor {
     pub(crate) uuid: ble_uuid_any_t,
     pub(crate) properties: DescriptorProperties,
+    pub(crate) handle: u16,
     value: AttValue,
     on_read: Option<Box<dyn FnMut(&mut AttValue, &BLEConnDesc) + Send + Sync>>,
     on_write: Option<Box<dyn FnMut(&mut OnWriteDescriptorArgs) + Send + Sync>>,
@@ -40,12 +44,17 @@ impl BLEDescriptor {
         Self {
             uuid: ble_uuid_any_t::from(uuid),
             properties,
+            handle: NULL_HANDLE,
             value: AttValue::new(),
             on_read: None,
             on_write: None,
         }
     }
 
+    pub fn uuid(&self) -> BleUuid {
+        BleUuid::from(self.uuid)
+    }
+
     pub fn set_value(&mut self, value: &[u8]) -> &mut Self {
         self.value.set_value(value);
         self
@@ -122,6 +131,10 @@ impl BLEDescriptor {
                 let om = OsMBuf(ctxt.om);
                 let buf = om.as_flat();
 
+                if !descriptor.value.accepts_len(buf.as_slice().len()) {
+                    return esp_idf_sys::BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as _;
+                }
+
                 unsafe {
                     let descriptor = UnsafeCell::new(&mut descriptor);
                     if let Some(callback) = &mut (&mut (*descriptor.get())).on_write {
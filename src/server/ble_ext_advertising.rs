@@ -1,4 +1,4 @@
-use alloc::{vec, vec::Vec};
+use alloc::{boxed::Box, vec, vec::Vec};
 use core::ffi::c_void;
 use esp_idf_svc::sys as esp_idf_sys;
 use once_cell::sync::Lazy;
@@ -65,6 +65,16 @@ impl BLEExtAdvertisement {
         self.params.own_addr_type = esp_idf_sys::BLE_OWN_ADDR_RANDOM as _;
     }
 
+    /// Use directed advertising to the peer.
+    ///
+    /// * `addr`: The address of the peer, or its identity address if it is bonded.
+    /// * `high_duty_cycle`: Only valid for connectable legacy advertising.
+    pub fn directed(&mut self, addr: &BLEAddress, high_duty_cycle: bool) {
+        self.params.set_directed(1);
+        self.params.set_high_duty_directed(high_duty_cycle as _);
+        self.params.peer = (*addr).into();
+    }
+
     /// Sets The primary channels to advertise on.
     pub fn primary_channels(&mut self, ch37: bool, ch38: bool, ch39: bool) {
         self.params.channel_map = (ch37 as u8) | ((ch38 as u8) << 1) | ((ch39 as u8) << 2);
@@ -101,6 +111,11 @@ impl BLEExtAdvertisement {
         self.payload.len()
     }
 
+    /// Add the advertising flags.
+    pub fn flags(&mut self, flags: AdvFlag) {
+        self.add_data(esp_idf_sys::BLE_HS_ADV_TYPE_FLAGS as _, &[flags.bits()]);
+    }
+
     pub fn appearance(&mut self, appearance: u16) {
         self.add_data(
             esp_idf_sys::BLE_HS_ADV_TYPE_APPEARANCE as _,
@@ -132,21 +147,21 @@ impl BLEExtAdvertisement {
         let data_type: u8 = match size {
             16 => {
                 if complete {
-                    esp_idf_sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID16 as _
+                    esp_idf_sys::BLE_HS_ADV_TYPE_COMP_UUIDS16 as _
                 } else {
                     esp_idf_sys::BLE_HS_ADV_TYPE_INCOMP_UUIDS16 as _
                 }
             }
             32 => {
                 if complete {
-                    esp_idf_sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID32 as _
+                    esp_idf_sys::BLE_HS_ADV_TYPE_COMP_UUIDS32 as _
                 } else {
                     esp_idf_sys::BLE_HS_ADV_TYPE_INCOMP_UUIDS32 as _
                 }
             }
             128 => {
                 if complete {
-                    esp_idf_sys::BLE_HS_ADV_TYPE_SVC_DATA_UUID128 as _
+                    esp_idf_sys::BLE_HS_ADV_TYPE_COMP_UUIDS128 as _
                 } else {
                     esp_idf_sys::BLE_HS_ADV_TYPE_INCOMP_UUIDS128 as _
                 }
@@ -212,15 +227,49 @@ impl BLEExtAdvertisement {
     }
 }
 
+/// Arguments of the [`BLEExtAdvertising::on_complete`] callback.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct AdvComplete {
+    pub instance: u8,
+    /// * 0: A connection was established.
+    /// * `BLE_HS_ETIMEOUT`: The duration elapsed.
+    /// * `BLE_HS_EDONE`: The maximum number of events was reached.
+    /// * `BLE_HS_EPREEMPTED`: Advertising was preempted by the host.
+    pub reason: i32,
+    /// The established connection, if any.
+    pub conn_handle: Option<u16>,
+    pub num_ext_adv_events: u8,
+}
+
+type OnCompleteCallback = Box<dyn FnMut(&AdvComplete) + Send + Sync>;
+type OnScanRequestCallback = Box<dyn FnMut(u8, &BLEAddress) + Send + Sync>;
+
+const MAX_INSTANCES: usize = (esp_idf_sys::CONFIG_BT_NIMBLE_MAX_EXT_ADV_INSTANCES + 1) as _;
+
+fn check_instance(inst_id: u8) -> Result<(), BLEError> {
+    if (inst_id as usize) < MAX_INSTANCES {
+        Ok(())
+    } else {
+        Err(BLEError::convert(esp_idf_sys::BLE_HS_EINVAL).unwrap_err())
+    }
+}
+
 pub struct BLEExtAdvertising {
     adv_status: Vec<bool>,
+    /// The duration and maximum number of events each instance was started with.
+    adv_limits: Vec<(i32, i32)>,
+    on_complete: Vec<Option<OnCompleteCallback>>,
+    on_scan_request: Vec<Option<OnScanRequestCallback>>,
 }
 
 impl BLEExtAdvertising {
     #[allow(dead_code)]
     pub(crate) fn new() -> Self {
         Self {
-            adv_status: vec![false; (esp_idf_sys::CONFIG_BT_NIMBLE_MAX_EXT_ADV_INSTANCES + 1) as _],
+            adv_status: vec![false; MAX_INSTANCES],
+            adv_limits: vec![(0, 0); MAX_INSTANCES],
+            on_complete: (0..MAX_INSTANCES).map(|_| None).collect(),
+            on_scan_request: (0..MAX_INSTANCES).map(|_| None).collect(),
         }
     }
 
@@ -231,8 +280,12 @@ impl BLEExtAdvertising {
     ) -> Result<(), BLEError> {
         adv.params.sid = inst_id;
 
-        // Legacy advertising as connectable requires the scannable flag also.
-        if adv.params.legacy_pdu() != 0 && adv.params.connectable() != 0 {
+        // Legacy advertising as connectable requires the scannable flag also,
+        // except for directed advertising.
+        if adv.params.legacy_pdu() != 0
+            && adv.params.connectable() != 0
+            && adv.params.directed() == 0
+        {
             adv.params.set_scannable(1);
         }
 
@@ -311,11 +364,138 @@ impl BLEExtAdvertising {
         duration: i32,
         max_event: i32,
     ) -> Result<(), BLEError> {
+        check_instance(inst_id)?;
         unsafe {
             ble!(esp_idf_sys::ble_gap_ext_adv_start(
                 inst_id, duration, max_event
-            ))
+            ))?;
         }
+        self.adv_status[inst_id as usize] = true;
+        self.adv_limits[inst_id as usize] = (duration, max_event);
+        Ok(())
+    }
+
+    /// Stop advertising the instance.
+    pub fn stop(&mut self, inst_id: u8) -> Result<(), BLEError> {
+        check_instance(inst_id)?;
+        unsafe { ble!(esp_idf_sys::ble_gap_ext_adv_stop(inst_id))? };
+        self.adv_status[inst_id as usize] = false;
+        Ok(())
+    }
+
+    /// Stop advertising all instances.
+    pub fn stop_all(&mut self) -> Result<(), BLEError> {
+        for inst_id in 0..MAX_INSTANCES {
+            if self.is_active(inst_id as _) {
+                self.stop(inst_id as _)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop and remove the instance. Its data and callbacks are cleared.
+    pub fn remove(&mut self, inst_id: u8) -> Result<(), BLEError> {
+        check_instance(inst_id)?;
+        if self.is_active(inst_id) {
+            self.stop(inst_id)?;
+        }
+
+        unsafe { ble!(esp_idf_sys::ble_gap_ext_adv_remove(inst_id))? };
+        self.on_complete[inst_id as usize] = None;
+        self.on_scan_request[inst_id as usize] = None;
+        Ok(())
+    }
+
+    /// Stop and remove all instances.
+    pub fn clear(&mut self) -> Result<(), BLEError> {
+        self.stop_all()?;
+
+        unsafe { ble!(esp_idf_sys::ble_gap_ext_adv_clear())? };
+        self.on_complete.iter_mut().for_each(|x| *x = None);
+        self.on_scan_request.iter_mut().for_each(|x| *x = None);
+        Ok(())
+    }
+
+    /// Returns whether the instance is advertising.
+    pub fn is_active(&self, inst_id: u8) -> bool {
+        self.adv_status
+            .get(inst_id as usize)
+            .is_some_and(|x| *x && unsafe { esp_idf_sys::ble_gap_ext_adv_active(inst_id) })
+    }
+
+    /// Set a new random address for the instance.
+    ///
+    /// The instance must be configured with a random address (see [`BLEExtAdvertisement::address`]).
+    /// An advertising instance is stopped while the address is changed and then restarted
+    /// with the duration and maximum number of events it was started with, counted anew.
+    ///
+    /// The address is not rotated automatically, call this periodically to rotate it.
+    ///
+    /// * `nrpa`: Generate a non-resolvable private address instead of a static random address.
+    pub fn rotate_address(&mut self, inst_id: u8, nrpa: bool) -> Result<BLEAddress, BLEError> {
+        let mut addr = esp_idf_sys::ble_addr_t::default();
+        unsafe { ble!(esp_idf_sys::ble_hs_id_gen_rnd(nrpa as _, &mut addr))? };
+
+        let active = self.is_active(inst_id);
+        if active {
+            self.stop(inst_id)?;
+        }
+
+        unsafe { ble!(esp_idf_sys::ble_gap_ext_adv_set_addr(inst_id, &addr))? };
+
+        if active {
+            let (duration, max_event) = self.adv_limits[inst_id as usize];
+            self.start_with_duration(inst_id, duration, max_event)?;
+        }
+
+        Ok(BLEAddress::from(addr))
+    }
+
+    /// Advertise the instance to the peer with high duty cycle directed advertising,
+    /// e.g. from [`BLEServer::on_disconnect`] for a bonded central (see [`BLEServer::last_bonded_peer`]).
+    ///
+    /// The instance is reconfigured as connectable legacy advertising, so use one that is
+    /// not otherwise advertised. The controller stops it after 1.28 seconds, then
+    /// [`Self::on_complete`] is called with `BLE_HS_ETIMEOUT`.
+    pub fn start_fast_reconnect(&mut self, inst_id: u8, addr: &BLEAddress) -> Result<(), BLEError> {
+        check_instance(inst_id)?;
+        if self.is_active(inst_id) {
+            self.stop(inst_id)?;
+        }
+
+        let mut adv = BLEExtAdvertisement::new(PrimPhy::Phy1M, SecPhy::Phy1M);
+        adv.legacy_advertising(true);
+        adv.connectable(true);
+        adv.directed(addr, true);
+        self.set_instance_data(inst_id, &mut adv)?;
+
+        // High duty cycle directed advertising must not last longer than 1.28 seconds.
+        self.start_with_duration(inst_id, 128, 0)
+    }
+
+    /// Set a callback to be called when advertising of the instance completes.
+    pub fn on_complete(
+        &mut self,
+        inst_id: u8,
+        callback: impl FnMut(&AdvComplete) + Send + Sync + 'static,
+    ) -> Result<&mut Self, BLEError> {
+        check_instance(inst_id)?;
+        self.on_complete[inst_id as usize] = Some(Box::new(callback));
+        Ok(self)
+    }
+
+    /// Set a callback to be called when a scan request is received for the instance.
+    /// The callback gets the instance and the address of the scanner.
+    ///
+    /// Requires [`BLEExtAdvertisement::enable_scan_request_callback`].
+    pub fn on_scan_request(
+        &mut self,
+        inst_id: u8,
+        callback: impl FnMut(u8, &BLEAddress) + Send + Sync + 'static,
+    ) -> Result<&mut Self, BLEError> {
+        check_instance(inst_id)?;
+        self.on_scan_request[inst_id as usize] = Some(Box::new(callback));
+        Ok(self)
     }
 
     pub(crate) extern "C" fn handle_gap_event(
@@ -328,7 +508,30 @@ impl BLEExtAdvertising {
         match event.type_ as _ {
             esp_idf_sys::BLE_GAP_EVENT_ADV_COMPLETE => {
                 let adv_complete = unsafe { event.__bindgen_anon_1.adv_complete };
-                adv.adv_status[adv_complete.instance as usize] = false;
+                let instance = adv_complete.instance as usize;
+                if let Some(status) = adv.adv_status.get_mut(instance) {
+                    *status = false;
+                }
+
+                if let Some(Some(callback)) = adv.on_complete.get_mut(instance) {
+                    callback(&AdvComplete {
+                        instance: adv_complete.instance,
+                        reason: adv_complete.reason,
+                        conn_handle: (adv_complete.reason == 0).then_some(adv_complete.conn_handle),
+                        num_ext_adv_events: adv_complete.num_ext_adv_events,
+                    });
+                }
+            }
+            esp_idf_sys::BLE_GAP_EVENT_SCAN_REQ_RCVD => {
+                let scan_req_rcvd = unsafe { event.__bindgen_anon_1.scan_req_rcvd };
+                if let Some(Some(callback)) =
+                    adv.on_scan_request.get_mut(scan_req_rcvd.instance as usize)
+                {
+                    callback(
+                        scan_req_rcvd.instance,
+                        &BLEAddress::from(scan_req_rcvd.scan_addr),
+                    );
+                }
             }
             _ => {}
         }
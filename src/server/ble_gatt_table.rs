@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use esp_idf_svc::sys as esp_idf_sys;
+
+#[cfg(cpfd)]
+use crate::descriptors::PRESENTATION_FORMAT_UUID16;
+use crate::{
+    BLECharacteristic, BLEDescriptor, BLEDevice, BLEService, DescriptorProperties,
+    NimbleProperties, NimbleSub,
+    utilities::{BleUuid, mutex::Mutex, voidp_to_ref},
+};
+
+const CCCD_UUID16: u16 = esp_idf_sys::BLE_GATT_DSC_CLT_CFG_UUID16 as _;
+
+/// A service of the local GATT table.
+#[derive(Debug, Clone)]
+pub struct GattServiceInfo {
+    pub uuid: BleUuid,
+    pub secondary: bool,
+    pub start_handle: u16,
+    pub end_handle: u16,
+    /// Start handles of the included services.
+    pub includes: Vec<u16>,
+    pub characteristics: Vec<GattCharacteristicInfo>,
+}
+
+/// A characteristic of the local GATT table.
+#[derive(Debug, Clone)]
+pub struct GattCharacteristicInfo {
+    pub uuid: BleUuid,
+    /// Handle of the characteristic declaration.
+    pub def_handle: u16,
+    pub value_handle: u16,
+    pub properties: NimbleProperties,
+    /// Handle of the Client Characteristic Configuration Descriptor (0x2902).
+    pub cccd_handle: Option<u16>,
+    /// Handle of the Characteristic Presentation Format Descriptor (0x2904) added by NimBLE.
+    pub cpfd_handle: Option<u16>,
+    pub descriptors: Vec<GattDescriptorInfo>,
+    /// Connections subscribed to the characteristic.
+    pub subscribers: Vec<(u16, NimbleSub)>,
+}
+
+/// A descriptor of the local GATT table.
+#[derive(Debug, Clone)]
+pub struct GattDescriptorInfo {
+    pub uuid: BleUuid,
+    pub handle: u16,
+    pub properties: DescriptorProperties,
+    pub value: Vec<u8>,
+}
+
+/// `gatts_register_cb` that records the handles NimBLE does not write back to the definitions,
+/// then calls the callback that was set before the server started.
+pub(crate) extern "C" fn on_register(
+    ctxt: *mut esp_idf_sys::ble_gatt_register_ctxt,
+    arg: *mut c_void,
+) {
+    let server = BLEDevice::take().get_server();
+
+    {
+        let ctxt = unsafe { &*ctxt };
+        match ctxt.op as _ {
+            esp_idf_sys::BLE_GATT_REGISTER_OP_SVC => {
+                let svc = unsafe { &ctxt.__bindgen_anon_1.svc };
+                // Attributes are registered in handle order, so this service ends the previous one.
+                if let Some(prev) = server.registering_service.take() {
+                    prev.lock().end_handle = svc.handle - 1;
+                }
+                if let Some(service) = server.local_service(svc.svc_def) {
+                    service.lock().end_handle = svc.handle;
+                    server.registering_service = Some(service);
+                }
+            }
+            esp_idf_sys::BLE_GATT_REGISTER_OP_CHR => {
+                let chr = unsafe { &ctxt.__bindgen_anon_1.chr };
+                if let Some(service) = server.local_service(chr.svc_def) {
+                    let chr_def = unsafe { &*chr.chr_def };
+                    let characteristic =
+                        unsafe { voidp_to_ref::<Mutex<BLECharacteristic>>(chr_def.arg) };
+                    characteristic.lock().def_handle = chr.def_handle;
+                    extend_end_handle(&service, chr.val_handle);
+                }
+            }
+            esp_idf_sys::BLE_GATT_REGISTER_OP_DSC => {
+                let dsc = unsafe { &ctxt.__bindgen_anon_1.dsc };
+                if let Some(service) = server.local_service(dsc.svc_def) {
+                    let dsc_def = unsafe { &*dsc.dsc_def };
+                    let descriptor = unsafe { voidp_to_ref::<Mutex<BLEDescriptor>>(dsc_def.arg) };
+                    descriptor.lock().handle = dsc.handle;
+                    extend_end_handle(&service, dsc.handle);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(callback) = server.prev_register_cb {
+        unsafe { callback(ctxt, arg) };
+    }
+}
+
+fn extend_end_handle(service: &Mutex<BLEService>, handle: u16) {
+    let mut service = service.lock();
+    service.end_handle = service.end_handle.max(handle);
+}
+
+pub(crate) fn service_info(service: &BLEService) -> GattServiceInfo {
+    let svc_uuid = service.uuid();
+    let characteristics: Vec<GattCharacteristicInfo> = service
+        .characteristics
+        .iter()
+        .map(|x| characteristic_info(&svc_uuid, &x.lock()))
+        .collect();
+
+    // The end handle of the last registered service only covers the handles reported to
+    // `on_register`, the CCCD and CPFD that NimBLE adds are not.
+    let end_handle = characteristics
+        .iter()
+        .flat_map(|chr| chr.cccd_handle.into_iter().chain(chr.cpfd_handle))
+        .fold(service.end_handle, u16::max);
+
+    GattServiceInfo {
+        uuid: svc_uuid,
+        secondary: service.is_secondary(),
+        start_handle: service.handle,
+        end_handle,
+        includes: service
+            .includes
+            .iter()
+            .map(|x| unsafe { x.raw() }.handle)
+            .collect(),
+        characteristics,
+    }
+}
+
+fn characteristic_info(
+    svc_uuid: &BleUuid,
+    characteristic: &BLECharacteristic,
+) -> GattCharacteristicInfo {
+    let uuid = characteristic.uuid();
+    let cccd_handle = if characteristic
+        .properties
+        .intersects(NimbleProperties::NOTIFY | NimbleProperties::INDICATE)
+    {
+        find_dsc(svc_uuid, &uuid, CCCD_UUID16)
+    } else {
+        None
+    };
+
+    #[cfg(cpfd)]
+    let cpfd_handle = if characteristic.cpfd[0].format != 0 {
+        find_dsc(svc_uuid, &uuid, PRESENTATION_FORMAT_UUID16)
+    } else {
+        None
+    };
+    #[cfg(not(cpfd))]
+    let cpfd_handle = None;
+
+    GattCharacteristicInfo {
+        uuid,
+        def_handle: characteristic.def_handle,
+        value_handle: characteristic.handle,
+        properties: characteristic.properties,
+        cccd_handle,
+        cpfd_handle,
+        descriptors: characteristic
+            .descriptors
+            .iter()
+            .map(|x| {
+                let mut descriptor = x.lock();
+                GattDescriptorInfo {
+                    uuid: descriptor.uuid(),
+                    handle: descriptor.handle,
+                    properties: descriptor.properties,
+                    value: descriptor.value_mut().as_slice().to_vec(),
+                }
+            })
+            .collect(),
+        subscribers: characteristic.subscribers().collect(),
+    }
+}
+
+fn find_dsc(svc_uuid: &BleUuid, chr_uuid: &BleUuid, dsc_uuid16: u16) -> Option<u16> {
+    let svc_uuid = esp_idf_sys::ble_uuid_any_t::from(*svc_uuid);
+    let chr_uuid = esp_idf_sys::ble_uuid_any_t::from(*chr_uuid);
+    let dsc_uuid = esp_idf_sys::ble_uuid_any_t::from(BleUuid::Uuid16(dsc_uuid16));
+    let mut handle = 0;
+    let rc = unsafe {
+        esp_idf_sys::ble_gatts_find_dsc(&svc_uuid.u, &chr_uuid.u, &dsc_uuid.u, &mut handle)
+    };
+    (rc == 0).then_some(handle)
+}
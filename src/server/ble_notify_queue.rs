@@ -0,0 +1,170 @@
+use super::ble_characteristic::send_value_async;
+use crate::{
+    BLECharacteristic, BLEError, NimbleSub, Signal,
+    utilities::{Notify, mutex::Mutex},
+};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use esp_idf_svc::sys as esp_idf_sys;
+
+struct SubscriberQueue {
+    conn_handle: u16,
+    flag: NimbleSub,
+    values: VecDeque<Vec<u8>>,
+}
+
+struct NotifyQueueInner {
+    characteristic: Arc<Mutex<BLECharacteristic>>,
+    capacity: usize,
+    queues: Mutex<Vec<SubscriberQueue>>,
+    data: Signal<()>,
+    space: Notify,
+}
+
+/// A bounded queue per subscriber for streaming values of a characteristic.
+///
+/// Values are sent by [`NotifyQueue::run`] as indications when the subscriber enabled them,
+/// otherwise as notifications, in order and without dropping values while the peer is connected.
+///
+/// # Examples
+///
+/// ```ignore
+/// let queue = NotifyQueue::new(characteristic, 16);
+/// let sender = queue.clone();
+/// spawn(async move { sender.run().await });
+/// loop {
+///   queue.push(&read_sensor()).await?;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct NotifyQueue {
+    inner: Arc<NotifyQueueInner>,
+}
+
+impl NotifyQueue {
+    /// * `capacity`: Maximum number of queued values per subscriber.
+    pub fn new(characteristic: Arc<Mutex<BLECharacteristic>>, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(NotifyQueueInner {
+                characteristic,
+                capacity,
+                queues: Mutex::new(Vec::new()),
+                data: Signal::new(),
+                space: Notify::new(),
+            }),
+        }
+    }
+
+    /// Queue the value for every subscriber.
+    ///
+    /// Returns `BLE_HS_EBUSY` without queueing anything if the queue of a subscriber is full.
+    pub fn try_push(&self, value: &[u8]) -> Result<(), BLEError> {
+        let mut queues = self.inner.queues.lock();
+        self.update_subscribers(&mut queues);
+
+        if queues.iter().any(|x| x.values.len() >= self.inner.capacity) {
+            return BLEError::convert(esp_idf_sys::BLE_HS_EBUSY);
+        }
+
+        for queue in queues.iter_mut() {
+            queue.values.push_back(value.to_vec());
+        }
+        if !queues.is_empty() {
+            self.inner.data.signal(());
+        }
+        Ok(())
+    }
+
+    /// Queue the value for every subscriber, waiting until there is room in every queue.
+    pub async fn push(&self, value: &[u8]) -> Result<(), BLEError> {
+        loop {
+            let version = self.inner.space.version();
+            match self.try_push(value) {
+                Err(err) if err.code() == esp_idf_sys::BLE_HS_EBUSY => {
+                    self.inner.space.wait(version).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Number of values queued for the connection.
+    pub fn len(&self, conn_handle: u16) -> usize {
+        self.inner
+            .queues
+            .lock()
+            .iter()
+            .find(|x| x.conn_handle == conn_handle)
+            .map_or(0, |x| x.values.len())
+    }
+
+    /// Send the queued values. One value is sent to each subscriber in turn.
+    ///
+    /// Runs until the future is dropped.
+    pub async fn run(&self) {
+        loop {
+            let handle = self.inner.characteristic.lock().handle;
+            let next: Vec<(u16, NimbleSub, Vec<u8>)> = {
+                let mut queues = self.inner.queues.lock();
+                self.update_subscribers(&mut queues);
+                queues
+                    .iter()
+                    .filter_map(|x| Some((x.conn_handle, x.flag, x.values.front()?.clone())))
+                    .collect()
+            };
+
+            if next.is_empty() {
+                self.inner.data.wait().await;
+                continue;
+            }
+
+            for (conn_handle, flag, value) in next {
+                let result = send_value_async(handle, &value, conn_handle, flag).await;
+
+                let mut queues = self.inner.queues.lock();
+                let Some(idx) = queues.iter().position(|x| x.conn_handle == conn_handle) else {
+                    continue;
+                };
+                match result {
+                    Err(err) if err.code() == esp_idf_sys::BLE_HS_ENOTCONN => {
+                        queues.swap_remove(idx);
+                    }
+                    result => {
+                        if let Err(err) = result {
+                            ::log::warn!("notify error({conn_handle}): {err:?}");
+                        }
+                        queues[idx].values.pop_front();
+                    }
+                }
+                self.inner.space.notify();
+            }
+        }
+    }
+
+    /// Add queues for new subscribers and remove the queues of unsubscribed connections.
+    fn update_subscribers(&self, queues: &mut Vec<SubscriberQueue>) {
+        let characteristic = self.inner.characteristic.lock();
+
+        queues.retain(|x| {
+            characteristic
+                .subscribers()
+                .any(|(conn_handle, _)| conn_handle == x.conn_handle)
+        });
+
+        for (conn_handle, sub) in characteristic.subscribers() {
+            let flag = if sub.contains(NimbleSub::INDICATE) {
+                NimbleSub::INDICATE
+            } else {
+                NimbleSub::NOTIFY
+            };
+
+            match queues.iter_mut().find(|x| x.conn_handle == conn_handle) {
+                Some(queue) => queue.flag = flag,
+                None => queues.push(SubscriberQueue {
+                    conn_handle,
+                    flag,
+                    values: VecDeque::new(),
+                }),
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use crate::{
-    BLECharacteristic, BLEConnDesc, BLEDevice, BLEError, BLEService, NimbleProperties, NotifyTx,
-    ble,
-    utilities::{BleUuid, ble_gap_conn_find, extend_lifetime_mut, mutex::Mutex},
+    BLEAddress, BLECharacteristic, BLEConnDesc, BLEDevice, BLEError, BLEService, DataLengthChange,
+    GattServiceInfo, NimbleProperties, NotifyTx, OnExecuteWriteArgs, PhyUpdate, PreparedWrite,
+    Signal, ble, ble_phy,
+    utilities::{
+        BleUuid, Notify, ble_gap_conn_find, ble_npl_event_init, ble_npl_eventq_put_dflt,
+        extend_lifetime_mut, mutex::Mutex,
+    },
 };
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::{cell::UnsafeCell, ffi::c_void};
@@ -14,10 +18,20 @@ const MAX_CONNECTIONS: usize = esp_idf_sys::CONFIG_BT_NIMBLE_MAX_CONNECTIONS as
 pub struct BLEServer {
     pub(crate) started: bool,
     advertise_on_disconnect: bool,
+    #[cfg(not(esp_idf_bt_nimble_ext_adv))]
+    fast_reconnect: bool,
+    last_bonded_peer: Option<BLEAddress>,
     services: Vec<Arc<Mutex<BLEService>>>,
     notify_characteristic: Vec<&'static mut BLECharacteristic>,
     connections: heapless::Vec<u16, MAX_CONNECTIONS>,
     indicate_wait: [u16; MAX_CONNECTIONS],
+    indicate_signal: [Signal<u32>; MAX_CONNECTIONS],
+    notify_tx: Notify,
+    pub(super) registering_service: Option<Arc<Mutex<BLEService>>>,
+    register_cb_installed: bool,
+    pub(super) prev_register_cb: esp_idf_sys::ble_gatt_register_fn,
+    prepared_writes: Vec<PreparedWrite>,
+    execute_write_event: esp_idf_sys::ble_npl_event,
 
     on_connect: Option<Box<dyn FnMut(&mut Self, &BLEConnDesc) + Send + Sync>>,
     on_disconnect: Option<Box<dyn FnMut(&BLEConnDesc, Result<(), BLEError>) + Send + Sync>>,
@@ -25,6 +39,9 @@ pub struct BLEServer {
     on_confirm_pin: Option<Box<dyn Fn(u32) -> bool + Send + Sync>>,
     on_authentication_complete:
         Option<Box<dyn Fn(&mut Self, &BLEConnDesc, Result<(), BLEError>) + Send + Sync>>,
+    on_phy_update: Option<Box<dyn FnMut(&BLEConnDesc, Result<PhyUpdate, BLEError>) + Send + Sync>>,
+    on_data_len_change: Option<Box<dyn FnMut(&BLEConnDesc, &DataLengthChange) + Send + Sync>>,
+    on_execute_write: Option<Box<dyn FnMut(&mut OnExecuteWriteArgs) + Send + Sync>>,
 }
 
 impl BLEServer {
@@ -32,15 +49,28 @@ impl BLEServer {
         Self {
             started: false,
             advertise_on_disconnect: true,
+            #[cfg(not(esp_idf_bt_nimble_ext_adv))]
+            fast_reconnect: false,
+            last_bonded_peer: None,
             services: Vec::new(),
             notify_characteristic: Vec::new(),
             connections: heapless::Vec::new(),
             indicate_wait: [BLE_HS_CONN_HANDLE_NONE; MAX_CONNECTIONS],
+            indicate_signal: core::array::from_fn(|_| Signal::new()),
+            notify_tx: Notify::new(),
+            registering_service: None,
+            register_cb_installed: false,
+            prev_register_cb: None,
+            prepared_writes: Vec::new(),
+            execute_write_event: Default::default(),
             on_connect: None,
             on_disconnect: None,
             on_passkey_request: None,
             on_confirm_pin: None,
             on_authentication_complete: None,
+            on_phy_update: None,
+            on_data_len_change: None,
+            on_execute_write: None,
         }
     }
 
@@ -108,6 +138,44 @@ impl BLEServer {
         self
     }
 
+    /// Handle the completion of a PHY update procedure, started by either side.
+    pub fn on_phy_update(
+        &mut self,
+        callback: impl FnMut(&BLEConnDesc, Result<PhyUpdate, BLEError>) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_phy_update = Some(Box::new(callback));
+        self
+    }
+
+    /// Handle a change of the maximum data length of a connection.
+    pub fn on_data_len_change(
+        &mut self,
+        callback: impl FnMut(&BLEConnDesc, &DataLengthChange) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_data_len_change = Some(Box::new(callback));
+        self
+    }
+
+    /// Decide at execute time whether the prepared write queue of a client is applied.
+    ///
+    /// Writes to characteristics with [`NimbleProperties::RELIABLE_WRITE`] are checked as usual
+    /// (length, valid range, `on_validate` and `on_write`), then held per connection
+    /// instead of being stored. A value rejected by these checks makes the
+    /// "Execute Write" request fail, and the values held so far are discarded.
+    /// When NimBLE has handed over the whole queue, the callback is called with it,
+    /// and the values are stored only if the callback does not reject them.
+    ///
+    /// NimBLE answers the "Execute Write" request before the callback is called,
+    /// so a rejection by the callback is not reported to the client.
+    /// A plain write to one of these characteristics is handed over as a queue of one value.
+    pub fn on_execute_write(
+        &mut self,
+        callback: impl FnMut(&mut OnExecuteWriteArgs) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_execute_write = Some(Box::new(callback));
+        self
+    }
+
     pub fn start(&mut self) -> Result<(), BLEError> {
         if self.started {
             return Ok(());
@@ -115,16 +183,28 @@ impl BLEServer {
 
         unsafe {
             esp_idf_sys::ble_gatts_reset();
+            if !self.register_cb_installed {
+                self.prev_register_cb = esp_idf_sys::ble_hs_cfg.gatts_register_cb;
+                esp_idf_sys::ble_hs_cfg.gatts_register_cb =
+                    Some(super::ble_gatt_table::on_register);
+                self.register_cb_installed = true;
+            }
             esp_idf_sys::ble_svc_gap_init();
             esp_idf_sys::ble_svc_gatt_init();
         }
 
-        for svc in &mut self.services {
+        // Included services are referenced by their definitions.
+        for svc in &self.services {
+            svc.lock().construct_svc_def();
+        }
+        for svc in &self.services {
             svc.lock().start()?;
         }
 
         unsafe {
-            ble!(esp_idf_sys::ble_gatts_start())?;
+            let rc = ble!(esp_idf_sys::ble_gatts_start());
+            self.registering_service = None;
+            rc?;
 
             for svc in &self.services {
                 let mut svc = svc.lock();
@@ -135,6 +215,7 @@ impl BLEServer {
 
                 for chr in &svc.characteristics {
                     let mut chr = chr.lock();
+                    chr.resolve_aggregate_format();
                     if chr
                         .properties
                         .intersects(NimbleProperties::INDICATE | NimbleProperties::NOTIFY)
@@ -146,6 +227,10 @@ impl BLEServer {
             }
         }
 
+        ble_npl_event_init(
+            &mut self.execute_write_event,
+            Some(Self::handle_execute_write_event),
+        );
         self.started = true;
 
         Ok(())
@@ -172,6 +257,32 @@ impl BLEServer {
         }
     }
 
+    /// The services registered by this server, sorted by handle.
+    /// Empty until the server is started.
+    pub fn gatt_table(&self) -> Vec<GattServiceInfo> {
+        if !self.started {
+            return Vec::new();
+        }
+
+        let mut table: Vec<GattServiceInfo> = self
+            .services
+            .iter()
+            .map(|x| super::ble_gatt_table::service_info(&x.lock()))
+            .collect();
+        table.sort_by_key(|x| x.start_handle);
+        table
+    }
+
+    pub(super) fn local_service(
+        &self,
+        svc_def: *const esp_idf_sys::ble_gatt_svc_def,
+    ) -> Option<Arc<Mutex<BLEService>>> {
+        self.services
+            .iter()
+            .find(|x| core::ptr::eq(unsafe { x.raw() }.svc_def_ptr(), svc_def))
+            .cloned()
+    }
+
     pub fn connected_count(&self) -> usize {
         self.connections.len()
     }
@@ -183,7 +294,15 @@ impl BLEServer {
     }
 
     pub fn create_service(&mut self, uuid: BleUuid) -> Arc<Mutex<BLEService>> {
-        let service = Arc::new(Mutex::new(BLEService::new(uuid)));
+        let service = Arc::new(Mutex::new(BLEService::new(uuid, false)));
+        self.services.push(service.clone());
+        service
+    }
+
+    /// Create a secondary service.
+    /// It is only discoverable by clients through a service that includes it with [`BLEService::include`].
+    pub fn create_secondary_service(&mut self, uuid: BleUuid) -> Arc<Mutex<BLEService>> {
+        let service = Arc::new(Mutex::new(BLEService::new(uuid, true)));
         self.services.push(service.clone());
         service
     }
@@ -201,6 +320,22 @@ impl BLEServer {
         self
     }
 
+    /// When advertising is started after a disconnection,
+    /// first use high duty cycle directed advertising to the last bonded central.
+    ///
+    /// Requires `advertise_on_disconnect`.
+    /// With extended advertising use `BLEExtAdvertising::start_fast_reconnect` instead.
+    #[cfg(not(esp_idf_bt_nimble_ext_adv))]
+    pub fn fast_reconnect(&mut self, value: bool) -> &mut Self {
+        self.fast_reconnect = value;
+        self
+    }
+
+    /// Identity address of the last bonded central that disconnected.
+    pub fn last_bonded_peer(&self) -> Option<BLEAddress> {
+        self.last_bonded_peer
+    }
+
     /// Request an Update the connection parameters:
     /// Can only be used after a connection has been established.
     ///
@@ -231,14 +366,25 @@ impl BLEServer {
 
     pub(crate) fn reset(&mut self) {
         self.advertise_on_disconnect = true;
+        #[cfg(not(esp_idf_bt_nimble_ext_adv))]
+        self.fast_reconnect = false;
+        self.last_bonded_peer = None;
         self.services.clear();
         self.notify_characteristic.clear();
         self.connections.clear();
+        if self.register_cb_installed {
+            unsafe { esp_idf_sys::ble_hs_cfg.gatts_register_cb = self.prev_register_cb };
+            self.register_cb_installed = false;
+        }
         self.on_connect = None;
         self.on_disconnect = None;
         self.on_passkey_request = None;
         self.on_confirm_pin = None;
         self.on_authentication_complete = None;
+        self.on_phy_update = None;
+        self.on_data_len_change = None;
+        self.prepared_writes.clear();
+        self.on_execute_write = None;
     }
 
     pub(crate) extern "C" fn handle_gap_event(
@@ -262,6 +408,12 @@ impl BLEServer {
                         return esp_idf_sys::BLE_ATT_ERR_INSUFFICIENT_RES as _;
                     }
 
+                    for svc in &server.services {
+                        for chr in &svc.lock().characteristics {
+                            chr.lock().connect(connect.conn_handle);
+                        }
+                    }
+
                     if let Ok(desc) = ble_gap_conn_find(connect.conn_handle) {
                         let server = UnsafeCell::new(server);
                         unsafe {
@@ -281,19 +433,37 @@ impl BLEServer {
                 {
                     server.connections.swap_remove(idx);
                 }
+                server.complete_indicate(disconnect.conn.conn_handle, esp_idf_sys::BLE_HS_ENOTCONN);
+                server.discard_prepared_writes(disconnect.conn.conn_handle);
+                server.notify_tx.notify();
+                ble_phy::on_disconnect(disconnect.conn.conn_handle);
+
+                let desc = BLEConnDesc(disconnect.conn);
+                if desc.bonded() {
+                    server.last_bonded_peer = Some(desc.id_address());
+                }
+
+                for svc in &server.services {
+                    for chr in &svc.lock().characteristics {
+                        chr.lock().disconnect(desc.conn_handle());
+                    }
+                }
 
                 if let Some(callback) = server.on_disconnect.as_mut() {
-                    callback(
-                        &BLEConnDesc(disconnect.conn),
-                        BLEError::convert(disconnect.reason as _),
-                    );
+                    callback(&desc, BLEError::convert(disconnect.reason as _));
                 }
 
                 #[cfg(not(esp_idf_bt_nimble_ext_adv))]
-                if server.advertise_on_disconnect
-                    && let Err(err) = BLEDevice::take().get_advertising().lock().start()
-                {
-                    ::log::warn!("can't start advertising: {err:?}");
+                if server.advertise_on_disconnect {
+                    let mut advertising = BLEDevice::take().get_advertising().lock();
+                    let peer = desc.bonded().then(|| desc.id_address());
+                    let result = match peer.filter(|_| server.fast_reconnect) {
+                        Some(peer) => advertising.start_fast_reconnect(&peer),
+                        None => advertising.start(),
+                    };
+                    if let Err(err) = result {
+                        ::log::warn!("can't start advertising: {err:?}");
+                    }
                 }
             }
             esp_idf_sys::BLE_GAP_EVENT_SUBSCRIBE => {
@@ -331,19 +501,19 @@ impl BLEServer {
             }
             esp_idf_sys::BLE_GAP_EVENT_NOTIFY_TX => {
                 let notify_tx = unsafe { &event.__bindgen_anon_1.notify_tx };
+                if notify_tx.indication() > 0 && notify_tx.status != 0 {
+                    server.complete_indicate(notify_tx.conn_handle, notify_tx.status as _);
+                }
+                // The mbuf is released, wake senders waiting for one.
+                server.notify_tx.notify();
+
                 if let Some(chr) = server
                     .notify_characteristic
                     .iter_mut()
                     .find(|x| x.handle == notify_tx.attr_handle)
                 {
-                    if notify_tx.indication() > 0 {
-                        if notify_tx.status == 0 {
-                            return 0;
-                        }
-
-                        BLEDevice::take()
-                            .get_server()
-                            .clear_indicate_wait(notify_tx.conn_handle);
+                    if notify_tx.indication() > 0 && notify_tx.status == 0 {
+                        return 0;
                     }
 
                     if let Some(callback) = &mut chr.on_notify_tx {
@@ -452,25 +622,24 @@ impl BLEServer {
                 any(esp_idf_version_minor = "1", esp_idf_version_minor = "2"),
             )))]
             esp_idf_sys::BLE_GAP_EVENT_DATA_LEN_CHG => {
-                let data_len = unsafe { &event.__bindgen_anon_1.data_len_chg };
-                ::log::debug!(
-                    "==========
-data length changed:
-conn_handle={},
-tx_octets={},
-tx_time={},
-rx_octets={},
-rx_time={}
-==========",
-                    data_len.conn_handle,
-                    data_len.max_tx_octets,
-                    data_len.max_tx_time,
-                    data_len.max_rx_octets,
-                    data_len.max_rx_time
-                );
+                let (conn_handle, change) = ble_phy::on_data_len_change(event);
+                ::log::debug!("data length changed; conn_handle={conn_handle} {change:?}");
+
+                if let Some(callback) = server.on_data_len_change.as_mut()
+                    && let Ok(desc) = ble_gap_conn_find(conn_handle)
+                {
+                    callback(&desc, &change);
+                }
             }
-            esp_idf_sys::BLE_GAP_EVENT_IDENTITY_RESOLVED
-            | esp_idf_sys::BLE_GAP_EVENT_PHY_UPDATE_COMPLETE => {}
+            esp_idf_sys::BLE_GAP_EVENT_PHY_UPDATE_COMPLETE => {
+                let (conn_handle, result) = ble_phy::on_phy_update(event);
+                if let Some(callback) = server.on_phy_update.as_mut()
+                    && let Ok(desc) = ble_gap_conn_find(conn_handle)
+                {
+                    callback(&desc, result);
+                }
+            }
+            esp_idf_sys::BLE_GAP_EVENT_IDENTITY_RESOLVED => {}
             _ => {
                 ::log::warn!("unhandled event: {}", event.type_);
             }
@@ -479,8 +648,77 @@ rx_time={}
         0
     }
 
-    pub(super) fn set_indicate_wait(&self, conn_handle: u16) -> bool {
-        !self.indicate_wait.contains(&conn_handle)
+    /// Returns whether writes to characteristics with `RELIABLE_WRITE` are held for [`Self::on_execute_write`].
+    pub(super) fn holds_prepared_writes(&self) -> bool {
+        self.on_execute_write.is_some()
+    }
+
+    /// Hold the value until NimBLE has handed over the rest of the queue.
+    pub(super) fn hold_prepared_write(&mut self, write: PreparedWrite) {
+        self.prepared_writes.push(write);
+        // The event is processed after the event delivering the queue.
+        ble_npl_eventq_put_dflt(&mut self.execute_write_event);
+    }
+
+    pub(super) fn discard_prepared_writes(&mut self, conn_handle: u16) {
+        self.prepared_writes
+            .retain(|x| x.conn_handle != conn_handle);
+    }
+
+    extern "C" fn handle_execute_write_event(_ev: *mut esp_idf_sys::ble_npl_event) {
+        let server = BLEDevice::take().get_server();
+
+        let mut prepared_writes = core::mem::take(&mut server.prepared_writes);
+        while let Some(conn_handle) = prepared_writes.first().map(|x| x.conn_handle) {
+            let (writes, rest): (Vec<_>, Vec<_>) = prepared_writes
+                .into_iter()
+                .partition(|x| x.conn_handle == conn_handle);
+            prepared_writes = rest;
+
+            let Ok(desc) = ble_gap_conn_find(conn_handle) else {
+                continue;
+            };
+            if let Some(callback) = server.on_execute_write.as_mut() {
+                let mut args = OnExecuteWriteArgs {
+                    writes: &writes,
+                    desc: &desc,
+                    reject: false,
+                };
+                callback(&mut args);
+                if args.reject {
+                    continue;
+                }
+            }
+
+            for write in &writes {
+                let mut characteristic = unsafe { &*write.characteristic }.lock();
+                characteristic.set_value_for(conn_handle, &write.value);
+                if write.notify {
+                    characteristic.notify();
+                }
+            }
+        }
+    }
+
+    /// Returns whether an indication started by [`Self::set_indicate_wait`] is in progress.
+    pub(super) fn indicate_pending(&self, conn_handle: u16) -> bool {
+        self.indicate_wait.contains(&conn_handle)
+    }
+
+    /// Mark an indication as in progress on the connection.
+    /// Returns the slot of the connection, or `None` if a prior indication is in progress.
+    pub(super) fn set_indicate_wait(&mut self, conn_handle: u16) -> Option<usize> {
+        if self.indicate_wait.contains(&conn_handle) {
+            return None;
+        }
+
+        let idx = self
+            .indicate_wait
+            .iter()
+            .position(|x| *x == BLE_HS_CONN_HANDLE_NONE)?;
+        self.indicate_wait[idx] = conn_handle;
+        self.indicate_signal[idx].reset();
+        Some(idx)
     }
 
     pub(super) fn clear_indicate_wait(&mut self, conn_handle: u16) {
@@ -488,4 +726,28 @@ rx_time={}
             *it = BLE_HS_CONN_HANDLE_NONE;
         }
     }
+
+    /// Finish the indication in progress on the connection with the status of `NOTIFY_TX`.
+    fn complete_indicate(&mut self, conn_handle: u16, status: u32) {
+        if let Some(idx) = self.indicate_wait.iter().position(|x| *x == conn_handle) {
+            self.indicate_wait[idx] = BLE_HS_CONN_HANDLE_NONE;
+            self.indicate_signal[idx].signal(status);
+        }
+    }
+
+    /// Number of `NOTIFY_TX` events and disconnections so far. Read before trying to send,
+    /// then pass to [`Self::wait_notify_tx`] if the send has to be retried.
+    pub(super) fn notify_tx_version(&self) -> u32 {
+        self.notify_tx.version()
+    }
+
+    /// Wait for a `NOTIFY_TX` or disconnection after `version`.
+    pub(super) async fn wait_notify_tx(&self, version: u32) {
+        self.notify_tx.wait(version).await
+    }
+
+    /// Wait for the result of the indication started in the slot.
+    pub(super) async fn wait_indicate(&self, idx: usize) -> u32 {
+        self.indicate_signal[idx].wait().await
+    }
 }
@@ -7,26 +7,32 @@ use crate::{
     utilities::{BleUuid, mutex::Mutex},
 };
 
-use super::ble_characteristic::NimbleProperties;
-
-const NULL_HANDLE: u16 = 0xFFFF;
+use super::{NULL_HANDLE, ble_characteristic::NimbleProperties};
 
 pub struct BLEService {
     pub(crate) uuid: ble_uuid_any_t,
     pub(crate) handle: u16,
+    pub(crate) end_handle: u16,
     pub(crate) characteristics: Vec<Arc<Mutex<BLECharacteristic>>>,
+    secondary: bool,
+    pub(crate) includes: Vec<Arc<Mutex<BLEService>>>,
     svc_def: Option<[esp_idf_sys::ble_gatt_svc_def; 2]>,
     svc_def_characteristics: Vec<esp_idf_sys::ble_gatt_chr_def>,
+    svc_def_includes: Vec<*const esp_idf_sys::ble_gatt_svc_def>,
 }
 
 impl BLEService {
-    pub(crate) fn new(uuid: BleUuid) -> Self {
+    pub(crate) fn new(uuid: BleUuid, secondary: bool) -> Self {
         Self {
             uuid: ble_uuid_any_t::from(uuid),
             handle: NULL_HANDLE,
+            end_handle: NULL_HANDLE,
             characteristics: Vec::new(),
+            secondary,
+            includes: Vec::new(),
             svc_def: None,
             svc_def_characteristics: Vec::new(),
+            svc_def_includes: Vec::new(),
         }
     }
 
@@ -34,10 +40,34 @@ impl BLEService {
         BleUuid::from(self.uuid)
     }
 
-    pub(crate) fn start(&mut self) -> Result<(), BLEError> {
+    pub fn is_secondary(&self) -> bool {
+        self.secondary
+    }
+
+    /// Include another service of the same server in this service.
+    ///
+    /// The include declaration is resolved when the server is started.
+    /// Including the service itself is ignored.
+    pub fn include(&mut self, service: &Arc<Mutex<BLEService>>) -> &mut Self {
+        if core::ptr::eq(unsafe { service.raw() }, self) {
+            ::log::warn!("a service cannot include itself");
+            return self;
+        }
+
+        if !self.includes.iter().any(|x| Arc::ptr_eq(x, service)) {
+            self.includes.push(service.clone());
+        }
+        self
+    }
+
+    pub(crate) fn construct_svc_def(&mut self) -> *const esp_idf_sys::ble_gatt_svc_def {
         let svc_def = self.svc_def.get_or_insert_with(|| {
             let mut svc = [esp_idf_sys::ble_gatt_svc_def::default(); 2];
-            svc[0].type_ = esp_idf_sys::BLE_GATT_SVC_TYPE_PRIMARY as _;
+            svc[0].type_ = if self.secondary {
+                esp_idf_sys::BLE_GATT_SVC_TYPE_SECONDARY as _
+            } else {
+                esp_idf_sys::BLE_GATT_SVC_TYPE_PRIMARY as _
+            };
             svc[0].uuid = unsafe { &self.uuid.u };
             svc[0].includes = core::ptr::null_mut();
 
@@ -68,6 +98,35 @@ impl BLEService {
             svc[1].type_ = 0;
             svc
         });
+        svc_def.as_ptr()
+    }
+
+    pub(crate) fn svc_def_ptr(&self) -> *const esp_idf_sys::ble_gatt_svc_def {
+        self.svc_def
+            .as_ref()
+            .map_or(core::ptr::null(), |x| x.as_ptr())
+    }
+
+    /// All services of the server must be constructed before the first one is started.
+    pub(crate) fn start(&mut self) -> Result<(), BLEError> {
+        self.construct_svc_def();
+
+        self.svc_def_includes.clear();
+        for service in &self.includes {
+            let Some(included) = unsafe { service.raw() }.svc_def.as_ref() else {
+                ::log::error!("the included service is not part of the server");
+                return BLEError::convert(esp_idf_sys::BLE_HS_EINVAL);
+            };
+            self.svc_def_includes.push(included.as_ptr());
+        }
+
+        let svc_def = self.svc_def.as_mut().unwrap();
+        svc_def[0].includes = if self.svc_def_includes.is_empty() {
+            core::ptr::null_mut()
+        } else {
+            self.svc_def_includes.push(core::ptr::null());
+            self.svc_def_includes.as_mut_ptr()
+        };
 
         unsafe {
             ble!(esp_idf_sys::ble_gatts_count_cfg(svc_def.as_ptr()))?;
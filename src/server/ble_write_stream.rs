@@ -0,0 +1,154 @@
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use embassy_sync::pipe::Pipe;
+use esp_idf_svc::hal::task::embassy_sync::EspRawMutex;
+
+/// Length of the record header: connection handle and value length, both little-endian `u16`.
+const HEADER_LEN: usize = 4;
+
+/// A queue of the values written to a characteristic,
+/// filled by [`crate::BLECharacteristic::fast_write_stream`] and consumed by an async task.
+///
+/// Every value is stored as a record with the connection handle and its length,
+/// so the boundaries of the writes are kept.
+/// Values that do not fit into the free capacity are dropped as a whole.
+/// There must be only one reader.
+///
+/// ```ignore
+/// static STREAM: WriteStream<8192> = WriteStream::new();
+/// characteristic.lock().fast_write_stream(&STREAM)?;
+/// let mut buf = [0; 512];
+/// let (conn_handle, len) = STREAM.read(&mut buf).await;
+/// ```
+pub struct WriteStream<const N: usize> {
+    pipe: Pipe<EspRawMutex, N>,
+    records: AtomicIsize,
+    dropped: AtomicUsize,
+}
+
+impl<const N: usize> WriteStream<N> {
+    pub const fn new() -> Self {
+        Self {
+            pipe: Pipe::new(),
+            records: AtomicIsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn push(&self, conn_handle: u16, data: &[u8]) {
+        // NimBLE host task is the only writer, so the free capacity can only grow.
+        if HEADER_LEN + data.len() > self.pipe.free_capacity() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let mut header = [0; HEADER_LEN];
+        header[..2].copy_from_slice(&conn_handle.to_le_bytes());
+        header[2..].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        self.write_all(&header);
+        self.write_all(data);
+        self.records.fetch_add(1, Ordering::Release);
+    }
+
+    fn write_all(&self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // Writes stop at the end of the ring buffer.
+            let Ok(n) = self.pipe.try_write(data) else {
+                break;
+            };
+            data = &data[n..];
+        }
+    }
+
+    /// Wait for the next value and copy it into `buf`.
+    ///
+    /// Returns the connection handle of the writer and the length of the value.
+    /// A value longer than `buf` is truncated to its length.
+    pub async fn read(&self, buf: &mut [u8]) -> (u16, usize) {
+        let mut header = [0; HEADER_LEN];
+        self.read_exact(&mut header).await;
+        let conn_handle = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+
+        let copy_len = len.min(buf.len());
+        self.read_exact(&mut buf[..copy_len]).await;
+
+        let mut discard = [0; 32];
+        let mut rest = len - copy_len;
+        while rest > 0 {
+            let n = rest.min(discard.len());
+            self.read_exact(&mut discard[..n]).await;
+            rest -= n;
+        }
+
+        self.records.fetch_sub(1, Ordering::Relaxed);
+        (conn_handle, copy_len)
+    }
+
+    async fn read_exact(&self, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let n = self.pipe.read(buf).await;
+            buf = &mut buf[n..];
+        }
+    }
+
+    /// Read the next value without waiting, see [`Self::read`].
+    pub fn try_read(&self, buf: &mut [u8]) -> Option<(u16, usize)> {
+        if self.records.load(Ordering::Acquire) <= 0 {
+            return None;
+        }
+
+        // The whole record is in the pipe.
+        let mut header = [0; HEADER_LEN];
+        self.try_read_exact(&mut header);
+        let conn_handle = u16::from_le_bytes([header[0], header[1]]);
+        let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+
+        let copy_len = len.min(buf.len());
+        self.try_read_exact(&mut buf[..copy_len]);
+
+        let mut discard = [0; 32];
+        let mut rest = len - copy_len;
+        while rest > 0 {
+            let n = rest.min(discard.len());
+            self.try_read_exact(&mut discard[..n]);
+            rest -= n;
+        }
+
+        self.records.fetch_sub(1, Ordering::Relaxed);
+        Some((conn_handle, copy_len))
+    }
+
+    fn try_read_exact(&self, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let Ok(n) = self.pipe.try_read(buf) else {
+                break;
+            };
+            buf = &mut buf[n..];
+        }
+    }
+
+    /// Number of values in the stream.
+    pub fn len(&self) -> usize {
+        self.records.load(Ordering::Relaxed).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.pipe.clear();
+        self.records.store(0, Ordering::Relaxed);
+    }
+
+    /// Number of values dropped because the stream was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Default for WriteStream<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,3 +1,8 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use num_enum::IntoPrimitive;
 use zerocopy_derive::{Immutable, IntoBytes, KnownLayout, TryFromBytes};
 
@@ -327,7 +332,7 @@ pub enum ChrUnit {
     VoltAmpere = esp_idf_sys::BLE_GATT_CHR_UNIT_VOLT_AMPERE as _,
 }
 
-#[derive(TryFromBytes, KnownLayout, IntoBytes, Immutable)]
+#[derive(Copy, Clone, Debug, TryFromBytes, KnownLayout, IntoBytes, Immutable)]
 #[repr(C, packed)]
 pub struct Cpfd {
     /// Format of the value of this characteristic.
@@ -341,3 +346,409 @@ pub struct Cpfd {
     /// The description of this characteristic. Depends on name space.
     pub description: u16,
 }
+
+impl ChrUnit {
+    /// The symbol of the unit, if it has a common one.
+    pub fn symbol(self) -> Option<&'static str> {
+        Some(match self {
+            Self::Metre => "m",
+            Self::Kilogram => "kg",
+            Self::Second => "s",
+            Self::Ampere => "A",
+            Self::Kelvin => "K",
+            Self::Mole => "mol",
+            Self::Candela => "cd",
+            Self::SquareMetres => "m²",
+            Self::CubicMetres => "m³",
+            Self::MetresPerSecond => "m/s",
+            Self::MetresPerSecondSquared => "m/s²",
+            Self::Radian => "rad",
+            Self::Hertz => "Hz",
+            Self::Newton => "N",
+            Self::Pascal => "Pa",
+            Self::Joule => "J",
+            Self::Watt => "W",
+            Self::Coulomb => "C",
+            Self::Volt => "V",
+            Self::Farad => "F",
+            Self::Ohm => "Ω",
+            Self::Siemens => "S",
+            Self::Weber => "Wb",
+            Self::Tesla => "T",
+            Self::Henry => "H",
+            Self::DegreeCelsius => "°C",
+            Self::Lumen => "lm",
+            Self::Lux => "lx",
+            Self::Becquerel => "Bq",
+            Self::Gray => "Gy",
+            Self::Sievert => "Sv",
+            Self::NewtonMetre => "N·m",
+            Self::RadianPerSecond => "rad/s",
+            Self::Minute => "min",
+            Self::Hour => "h",
+            Self::Day => "d",
+            Self::Degree => "°",
+            Self::Litre => "L",
+            Self::Tonne => "t",
+            Self::Bar => "bar",
+            Self::MillimetreOfMercury => "mmHg",
+            Self::Knot => "kn",
+            Self::Inch => "in",
+            Self::Foot => "ft",
+            Self::Mile => "mi",
+            Self::PoundForcePerSquareInch => "psi",
+            Self::KilometrePerHour => "km/h",
+            Self::MilePerHour => "mph",
+            Self::RevolutionPerMinute => "rpm",
+            Self::KilowattHour => "kWh",
+            Self::DegreeFahrenheit => "°F",
+            Self::Percentage => "%",
+            Self::PerMille => "‰",
+            Self::BeatsPerMinute => "bpm",
+            Self::AmpereHours => "Ah",
+            Self::Decibel => "dB",
+            Self::PartsPerMillion => "ppm",
+            Self::PartsPerBillion => "ppb",
+            _ => return None,
+        })
+    }
+}
+
+impl Cpfd {
+    /// The size of a value of this format, `None` for variable length formats.
+    pub fn size(&self) -> Option<usize> {
+        Some(match self.format {
+            ChrFormat::Boolean
+            | ChrFormat::Uint2
+            | ChrFormat::Uint4
+            | ChrFormat::Uint8
+            | ChrFormat::Sint8 => 1,
+            ChrFormat::Uint12
+            | ChrFormat::Uint16
+            | ChrFormat::Sint12
+            | ChrFormat::Sint16
+            | ChrFormat::Medfloat16 => 2,
+            ChrFormat::Uint24 | ChrFormat::Sint24 => 3,
+            ChrFormat::Uint32
+            | ChrFormat::Sint32
+            | ChrFormat::Float32
+            | ChrFormat::Medfloat32
+            | ChrFormat::Uint162 => 4,
+            ChrFormat::Uint48 | ChrFormat::Sint48 => 6,
+            ChrFormat::Uint64 | ChrFormat::Sint64 | ChrFormat::Float64 => 8,
+            ChrFormat::Uint128 | ChrFormat::Sint128 => 16,
+            ChrFormat::Utf8s | ChrFormat::Utf16s | ChrFormat::Struct | ChrFormat::Medasn1 => {
+                return None;
+            }
+        })
+    }
+
+    /// Format a value of this format into a human readable string,
+    /// applying the exponent and appending the unit.
+    ///
+    /// Returns `None` if the value does not match the format,
+    /// or the format is `Struct`, `Medasn1` or `Uint128`.
+    pub fn format_value(&self, value: &[u8]) -> Option<String> {
+        if self.size().is_some_and(|size| size != value.len()) {
+            return None;
+        }
+
+        let exponent = self.exponent as i32;
+        let number = match self.format {
+            ChrFormat::Boolean => {
+                return Some(String::from(if value[0] & 0x01 != 0 {
+                    "true"
+                } else {
+                    "false"
+                }));
+            }
+            ChrFormat::Utf8s => return Some(String::from_utf8_lossy(value).into_owned()),
+            ChrFormat::Utf16s => {
+                if !value.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = value
+                    .chunks_exact(2)
+                    .map(|x| u16::from_le_bytes([x[0], x[1]]));
+                return Some(
+                    char::decode_utf16(units)
+                        .map(|x| x.unwrap_or('\u{FFFD}'))
+                        .collect(),
+                );
+            }
+            ChrFormat::Struct | ChrFormat::Medasn1 | ChrFormat::Uint128 => return None,
+            ChrFormat::Uint2 => decimal((value[0] & 0x03) as i128, exponent),
+            ChrFormat::Uint4 => decimal((value[0] & 0x0F) as i128, exponent),
+            ChrFormat::Uint12 => decimal((le_unsigned(value) & 0x0FFF) as i128, exponent),
+            ChrFormat::Uint8
+            | ChrFormat::Uint16
+            | ChrFormat::Uint24
+            | ChrFormat::Uint32
+            | ChrFormat::Uint48
+            | ChrFormat::Uint64 => decimal(le_unsigned(value) as i128, exponent),
+            ChrFormat::Sint12 => decimal(sign_extend(le_unsigned(value), 12), exponent),
+            ChrFormat::Sint8
+            | ChrFormat::Sint16
+            | ChrFormat::Sint24
+            | ChrFormat::Sint32
+            | ChrFormat::Sint48
+            | ChrFormat::Sint64
+            | ChrFormat::Sint128 => decimal(
+                sign_extend(le_unsigned(value), value.len() as u32 * 8),
+                exponent,
+            ),
+            ChrFormat::Uint162 => format!(
+                "{}, {}",
+                decimal(le_unsigned(&value[..2]) as i128, exponent),
+                decimal(le_unsigned(&value[2..]) as i128, exponent)
+            ),
+            ChrFormat::Float32 => float(
+                f32::from_le_bytes(value.try_into().unwrap()) as f64,
+                exponent,
+            ),
+            ChrFormat::Float64 => float(f64::from_le_bytes(value.try_into().unwrap()), exponent),
+            ChrFormat::Medfloat16 => {
+                let raw = le_unsigned(value);
+                match raw {
+                    0x07FF..=0x0801 => String::from("NaN"),
+                    0x07FE => String::from("inf"),
+                    0x0802 => String::from("-inf"),
+                    _ => decimal(
+                        sign_extend(raw & 0x0FFF, 12),
+                        sign_extend(raw >> 12, 4) as i32 + exponent,
+                    ),
+                }
+            }
+            ChrFormat::Medfloat32 => {
+                let raw = le_unsigned(value);
+                match raw {
+                    0x007F_FFFF..=0x0080_0001 => String::from("NaN"),
+                    0x007F_FFFE => String::from("inf"),
+                    0x0080_0002 => String::from("-inf"),
+                    _ => decimal(
+                        sign_extend(raw & 0x00FF_FFFF, 24),
+                        sign_extend(raw >> 24, 8) as i32 + exponent,
+                    ),
+                }
+            }
+        };
+
+        let unit = self.unit;
+        Some(if unit == ChrUnit::Unitless {
+            number
+        } else if let Some(symbol) = unit.symbol() {
+            format!("{number} {symbol}")
+        } else {
+            format!("{number} {unit:?}")
+        })
+    }
+}
+
+/// Format each field of an aggregate value, described by the formats in order.
+///
+/// Only the last format can have a variable size.
+pub fn format_aggregate(formats: &[Cpfd], value: &[u8]) -> Option<Vec<String>> {
+    let mut fields = Vec::with_capacity(formats.len());
+    let mut rest = value;
+    for (idx, cpfd) in formats.iter().enumerate() {
+        let size = match cpfd.size() {
+            Some(size) => size,
+            None if idx == formats.len() - 1 => rest.len(),
+            None => return None,
+        };
+        if rest.len() < size {
+            return None;
+        }
+        let (field, tail) = rest.split_at(size);
+        fields.push(cpfd.format_value(field)?);
+        rest = tail;
+    }
+    rest.is_empty().then_some(fields)
+}
+
+fn le_unsigned(value: &[u8]) -> u128 {
+    value.iter().rev().fold(0, |acc, x| (acc << 8) | *x as u128)
+}
+
+fn sign_extend(value: u128, bits: u32) -> i128 {
+    let shift = 128 - bits;
+    ((value << shift) as i128) >> shift
+}
+
+/// `mantissa * 10^exponent` without rounding errors.
+fn decimal(mantissa: i128, exponent: i32) -> String {
+    if mantissa == 0 {
+        return String::from("0");
+    }
+
+    let mut digits = mantissa.unsigned_abs().to_string();
+    if exponent >= 0 {
+        digits.extend(core::iter::repeat_n('0', exponent as usize));
+    } else {
+        let fraction = exponent.unsigned_abs() as usize;
+        if digits.len() <= fraction {
+            let zeros: String = core::iter::repeat_n('0', fraction + 1 - digits.len()).collect();
+            digits.insert_str(0, &zeros);
+        }
+        digits.insert(digits.len() - fraction, '.');
+    }
+
+    if mantissa < 0 {
+        digits.insert(0, '-');
+    }
+    digits
+}
+
+fn float(value: f64, exponent: i32) -> String {
+    // Dividing by the exact power of ten rounds once, unlike multiplying by 0.1 repeatedly.
+    let scale = pow10(exponent.unsigned_abs());
+    if exponent >= 0 {
+        format!("{}", value * scale)
+    } else {
+        format!("{}", value / scale)
+    }
+}
+
+#[cfg(feature = "std")]
+fn pow10(exponent: u32) -> f64 {
+    10f64.powi(exponent as i32)
+}
+
+// `powi` needs std.
+#[cfg(not(feature = "std"))]
+fn pow10(exponent: u32) -> f64 {
+    (0..exponent).fold(1.0, |acc, _| acc * 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpfd(format: ChrFormat, exponent: i8, unit: ChrUnit) -> Cpfd {
+        Cpfd {
+            format,
+            exponent,
+            unit,
+            name_space: 0,
+            description: 0,
+        }
+    }
+
+    #[test]
+    fn integers() {
+        let temperature = cpfd(ChrFormat::Sint16, -2, ChrUnit::DegreeCelsius);
+        assert_eq!(
+            temperature.format_value(&2345i16.to_le_bytes()).as_deref(),
+            Some("23.45 °C")
+        );
+        assert_eq!(
+            temperature.format_value(&(-5i16).to_le_bytes()).as_deref(),
+            Some("-0.05 °C")
+        );
+        assert_eq!(temperature.format_value(&[0]), None);
+
+        let count = cpfd(ChrFormat::Uint24, 3, ChrUnit::Unitless);
+        assert_eq!(count.format_value(&[12, 0, 0]).as_deref(), Some("12000"));
+        assert_eq!(count.format_value(&[0, 0, 0]).as_deref(), Some("0"));
+
+        let sint12 = cpfd(ChrFormat::Sint12, 0, ChrUnit::Unitless);
+        assert_eq!(sint12.format_value(&[0xFF, 0x0F]).as_deref(), Some("-1"));
+        let uint12 = cpfd(ChrFormat::Uint12, 0, ChrUnit::Unitless);
+        assert_eq!(uint12.format_value(&[0xFF, 0xFF]).as_deref(), Some("4095"));
+
+        let pair = cpfd(ChrFormat::Uint162, -1, ChrUnit::Volt);
+        assert_eq!(
+            pair.format_value(&[5, 0, 15, 0]).as_deref(),
+            Some("0.5, 1.5 V")
+        );
+    }
+
+    #[test]
+    fn other_formats() {
+        let boolean = cpfd(ChrFormat::Boolean, 0, ChrUnit::Unitless);
+        assert_eq!(boolean.format_value(&[1]).as_deref(), Some("true"));
+        assert_eq!(boolean.format_value(&[2]).as_deref(), Some("false"));
+
+        let utf8 = cpfd(ChrFormat::Utf8s, 0, ChrUnit::Unitless);
+        assert_eq!(utf8.format_value(b"abc").as_deref(), Some("abc"));
+
+        let utf16 = cpfd(ChrFormat::Utf16s, 0, ChrUnit::Unitless);
+        assert_eq!(
+            utf16.format_value(&[b'h', 0, b'i', 0]).as_deref(),
+            Some("hi")
+        );
+        assert_eq!(utf16.format_value(&[b'h', 0, b'i']), None);
+
+        let unsupported = cpfd(ChrFormat::Struct, 0, ChrUnit::Unitless);
+        assert_eq!(unsupported.format_value(&[1, 2]), None);
+    }
+
+    #[test]
+    fn floats() {
+        let float32 = cpfd(ChrFormat::Float32, -2, ChrUnit::Unitless);
+        assert_eq!(
+            float32.format_value(&1234f32.to_le_bytes()).as_deref(),
+            Some("12.34")
+        );
+        let float64 = cpfd(ChrFormat::Float64, -1, ChrUnit::Unitless);
+        assert_eq!(
+            float64.format_value(&3f64.to_le_bytes()).as_deref(),
+            Some("0.3")
+        );
+        let float64 = cpfd(ChrFormat::Float64, 2, ChrUnit::Unitless);
+        assert_eq!(
+            float64.format_value(&1.5f64.to_le_bytes()).as_deref(),
+            Some("150")
+        );
+
+        // Mantissa 0x0FF and exponent -1.
+        let medfloat16 = cpfd(ChrFormat::Medfloat16, 0, ChrUnit::Percentage);
+        assert_eq!(
+            medfloat16.format_value(&[0xFF, 0xF0]).as_deref(),
+            Some("25.5 %")
+        );
+        assert_eq!(
+            medfloat16.format_value(&[0xFF, 0x07]).as_deref(),
+            Some("NaN %")
+        );
+        assert_eq!(
+            medfloat16.format_value(&[0x02, 0x08]).as_deref(),
+            Some("-inf %")
+        );
+
+        let medfloat32 = cpfd(ChrFormat::Medfloat32, 0, ChrUnit::Unitless);
+        assert_eq!(
+            medfloat32.format_value(&[0x0C, 0, 0, 0x02]).as_deref(),
+            Some("1200")
+        );
+        assert_eq!(
+            medfloat32.format_value(&[0xFE, 0xFF, 0x7F, 0]).as_deref(),
+            Some("inf")
+        );
+    }
+
+    #[test]
+    fn aggregate() {
+        let formats = [
+            cpfd(ChrFormat::Uint8, 0, ChrUnit::Percentage),
+            cpfd(ChrFormat::Sint16, -1, ChrUnit::DegreeCelsius),
+            cpfd(ChrFormat::Utf8s, 0, ChrUnit::Unitless),
+        ];
+        assert_eq!(
+            format_aggregate(&formats, &[50, 0xFB, 0xFF, b'o', b'k']),
+            Some(["50 %", "-0.5 °C", "ok"].map(String::from).to_vec())
+        );
+        assert_eq!(
+            format_aggregate(&formats, &[50, 0xFB, 0xFF]),
+            Some(["50 %", "-0.5 °C", ""].map(String::from).to_vec())
+        );
+        assert_eq!(format_aggregate(&formats, &[50, 0xFB]), None);
+
+        let fixed = &formats[..2];
+        assert_eq!(format_aggregate(fixed, &[50, 0xFB, 0xFF, 0]), None);
+
+        // Only the last format can have a variable size.
+        let variable_first = [formats[2], formats[0]];
+        assert_eq!(format_aggregate(&variable_first, b"ok"), None);
+    }
+}
@@ -0,0 +1,387 @@
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use esp_idf_svc::sys;
+
+use crate::{BLEError, cpfd::ChrFormat};
+
+/// Characteristic Extended Properties
+pub const EXTENDED_PROPERTIES_UUID16: u16 = 0x2900;
+/// Characteristic User Description
+pub const USER_DESCRIPTION_UUID16: u16 = 0x2901;
+/// Server Characteristic Configuration
+pub const SERVER_CONFIGURATION_UUID16: u16 = 0x2903;
+/// Characteristic Presentation Format
+pub const PRESENTATION_FORMAT_UUID16: u16 = 0x2904;
+/// Characteristic Aggregate Format
+pub const AGGREGATE_FORMAT_UUID16: u16 = 0x2905;
+/// Valid Range
+pub const VALID_RANGE_UUID16: u16 = 0x2906;
+/// Report Reference
+pub const REPORT_REFERENCE_UUID16: u16 = 0x2908;
+/// Environmental Sensing Configuration
+pub const ES_CONFIGURATION_UUID16: u16 = 0x290B;
+/// Environmental Sensing Measurement
+pub const ES_MEASUREMENT_UUID16: u16 = 0x290C;
+/// Environmental Sensing Trigger Setting
+pub const ES_TRIGGER_SETTING_UUID16: u16 = 0x290D;
+
+/// ATT error code sent when a written value is outside of the Valid Range.
+pub const OUT_OF_RANGE: u8 = 0xFF;
+
+bitflags! {
+  #[repr(transparent)]
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct ExtendedProperties: u16 {
+    const RELIABLE_WRITE = 0x0001;
+    const WRITABLE_AUXILIARIES = 0x0002;
+  }
+}
+
+bitflags! {
+  #[repr(transparent)]
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  pub struct ServerConfiguration: u16 {
+    const BROADCAST = 0x0001;
+  }
+}
+
+/// Lower and upper inclusive bounds of an integer characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidRange {
+    format: ChrFormat,
+    lower: i64,
+    upper: i64,
+}
+
+impl ValidRange {
+    /// Supports the unsigned formats up to `Uint48` and the signed formats up to `Sint64`.
+    pub fn new(format: ChrFormat, lower: i64, upper: i64) -> Result<Self, BLEError> {
+        let range = Self {
+            format,
+            lower,
+            upper,
+        };
+
+        let Some((min, max)) = range.limits() else {
+            return Err(BLEError::convert(sys::BLE_HS_ENOTSUP).unwrap_err());
+        };
+        if lower > upper || lower < min || upper > max {
+            return Err(BLEError::convert(sys::BLE_HS_EINVAL).unwrap_err());
+        }
+        Ok(range)
+    }
+
+    pub fn format(&self) -> ChrFormat {
+        self.format
+    }
+
+    pub fn lower(&self) -> i64 {
+        self.lower
+    }
+
+    pub fn upper(&self) -> i64 {
+        self.upper
+    }
+
+    /// Whether the encoded value has the size of the format and is within the range.
+    pub fn contains(&self, value: &[u8]) -> bool {
+        self.decode(value)
+            .is_some_and(|x| self.lower <= x && x <= self.upper)
+    }
+
+    /// The descriptor value: the lower bound followed by the upper bound.
+    pub fn value(&self) -> Vec<u8> {
+        let size = self.size();
+        let mut value = Vec::with_capacity(size * 2);
+        value.extend_from_slice(&self.lower.to_le_bytes()[..size]);
+        value.extend_from_slice(&self.upper.to_le_bytes()[..size]);
+        value
+    }
+
+    fn decode(&self, value: &[u8]) -> Option<i64> {
+        if value.len() != self.size() {
+            return None;
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes[..value.len()].copy_from_slice(value);
+        let raw = i64::from_le_bytes(bytes);
+        let shift = 64 - 8 * value.len() as u32;
+        Some(if self.signed() {
+            (raw << shift) >> shift
+        } else {
+            raw
+        })
+    }
+
+    fn limits(&self) -> Option<(i64, i64)> {
+        let bits = match self.format {
+            ChrFormat::Uint8 | ChrFormat::Sint8 => 8,
+            ChrFormat::Uint12 | ChrFormat::Sint12 => 12,
+            ChrFormat::Uint16 | ChrFormat::Sint16 => 16,
+            ChrFormat::Uint24 | ChrFormat::Sint24 => 24,
+            ChrFormat::Uint32 | ChrFormat::Sint32 => 32,
+            ChrFormat::Uint48 | ChrFormat::Sint48 => 48,
+            ChrFormat::Sint64 => 64,
+            _ => return None,
+        };
+
+        Some(if self.signed() {
+            let max = (1i128 << (bits - 1)) - 1;
+            (-max as i64 - 1, max as i64)
+        } else {
+            (0, (1i64 << bits) - 1)
+        })
+    }
+
+    fn signed(&self) -> bool {
+        matches!(
+            self.format,
+            ChrFormat::Sint8
+                | ChrFormat::Sint12
+                | ChrFormat::Sint16
+                | ChrFormat::Sint24
+                | ChrFormat::Sint32
+                | ChrFormat::Sint48
+                | ChrFormat::Sint64
+        )
+    }
+
+    fn size(&self) -> usize {
+        match self.format {
+            ChrFormat::Uint8 | ChrFormat::Sint8 => 1,
+            ChrFormat::Uint12 | ChrFormat::Sint12 | ChrFormat::Uint16 | ChrFormat::Sint16 => 2,
+            ChrFormat::Uint24 | ChrFormat::Sint24 => 3,
+            ChrFormat::Uint32 | ChrFormat::Sint32 => 4,
+            ChrFormat::Uint48 | ChrFormat::Sint48 => 6,
+            _ => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ReportType {
+    Input = 0x01,
+    Output = 0x02,
+    Feature = 0x03,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EsSamplingFunction {
+    Unspecified = 0x00,
+    Instantaneous = 0x01,
+    ArithmeticMean = 0x02,
+    Rms = 0x03,
+    Maximum = 0x04,
+    Minimum = 0x05,
+    Accumulated = 0x06,
+    Count = 0x07,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EsApplication {
+    Unspecified = 0x00,
+    Air = 0x01,
+    Water = 0x02,
+    Barometric = 0x03,
+    Soil = 0x04,
+    Infrared = 0x05,
+    MapDatabase = 0x06,
+    BarometricElevationSource = 0x07,
+    GpsOnlyElevationSource = 0x08,
+    GpsAndMapDatabaseElevationSource = 0x09,
+    VerticalDatumElevationSource = 0x0A,
+    Onshore = 0x0B,
+    OnboardVesselOrVehicle = 0x0C,
+    Front = 0x0D,
+    BackRear = 0x0E,
+    Upper = 0x0F,
+    Lower = 0x10,
+    Primary = 0x11,
+    Secondary = 0x12,
+    Outdoor = 0x13,
+    Indoor = 0x14,
+    Top = 0x15,
+    Bottom = 0x16,
+    Main = 0x17,
+    Backup = 0x18,
+    Auxiliary = 0x19,
+    Supplementary = 0x1A,
+    Inside = 0x1B,
+    Outside = 0x1C,
+    Left = 0x1D,
+    Right = 0x1E,
+    Internal = 0x1F,
+    External = 0x20,
+    Solar = 0x21,
+}
+
+/// Environmental Sensing Measurement descriptor value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EsMeasurement {
+    pub sampling_function: EsSamplingFunction,
+    /// Measurement period in seconds, 24 bits. 0 if not in use.
+    pub measurement_period: u32,
+    /// Internal update interval in seconds, 24 bits. 0 if not in use.
+    pub update_interval: u32,
+    pub application: EsApplication,
+    /// Measurement uncertainty in 0.5% steps. 0xFF if unknown.
+    pub uncertainty: u8,
+}
+
+impl EsMeasurement {
+    pub fn value(&self) -> [u8; 11] {
+        let period = self.measurement_period.to_le_bytes();
+        let interval = self.update_interval.to_le_bytes();
+        [
+            // Flags (reserved)
+            0,
+            0,
+            self.sampling_function as u8,
+            period[0],
+            period[1],
+            period[2],
+            interval[0],
+            interval[1],
+            interval[2],
+            self.application as u8,
+            self.uncertainty,
+        ]
+    }
+}
+
+/// Environmental Sensing Trigger Setting descriptor value.
+///
+/// The compared values are encoded in the format of the characteristic value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EsTriggerSetting {
+    Inactive,
+    /// Notify at a fixed interval in seconds, 24 bits.
+    FixedInterval(u32),
+    /// Notify no more often than the interval in seconds, 24 bits.
+    NoLessThan(u32),
+    ValueChanged,
+    LessThan(Vec<u8>),
+    LessThanOrEqual(Vec<u8>),
+    GreaterThan(Vec<u8>),
+    GreaterThanOrEqual(Vec<u8>),
+    EqualTo(Vec<u8>),
+    NotEqualTo(Vec<u8>),
+}
+
+impl EsTriggerSetting {
+    pub fn value(&self) -> Vec<u8> {
+        let interval;
+        let (condition, operand): (u8, &[u8]) = match self {
+            Self::Inactive => (0x00, &[]),
+            Self::FixedInterval(x) => {
+                interval = x.to_le_bytes();
+                (0x01, &interval[..3])
+            }
+            Self::NoLessThan(x) => {
+                interval = x.to_le_bytes();
+                (0x02, &interval[..3])
+            }
+            Self::ValueChanged => (0x03, &[]),
+            Self::LessThan(x) => (0x04, x),
+            Self::LessThanOrEqual(x) => (0x05, x),
+            Self::GreaterThan(x) => (0x06, x),
+            Self::GreaterThanOrEqual(x) => (0x07, x),
+            Self::EqualTo(x) => (0x08, x),
+            Self::NotEqualTo(x) => (0x09, x),
+        };
+
+        let mut value = Vec::with_capacity(1 + operand.len());
+        value.push(condition);
+        value.extend_from_slice(operand);
+        value
+    }
+}
+
+/// Environmental Sensing Configuration descriptor value,
+/// how multiple Trigger Setting descriptors are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EsConfiguration {
+    BooleanAnd = 0x00,
+    BooleanOr = 0x01,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_range_bounds() {
+        let range = ValidRange::new(ChrFormat::Sint16, -10, 300).unwrap();
+        assert_eq!(range.format(), ChrFormat::Sint16);
+        assert_eq!((range.lower(), range.upper()), (-10, 300));
+
+        let code = |result: Result<ValidRange, BLEError>| result.unwrap_err().code();
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Uint8, 10, 5)),
+            sys::BLE_HS_EINVAL
+        );
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Uint8, 0, 256)),
+            sys::BLE_HS_EINVAL
+        );
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Uint16, -1, 10)),
+            sys::BLE_HS_EINVAL
+        );
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Sint12, -2049, 0)),
+            sys::BLE_HS_EINVAL
+        );
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Float32, 0, 1)),
+            sys::BLE_HS_ENOTSUP
+        );
+        assert_eq!(
+            code(ValidRange::new(ChrFormat::Uint64, 0, 1)),
+            sys::BLE_HS_ENOTSUP
+        );
+
+        assert!(ValidRange::new(ChrFormat::Sint12, -2048, 2047).is_ok());
+        assert!(ValidRange::new(ChrFormat::Uint48, 0, (1 << 48) - 1).is_ok());
+        assert!(ValidRange::new(ChrFormat::Sint64, i64::MIN, i64::MAX).is_ok());
+    }
+
+    #[test]
+    fn valid_range_value() {
+        let range = ValidRange::new(ChrFormat::Sint16, -10, 300).unwrap();
+        assert_eq!(range.value(), [0xF6, 0xFF, 0x2C, 0x01]);
+
+        let range = ValidRange::new(ChrFormat::Uint24, 1, 0x123456).unwrap();
+        assert_eq!(range.value(), [0x01, 0x00, 0x00, 0x56, 0x34, 0x12]);
+
+        let range = ValidRange::new(ChrFormat::Uint8, 0, 100).unwrap();
+        assert_eq!(range.value(), [0, 100]);
+    }
+
+    #[test]
+    fn valid_range_contains() {
+        let range = ValidRange::new(ChrFormat::Sint16, -10, 300).unwrap();
+        assert!(range.contains(&(-10i16).to_le_bytes()));
+        assert!(range.contains(&300i16.to_le_bytes()));
+        assert!(!range.contains(&(-11i16).to_le_bytes()));
+        assert!(!range.contains(&301i16.to_le_bytes()));
+        // The value must have the size of the format.
+        assert!(!range.contains(&[0]));
+        assert!(!range.contains(&[0, 0, 0]));
+
+        // Signed values are sign extended, unsigned ones are not.
+        let range = ValidRange::new(ChrFormat::Sint12, -5, 5).unwrap();
+        assert!(range.contains(&[0xFB, 0xFF]));
+        let range = ValidRange::new(ChrFormat::Uint16, 0, 100).unwrap();
+        assert!(!range.contains(&[0xFB, 0xFF]));
+
+        let range = ValidRange::new(ChrFormat::Sint64, i64::MIN, -1).unwrap();
+        assert!(range.contains(&i64::MIN.to_le_bytes()));
+        assert!(!range.contains(&0i64.to_le_bytes()));
+    }
+}
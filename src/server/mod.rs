@@ -1,12 +1,20 @@
+/// Handle of an attribute that is not registered yet.
+pub(crate) const NULL_HANDLE: u16 = 0xFFFF;
+
 mod att_value;
 pub use self::att_value::AttValue;
 
 mod ble_advertisement_data;
-pub use self::ble_advertisement_data::BLEAdvertisementData;
+pub use self::ble_advertisement_data::{AdField, AdvDataError, BLEAdvertisementData};
 
 mod ble_advertising;
 pub use self::ble_advertising::BLEAdvertising;
 
+#[cfg(not(esp_idf_bt_nimble_ext_adv))]
+mod ble_advertising_scheduler;
+#[cfg(not(esp_idf_bt_nimble_ext_adv))]
+pub use self::ble_advertising_scheduler::*;
+
 mod ble_characteristic;
 pub use self::ble_characteristic::*;
 
@@ -22,21 +30,34 @@ mod ble_ext_advertising;
 #[cfg(esp_idf_bt_nimble_ext_adv)]
 pub use self::ble_ext_advertising::*;
 
+mod ble_gatt_table;
+pub use self::ble_gatt_table::{GattCharacteristicInfo, GattDescriptorInfo, GattServiceInfo};
+
 mod ble_hid_device;
 pub use self::ble_hid_device::BLEHIDDevice;
 
+mod ble_notify_queue;
+pub use self::ble_notify_queue::NotifyQueue;
+
 mod ble_server;
 pub use self::ble_server::BLEServer;
 
 mod ble_service;
 pub use self::ble_service::BLEService;
 
+mod ble_write_stream;
+pub use self::ble_write_stream::WriteStream;
+
 pub mod cpfd;
 #[cfg(not(cpfd))]
 mod cpfd_constants;
 
+pub mod descriptors;
+
 pub mod hid;
 
 mod on_write_args;
+pub use self::on_write_args::OnExecuteWriteArgs;
 pub use self::on_write_args::OnWriteArgs;
 pub use self::on_write_args::OnWriteDescriptorArgs;
+pub use self::on_write_args::PreparedWrite;
@@ -1,4 +1,8 @@
-use crate::BLEConnDesc;
+use crate::{
+    BLECharacteristic, BLEConnDesc,
+    utilities::{BleUuid, mutex::Mutex},
+};
+use alloc::vec::Vec;
 
 pub struct OnWriteArgs<'a> {
     pub(crate) current_data: &'a [u8],
@@ -74,3 +78,51 @@ impl OnWriteDescriptorArgs<'_> {
         self.error_code = error_code;
     }
 }
+
+/// A value of a prepared write queue executed by a client.
+pub struct PreparedWrite {
+    pub(crate) conn_handle: u16,
+    pub(crate) characteristic: *const Mutex<BLECharacteristic>,
+    pub(crate) handle: u16,
+    pub(crate) uuid: BleUuid,
+    pub(crate) value: Vec<u8>,
+    pub(crate) notify: bool,
+}
+
+impl PreparedWrite {
+    /// Handle of the characteristic value.
+    pub fn handle(&self) -> u16 {
+        self.handle
+    }
+
+    /// UUID of the characteristic.
+    pub fn uuid(&self) -> BleUuid {
+        self.uuid
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+}
+
+pub struct OnExecuteWriteArgs<'a> {
+    pub(crate) writes: &'a [PreparedWrite],
+    pub(crate) desc: &'a BLEConnDesc,
+    pub(crate) reject: bool,
+}
+
+impl OnExecuteWriteArgs<'_> {
+    /// The values of the queue, sorted by handle.
+    pub fn writes(&self) -> &[PreparedWrite] {
+        self.writes
+    }
+
+    pub fn desc(&self) -> &BLEConnDesc {
+        self.desc
+    }
+
+    /// If the reject is called, none of the values are written to the characteristics.
+    pub fn reject(&mut self) {
+        self.reject = true;
+    }
+}
@@ -0,0 +1,16 @@
+use crate::BLEError;
+use core::time::Duration;
+use esp_idf_svc::{sys, timer::EspTaskTimerService};
+
+/// Wait for the given time without blocking the executor.
+pub(crate) async fn delay_ms(ms: u32) -> Result<(), BLEError> {
+    let os_error = |_| BLEError::convert(sys::BLE_HS_EOS).unwrap_err();
+
+    let mut timer = EspTaskTimerService::new()
+        .and_then(|service| service.timer_async())
+        .map_err(os_error)?;
+    timer
+        .after(Duration::from_millis(ms as _))
+        .await
+        .map_err(os_error)
+}
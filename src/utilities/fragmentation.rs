@@ -0,0 +1,329 @@
+use crate::BLEError;
+use alloc::vec::Vec;
+use esp_idf_svc::sys as esp_idf_sys;
+
+const MORE_FRAGMENTS: u8 = 0x80;
+const FIRST_FRAGMENT: u8 = 0x40;
+const SEQUENCE_MASK: u8 = 0x3F;
+
+/// Header options shared by the sender and the receiver.
+///
+/// Every fragment starts with a one byte header: bit 7 is set if more fragments follow,
+/// bit 6 is set on the first fragment of a value, bits 0-5 are the sequence number,
+/// starting at 0 for each value and wrapping after 63.
+/// With `total_length`, the first fragment carries the length of the value
+/// as a little-endian `u16` after the header.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct FragmentFormat {
+    /// The first fragment carries the total length of the value.
+    pub total_length: bool,
+}
+
+impl FragmentFormat {
+    fn header_len(&self, first: bool) -> usize {
+        if self.total_length && first { 3 } else { 1 }
+    }
+}
+
+/// Iterator over the fragments of a value. Created by [`fragments`].
+pub struct Fragments<'a> {
+    value: &'a [u8],
+    offset: usize,
+    first: bool,
+    seq: u8,
+    fragment_size: usize,
+    format: FragmentFormat,
+    done: bool,
+}
+
+/// Split the value into fragments of at most `fragment_size` bytes, including the header.
+///
+/// `fragment_size` is usually the ATT MTU - 3.
+/// Returns `BLE_HS_EINVAL` if `fragment_size` leaves no room for data,
+/// and `BLE_HS_EMSGSIZE` for values longer than `u16::MAX` with `total_length`.
+pub fn fragments(
+    value: &[u8],
+    fragment_size: usize,
+    format: FragmentFormat,
+) -> Result<Fragments<'_>, BLEError> {
+    if fragment_size <= format.header_len(true) {
+        return Err(BLEError::convert(esp_idf_sys::BLE_HS_EINVAL).unwrap_err());
+    }
+    if format.total_length && value.len() > u16::MAX as usize {
+        return Err(BLEError::convert(esp_idf_sys::BLE_HS_EMSGSIZE).unwrap_err());
+    }
+
+    Ok(Fragments {
+        value,
+        offset: 0,
+        first: true,
+        seq: 0,
+        fragment_size,
+        format,
+        done: false,
+    })
+}
+
+impl Iterator for Fragments<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let header_len = self.format.header_len(self.first);
+        let len = (self.value.len() - self.offset).min(self.fragment_size - header_len);
+        let end = self.offset + len;
+        let more = end < self.value.len();
+
+        let mut header = self.seq & SEQUENCE_MASK;
+        if more {
+            header |= MORE_FRAGMENTS;
+        }
+        if self.first {
+            header |= FIRST_FRAGMENT;
+        }
+
+        let mut fragment = Vec::with_capacity(header_len + len);
+        fragment.push(header);
+        if header_len == 3 {
+            fragment.extend_from_slice(&(self.value.len() as u16).to_le_bytes());
+        }
+        fragment.extend_from_slice(&self.value[self.offset..end]);
+
+        self.offset = end;
+        self.first = false;
+        self.seq = self.seq.wrapping_add(1) & SEQUENCE_MASK;
+        self.done = !more;
+        Some(fragment)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum FragmentError {
+    /// The fragment is shorter than its header.
+    TooShort,
+    /// A fragment was lost or duplicated.
+    UnexpectedSequence { expected: u8, received: u8 },
+    /// A fragment was received without the first fragment of its value.
+    MissingFirst,
+    /// The reassembled value does not have the announced total length.
+    LengthMismatch { expected: usize, received: usize },
+    /// The value is longer than the maximum length of the reassembler.
+    TooLarge,
+}
+
+/// Reassembles values split by [`fragments`].
+///
+/// A first fragment always starts a new value,
+/// so the reassembler recovers from lost fragments at the next value.
+pub struct Reassembler {
+    format: FragmentFormat,
+    max_len: usize,
+    buffer: Vec<u8>,
+    total_length: Option<usize>,
+    next_seq: Option<u8>,
+}
+
+impl Reassembler {
+    /// * `max_len`: Maximum length of a reassembled value.
+    pub fn new(format: FragmentFormat, max_len: usize) -> Self {
+        Self {
+            format,
+            max_len,
+            buffer: Vec::new(),
+            total_length: None,
+            next_seq: None,
+        }
+    }
+
+    /// Discard the partially received value.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.total_length = None;
+        self.next_seq = None;
+    }
+
+    /// Add a fragment. Returns the value when its last fragment was received.
+    pub fn push(&mut self, fragment: &[u8]) -> Result<Option<Vec<u8>>, FragmentError> {
+        let result = self.push_inner(fragment);
+        if !matches!(result, Ok(None)) {
+            self.next_seq = None;
+            self.total_length = None;
+        }
+        result
+    }
+
+    fn push_inner(&mut self, fragment: &[u8]) -> Result<Option<Vec<u8>>, FragmentError> {
+        let header = *fragment.first().ok_or(FragmentError::TooShort)?;
+        let seq = header & SEQUENCE_MASK;
+        let more = header & MORE_FRAGMENTS != 0;
+        let first = header & FIRST_FRAGMENT != 0;
+
+        if first {
+            self.buffer.clear();
+            self.total_length = None;
+        } else {
+            let Some(expected) = self.next_seq else {
+                self.buffer.clear();
+                return Err(FragmentError::MissingFirst);
+            };
+            if seq != expected {
+                self.buffer.clear();
+                return Err(FragmentError::UnexpectedSequence {
+                    expected,
+                    received: seq,
+                });
+            }
+        }
+
+        let header_len = self.format.header_len(first);
+        let data = fragment.get(header_len..).ok_or(FragmentError::TooShort)?;
+        if header_len == 3 {
+            let total_length = u16::from_le_bytes([fragment[1], fragment[2]]) as usize;
+            if total_length > self.max_len {
+                return Err(FragmentError::TooLarge);
+            }
+            self.total_length = Some(total_length);
+        }
+
+        if self.buffer.len() + data.len() > self.max_len {
+            self.buffer.clear();
+            return Err(FragmentError::TooLarge);
+        }
+        self.buffer.extend_from_slice(data);
+        self.next_seq = Some(seq.wrapping_add(1) & SEQUENCE_MASK);
+
+        if more {
+            return Ok(None);
+        }
+
+        let value = core::mem::take(&mut self.buffer);
+        match self.total_length {
+            Some(expected) if expected != value.len() => Err(FragmentError::LengthMismatch {
+                expected,
+                received: value.len(),
+            }),
+            _ => Ok(Some(value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(len: usize) -> Vec<u8> {
+        (0..len).map(|x| x as u8).collect()
+    }
+
+    fn round_trip(value: &[u8], fragment_size: usize, format: FragmentFormat) {
+        let mut reassembler = Reassembler::new(format, u16::MAX as _);
+        let fragments: Vec<_> = fragments(value, fragment_size, format).unwrap().collect();
+
+        let (last, rest) = fragments.split_last().unwrap();
+        for fragment in rest {
+            assert!(fragment.len() <= fragment_size);
+            assert_eq!(reassembler.push(fragment), Ok(None));
+        }
+        assert_eq!(reassembler.push(last), Ok(Some(value.to_vec())));
+    }
+
+    #[test]
+    fn round_trip_formats() {
+        for total_length in [false, true] {
+            let format = FragmentFormat { total_length };
+            round_trip(&[], 20, format);
+            round_trip(&value(1), 20, format);
+            round_trip(&value(17), 20, format);
+            round_trip(&value(500), 20, format);
+            round_trip(&value(500), 244, format);
+        }
+    }
+
+    #[test]
+    fn sequence_wraps() {
+        // 300 fragments, more than the sequence number can count.
+        let format = FragmentFormat::default();
+        let value = value(300 * 4);
+        let fragments: Vec<_> = fragments(&value, 5, format).unwrap().collect();
+        assert_eq!(fragments.len(), 300);
+        assert_eq!(fragments[64][0] & SEQUENCE_MASK, 0);
+        assert_eq!(fragments[64][0] & FIRST_FRAGMENT, 0);
+
+        round_trip(&value, 5, format);
+    }
+
+    #[test]
+    fn lost_fragment() {
+        let format = FragmentFormat { total_length: true };
+        let mut reassembler = Reassembler::new(format, 1024);
+        let first = value(100);
+        let fragments_1: Vec<_> = fragments(&first, 20, format).unwrap().collect();
+
+        assert_eq!(reassembler.push(&fragments_1[0]), Ok(None));
+        assert_eq!(
+            reassembler.push(&fragments_1[2]),
+            Err(FragmentError::UnexpectedSequence {
+                expected: 1,
+                received: 2
+            })
+        );
+        // The rest of the value is dropped.
+        assert_eq!(
+            reassembler.push(&fragments_1[3]),
+            Err(FragmentError::MissingFirst)
+        );
+
+        // Recovers at the next value.
+        let second = value(50);
+        for fragment in fragments(&second, 20, format).unwrap() {
+            if let Some(value) = reassembler.push(&fragment).unwrap() {
+                assert_eq!(value, second);
+            }
+        }
+    }
+
+    #[test]
+    fn lost_last_fragment() {
+        let format = FragmentFormat::default();
+        let mut reassembler = Reassembler::new(format, 1024);
+        let first: Vec<_> = fragments(&value(40), 20, format).unwrap().collect();
+        assert_eq!(reassembler.push(&first[0]), Ok(None));
+        assert_eq!(reassembler.push(&first[1]), Ok(None));
+
+        // The first fragment of the next value discards the partial one.
+        let second = value(10);
+        let fragment = fragments(&second, 20, format).unwrap().next().unwrap();
+        assert_eq!(reassembler.push(&fragment), Ok(Some(second)));
+    }
+
+    #[test]
+    fn length_mismatch() {
+        let format = FragmentFormat { total_length: true };
+        let mut reassembler = Reassembler::new(format, 1024);
+        let mut fragment = fragments(&value(10), 20, format).unwrap().next().unwrap();
+        fragment.pop();
+        assert_eq!(
+            reassembler.push(&fragment),
+            Err(FragmentError::LengthMismatch {
+                expected: 10,
+                received: 9
+            })
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let format = FragmentFormat { total_length: true };
+        let mut reassembler = Reassembler::new(format, 16);
+        let fragment = fragments(&value(17), 20, format).unwrap().next().unwrap();
+        assert_eq!(reassembler.push(&fragment), Err(FragmentError::TooLarge));
+
+        let value = value(u16::MAX as usize + 1);
+        assert!(fragments(&value, 20, format).is_err());
+        assert!(fragments(&value, 20, FragmentFormat::default()).is_ok());
+        assert!(fragments(&value, 3, format).is_err());
+    }
+}
@@ -9,6 +9,14 @@ pub(crate) use arc_unsafe_cell::*;
 mod ble_functions;
 pub(crate) use ble_functions::*;
 
+mod delay;
+pub(crate) use delay::*;
+
+pub mod fragmentation;
+
+mod notify;
+pub(crate) use notify::*;
+
 mod nimble_npl_os;
 pub(crate) use nimble_npl_os::*;
 
@@ -37,3 +37,22 @@ pub fn ble_npl_hw_exit_critical() {
         npl_freertos_hw_exit_critical(0);
     }
 }
+
+/// Set the function called when the event is processed by the NimBLE host task.
+#[inline]
+#[allow(unused)]
+pub fn ble_npl_event_init(ev: &mut ble_npl_event, callback: ble_npl_event_fn) {
+    unsafe {
+        npl_freertos_event_init(ev, callback, core::ptr::null_mut());
+    }
+}
+
+/// Queue the event to the NimBLE host task, after the events already queued.
+/// Nothing is done if the event is still queued.
+#[inline]
+#[allow(unused)]
+pub fn ble_npl_eventq_put_dflt(ev: &mut ble_npl_event) {
+    unsafe {
+        npl_freertos_eventq_put(nimble_port_get_dflt_eventq(), ev);
+    }
+}
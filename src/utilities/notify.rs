@@ -0,0 +1,126 @@
+use crate::utilities::mutex::Mutex;
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    task::{Poll, Waker},
+};
+
+/// An event any number of tasks can wait for.
+///
+/// Unlike [`crate::Signal`], every waiting task is woken. Notifications are counted, so a task
+/// reads [`Notify::version`] before trying and then waits for a later one: a notification in
+/// between is not lost, and no task can reset it for the others.
+pub(crate) struct Notify {
+    version: AtomicU32,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Notify {
+    pub(crate) const fn new() -> Self {
+        Self {
+            version: AtomicU32::new(0),
+            wakers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Number of notifications so far.
+    pub(crate) fn version(&self) -> u32 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Wake every waiting task.
+    pub(crate) fn notify(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
+        for waker in core::mem::take(&mut *self.wakers.lock()) {
+            waker.wake();
+        }
+    }
+
+    /// Wait for a notification after `version` was read.
+    pub(crate) async fn wait(&self, version: u32) {
+        core::future::poll_fn(|cx| {
+            if self.version() != version {
+                return Poll::Ready(());
+            }
+
+            let mut wakers = self.wakers.lock();
+            if !wakers.iter().any(|x| x.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+            drop(wakers);
+
+            // Notified before the waker was registered.
+            if self.version() != version {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{sync::Arc, task::Wake};
+    use core::{future::Future, pin::pin, task::Context};
+    use std::{thread, time::Duration};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn notification_before_wait() {
+        let notify = Notify::new();
+        let version = notify.version();
+        notify.notify();
+        block_on(notify.wait(version));
+    }
+
+    #[test]
+    fn several_waiters() {
+        let notify = Arc::new(Notify::new());
+        let version = notify.version();
+
+        let waiters: Vec<_> = (0..3)
+            .map(|_| {
+                let notify = notify.clone();
+                thread::spawn(move || block_on(notify.wait(version)))
+            })
+            .collect();
+
+        thread::sleep(Duration::from_millis(50));
+        notify.notify();
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn waiting_does_not_consume_the_notification() {
+        let notify = Notify::new();
+        let version = notify.version();
+        notify.notify();
+        block_on(notify.wait(version));
+        block_on(notify.wait(version));
+        assert_eq!(notify.version(), version.wrapping_add(1));
+    }
+}
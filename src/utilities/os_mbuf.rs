@@ -8,6 +8,12 @@ use sys::os_mbuf_append as _os_mbuf_append;
 #[cfg(esp_idf_soc_esp_nimble_controller)]
 use sys::r_os_mbuf_append as _os_mbuf_append;
 
+#[cfg(not(esp_idf_soc_esp_nimble_controller))]
+use sys::os_mbuf_free_chain as _os_mbuf_free_chain;
+
+#[cfg(esp_idf_soc_esp_nimble_controller)]
+use sys::r_os_mbuf_free_chain as _os_mbuf_free_chain;
+
 #[derive(Copy, Clone)]
 pub(crate) struct OsMBuf(pub *mut sys::os_mbuf);
 
@@ -29,6 +35,12 @@ impl OsMBuf {
         unsafe { _os_mbuf_append(self.0, data.as_ptr() as _, data.len() as _) }
     }
 
+    /// Free a chain of mbufs
+    #[inline]
+    pub(crate) fn free_chain(self) -> c_int {
+        unsafe { _os_mbuf_free_chain(self.0) }
+    }
+
     #[inline]
     pub fn from_flat(buf: &[u8]) -> Self {
         OsMBuf(unsafe { sys::ble_hs_mbuf_from_flat(buf.as_ptr() as _, buf.len() as _) })